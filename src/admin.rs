@@ -0,0 +1,266 @@
+use crate::{BoxFuture, Key, LimitState};
+use axum::extract::Path;
+use axum::routing::{delete, get, put};
+use axum::Router;
+use http::{HeaderMap, StatusCode};
+use std::fmt::{Display, Write};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+type Authorizer = Arc<dyn Fn(HeaderMap) -> BoxFuture<Option<AdminRole>> + Send + Sync>;
+
+/// The permissions of a caller of the [`AdminRouter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AdminRole {
+    /// May inspect bans and declared limits.
+    ReadOnly,
+    /// May also ban and unban keys, and reset and override their quotas.
+    Manage,
+}
+
+/// An inspection router for a [`LimitState`], to be nested into the application router.
+///
+/// The router serves the following plain-text endpoints:
+///
+/// - `GET /bans` lists banned keys with the UNIX time their ban expires, or `permanent`.
+/// - `PUT /bans/:key` permanently bans a key, `DELETE /bans/:key` unbans it.
+/// - `GET /limits` lists the limits recorded by the state's [`LimitRegistry`](crate::LimitRegistry).
+/// - `DELETE /quotas/:key` [resets](LimitState::reset) the in-memory quota of a key.
+/// - `PUT /quotas/:key/:count/:per` overrides the remaining quota of a key for a limit of `count` requests per `per`
+///   milliseconds with the number of requests in the body, like [`LimitState::preload`].
+///
+/// Only [`AdminRole::Manage`] callers may change bans and quotas.
+///
+/// Every request is passed to the authorizer, which decides the caller's [`AdminRole`] from the request headers.
+/// Without an authorizer, all requests are denied, so the router is safe to expose by construction. Since it is a
+/// regular [`Router`], tower layers may still be added for other authentication schemes.
+pub struct AdminRouter<K>
+where
+    K: Key,
+{
+    state: LimitState<K>,
+    authorizer: Option<Authorizer>,
+}
+
+impl<K> AdminRouter<K>
+where
+    K: Key + FromStr + Display + 'static,
+{
+    /// Constructs a new `AdminRouter` for `state`, denying all requests until an authorizer is installed.
+    pub fn new(state: LimitState<K>) -> Self {
+        Self {
+            state,
+            authorizer: None,
+        }
+    }
+
+    /// Installs an async authorizer returning the role of the caller, or `None` to deny the request.
+    pub fn authorize<F, Fut>(mut self, authorizer: F) -> Self
+    where
+        F: Fn(HeaderMap) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<AdminRole>> + Send + 'static,
+    {
+        self.authorizer = Some(Arc::new(move |headers| Box::pin(authorizer(headers))));
+        self
+    }
+
+    /// Builds the router.
+    pub fn into_router<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let admin = Arc::new(self);
+        let (list, ban, unban, limits) =
+            (admin.clone(), admin.clone(), admin.clone(), admin.clone());
+        let (reset, set) = (admin.clone(), admin);
+        Router::new()
+            .route(
+                "/bans",
+                get(move |headers: HeaderMap| async move {
+                    list.require(headers, AdminRole::ReadOnly).await?;
+                    let mut body = String::new();
+                    for entry in list.state.ban_list().entries() {
+                        let until = entry.until.map_or("permanent".to_string(), |until| {
+                            let secs = until.duration_since(UNIX_EPOCH).unwrap_or_default();
+                            secs.as_secs().to_string()
+                        });
+                        let _ = writeln!(body, "{}\t{until}", entry.key);
+                    }
+                    Ok::<_, StatusCode>(body)
+                }),
+            )
+            .route(
+                "/bans/:key",
+                put(
+                    move |headers: HeaderMap, Path(key): Path<String>| async move {
+                        ban.require(headers, AdminRole::Manage).await?;
+                        let key = key.parse::<K>().map_err(|_| StatusCode::BAD_REQUEST)?;
+                        ban.state.ban_list().ban(key, None);
+                        Ok::<_, StatusCode>(StatusCode::NO_CONTENT)
+                    },
+                )
+                .delete(
+                    move |headers: HeaderMap, Path(key): Path<String>| async move {
+                        unban.require(headers, AdminRole::Manage).await?;
+                        let key = key.parse::<K>().map_err(|_| StatusCode::BAD_REQUEST)?;
+                        if !unban.state.ban_list().unban(&key) {
+                            return Err(StatusCode::NOT_FOUND);
+                        }
+                        Ok(StatusCode::NO_CONTENT)
+                    },
+                ),
+            )
+            .route(
+                "/limits",
+                get(move |headers: HeaderMap| async move {
+                    limits.require(headers, AdminRole::ReadOnly).await?;
+                    let mut body = String::new();
+                    for limit in limits.state.registry.iter().flat_map(|r| r.limits()) {
+                        let route = limit.route.as_deref().unwrap_or("*");
+                        let _ = writeln!(
+                            body,
+                            "{} {route}\t{}/{}ms\t{}",
+                            limit.method, limit.count, limit.per, limit.key
+                        );
+                    }
+                    Ok::<_, StatusCode>(body)
+                }),
+            )
+            .route(
+                "/quotas/:key",
+                delete(
+                    move |headers: HeaderMap, Path(key): Path<String>| async move {
+                        reset.require(headers, AdminRole::Manage).await?;
+                        let key = key.parse::<K>().map_err(|_| StatusCode::BAD_REQUEST)?;
+                        reset.state.reset(&key);
+                        Ok::<_, StatusCode>(StatusCode::NO_CONTENT)
+                    },
+                ),
+            )
+            .route(
+                "/quotas/:key/:count/:per",
+                put(
+                    move |headers: HeaderMap,
+                          Path((key, count, per)): Path<(String, usize, u64)>,
+                          body: String| async move {
+                        set.require(headers, AdminRole::Manage).await?;
+                        let key = key.parse::<K>().map_err(|_| StatusCode::BAD_REQUEST)?;
+                        let remaining = body
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| StatusCode::BAD_REQUEST)?;
+                        set.state.preload([(key, remaining)], count, per);
+                        Ok::<_, StatusCode>(StatusCode::NO_CONTENT)
+                    },
+                ),
+            )
+    }
+
+    /// Fails with `401 Unauthorized` for unknown callers, and `403 Forbidden` for callers lacking `role`.
+    async fn require(&self, headers: HeaderMap, role: AdminRole) -> Result<(), StatusCode> {
+        let Some(authorizer) = &self.authorizer else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+        match authorizer(headers).await {
+            Some(granted) if granted >= role => Ok(()),
+            Some(_) => Err(StatusCode::FORBIDDEN),
+            None => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum_test::TestServer;
+    use http::{HeaderName, HeaderValue, Method};
+
+    /// Serves the admin endpoints of `state` under `/admin`, authorizing the `read` and `manage` tokens.
+    fn server(state: &LimitState<Method>) -> TestServer {
+        let admin = AdminRouter::new(state.clone()).authorize(|headers: HeaderMap| async move {
+            match headers.get("x-admin-token")?.to_str().ok()? {
+                "read" => Some(AdminRole::ReadOnly),
+                "manage" => Some(AdminRole::Manage),
+                _ => None,
+            }
+        });
+        TestServer::new(Router::new().nest("/admin", admin.into_router()))
+            .expect("Failed to create test server")
+    }
+
+    /// Returns the header presenting the admin token `value`.
+    fn token(value: &'static str) -> (HeaderName, HeaderValue) {
+        (
+            HeaderName::from_static("x-admin-token"),
+            HeaderValue::from_static(value),
+        )
+    }
+
+    #[tokio::test]
+    async fn roles_are_enforced() {
+        let state = LimitState::<Method>::default();
+        let server = server(&state);
+
+        let response = server.get("/admin/bans").await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+        let (name, value) = token("read");
+        let response = server.put("/admin/bans/POST").add_header(name, value).await;
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+
+        let (name, value) = token("manage");
+        let response = server.put("/admin/bans/POST").add_header(name, value).await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        assert!(state.ban_list().is_banned(&Method::POST));
+
+        let (name, value) = token("read");
+        let response = server.get("/admin/bans").add_header(name, value).await;
+        assert_eq!(response.text(), "POST\tpermanent\n");
+    }
+
+    #[tokio::test]
+    async fn quotas_are_managed() {
+        let state = LimitState::<Method>::default();
+        let server = server(&state);
+
+        assert!(state.check(Method::GET, 1, 60_000));
+        assert!(!state.check(Method::GET, 1, 60_000));
+
+        let (name, value) = token("read");
+        let response = server
+            .delete("/admin/quotas/GET")
+            .add_header(name, value)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+
+        let (name, value) = token("manage");
+        let response = server
+            .delete("/admin/quotas/GET")
+            .add_header(name, value)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        assert!(state.check(Method::GET, 1, 60_000));
+
+        let (name, value) = token("manage");
+        let response = server
+            .put("/admin/quotas/GET/5/60000")
+            .add_header(name, value)
+            .text("2")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        assert!(state.check(Method::GET, 5, 60_000));
+        assert!(state.check(Method::GET, 5, 60_000));
+        assert!(!state.check(Method::GET, 5, 60_000));
+
+        let (name, value) = token("manage");
+        let response = server
+            .put("/admin/quotas/GET/5/60000")
+            .add_header(name, value)
+            .text("plenty")
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::map::Map;
+use std::hash::Hash;
+
+/// Adapts the limits of keys to the health of what they protect, using additive-increase/multiplicative-decrease.
+///
+/// When installed on a [`LimitState`](crate::LimitState), every limit is scaled by a factor kept per key. Each
+/// failure reported with [`LimitState::report_failure`](crate::LimitState::report_failure), e.g. a `5xx` response
+/// from a flaky upstream, multiplies the factor by the decrease; each success reported with
+/// [`LimitState::report_success`](crate::LimitState::report_success) adds the increase back, up to the full limit.
+pub struct Aimd<K> {
+    increase: f64,
+    decrease: f64,
+    min: f64,
+    factors: Map<K, f64>,
+}
+
+impl<K> Default for Aimd<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Aimd<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs a new `Aimd` halving the limit of a key on every failure, and restoring a tenth of it on every
+    /// success. Limits are never reduced below a tenth of their count by default.
+    pub fn new() -> Self {
+        Self {
+            increase: 0.1,
+            decrease: 0.5,
+            min: 0.1,
+            factors: Map::new(),
+        }
+    }
+
+    /// Sets the fraction of the full limit restored on every success.
+    pub fn increase(mut self, fraction: f64) -> Self {
+        self.increase = fraction.max(0.0);
+        self
+    }
+
+    /// Sets the factor the limit is multiplied by on every failure, between `0.0` and `1.0`.
+    pub fn decrease(mut self, factor: f64) -> Self {
+        self.decrease = factor.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the lowest fraction of the full limit a key can be reduced to, between `0.0` and `1.0`.
+    pub fn min(mut self, fraction: f64) -> Self {
+        self.min = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Raises the limit of `key` after a success, forgetting keys back at their full limit.
+    pub(crate) fn success(&self, key: &K) {
+        if let Some(mut factor) = self.factors.get_mut(key) {
+            *factor = (*factor + self.increase).min(1.0);
+        }
+        self.factors.remove_if(key, |_, factor| *factor >= 1.0);
+    }
+
+    /// Lowers the limit of `key` after a failure.
+    pub(crate) fn failure(&self, key: &K) {
+        let mut factor = self.factors.entry(key.clone()).or_insert(1.0);
+        *factor = (*factor * self.decrease).max(self.min);
+    }
+
+    /// Returns the current limit of `key` for a limit of `count` requests, at least one request.
+    pub(crate) fn limit(&self, key: &K, count: usize) -> usize {
+        match self.factors.get(key) {
+            Some(factor) => ((count as f64 * *factor) as usize).clamp(count.min(1), count),
+            None => count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LimitState;
+    use http::Method;
+    use std::time::Duration;
+
+    #[test]
+    fn adapts_to_feedback() {
+        let state = LimitState::<Method>::default().with_aimd(Aimd::new().increase(0.25));
+        state.report_failure(&Method::GET);
+        for _ in 0..5 {
+            assert!(state.check(Method::GET, 10, 100));
+        }
+        assert!(!state.check(Method::GET, 10, 100));
+        assert!(state.check(Method::POST, 10, 100));
+
+        // Two successes restore the full limit, which the next refill makes available.
+        state.report_success(&Method::GET);
+        state.report_success(&Method::GET);
+        std::thread::sleep(Duration::from_millis(120));
+        for _ in 0..10 {
+            assert!(state.check(Method::GET, 10, 100));
+        }
+        assert!(!state.check(Method::GET, 10, 100));
+    }
+}
@@ -0,0 +1,701 @@
+//! The algorithms deciding whether a request fits within its limit, selected per [`Limit`](crate::Limit):
+//!
+//! ```
+//! use axum_limit::algorithm::SlidingWindow;
+//! use axum_limit::Limit;
+//! use http::Method;
+//!
+//! async fn handler(_: Limit<10, 60_000, Method, SlidingWindow>) {}
+//! ```
+//!
+//! Algorithms apply to the in-memory state of a [`LimitState`](crate::LimitState), including the one used with
+//! [`FailurePolicy::FallbackToLocal`](crate::FailurePolicy::FallbackToLocal); a [`Backend`](crate::Backend)
+//! always enforces its own algorithm.
+
+use crate::map::Map;
+use crate::{Decision, Key};
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// An algorithm deciding whether a request fits within its limit. Implemented by the types of this module.
+pub trait Algorithm: sealed::Sealed + Send + Sync + 'static {}
+
+/// The default algorithm: a bucket holding up to `COUNT` tokens, refilled completely every `PER` milliseconds.
+///
+/// Cheap and simple, but a client may send up to twice its limit around the end of a period: `COUNT` requests
+/// just before the refill, and `COUNT` more just after it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenBucket;
+
+/// A token bucket holding up to `BURST` tokens, refilled by `COUNT` tokens every `PER` milliseconds, e.g.
+/// `Limit<10, 1000, K, Burst<50>>` for 10 requests per second sustained, with bursts of up to 50.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Burst<const BURST: usize>;
+
+/// A token bucket holding up to `COUNT` tokens, refilled continuously at `COUNT` tokens per `PER` milliseconds
+/// instead of all at once every `PER` milliseconds, e.g. one token every 600 milliseconds for
+/// `Limit<100, 60_000, K, Smooth>`. Partially accrued tokens are tracked, so no capacity is lost to rounding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Smooth;
+
+/// A sliding window counter: requests are counted in fixed windows of `PER` milliseconds, and a request is allowed
+/// if the requests of the current window, plus those of the previous window weighted by how much of it still
+/// overlaps the last `PER` milliseconds, stay within `COUNT`.
+///
+/// This smooths out the burst a [`TokenBucket`] allows at the boundary of two periods, at the cost of assuming
+/// requests of the previous window were evenly spread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlidingWindow;
+
+/// A sliding log: the time of every admitted request is recorded, and a request is allowed if no more than `COUNT`
+/// requests fall within the last `PER` milliseconds, exactly. Times older than `PER` are dropped as requests arrive.
+///
+/// Memory grows with `COUNT` for every key, so this suits low-volume but strict endpoints, such as logins or
+/// password resets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlidingLog;
+
+/// The generic cell rate algorithm (GCRA), in its virtual scheduling form: requests are spaced `PER / COUNT`
+/// milliseconds apart on average, with bursts of up to `COUNT` requests.
+///
+/// Only a single point in time is stored per key, and remaining quota and reset times are exact, as capacity is
+/// regained continuously rather than in whole periods.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Gcra;
+
+/// A minimum interval between requests: requests of a key are spaced at least `PER / COUNT` milliseconds apart,
+/// without bursts, e.g. `Limit<1, 200, K, Pacing>` for one request every 200 milliseconds. Suits endpoints that
+/// trigger expensive downstream calls, which should not arrive in bursts even within a windowed limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pacing;
+
+impl Algorithm for TokenBucket {}
+
+impl<const BURST: usize> Algorithm for Burst<BURST> {}
+
+impl Algorithm for Smooth {}
+
+impl Algorithm for SlidingWindow {}
+
+impl Algorithm for SlidingLog {}
+
+impl Algorithm for Gcra {}
+
+impl Algorithm for Pacing {}
+
+impl<const UTC_OFFSET_MINUTES: i32> Algorithm for Calendar<UTC_OFFSET_MINUTES> {}
+
+/// Fixed windows of `PER` milliseconds aligned to wall-clock boundaries, allowing `COUNT` requests per window, e.g.
+/// `LimitPerDay<1000, K, Calendar>` for daily quotas resetting at midnight UTC, the way most public API quotas are
+/// documented.
+///
+/// Windows are aligned to multiples of `PER` since the Unix epoch, shifted by a fixed offset from UTC of
+/// `UTC_OFFSET_MINUTES`, so daily windows start at local midnight and hourly windows on the hour, e.g.
+/// `Calendar<-300>` for US Eastern Standard Time. Daylight saving time is not followed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Calendar<const UTC_OFFSET_MINUTES: i32 = 0>;
+
+/// Names the extractor of a [`Key`] for a limit enforced with the algorithm `A`, which is always the key's own
+/// [`Key::Extractor`]. This lets [`Limit`](crate::Limit) carry its algorithm while keeping the extractor as its only
+/// field.
+pub trait KeyExtractor<A> {
+    /// The extractor of the key, [`Key::Extractor`].
+    type Extractor;
+}
+
+impl<K, A> KeyExtractor<A> for K
+where
+    K: Key,
+    A: Algorithm,
+{
+    type Extractor = K::Extractor;
+}
+
+pub(crate) use sealed::Kind;
+
+mod sealed {
+    /// The algorithms, as dispatched on by the in-memory state.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum Kind {
+        #[default]
+        TokenBucket,
+        Burst(usize),
+        Smooth,
+        SlidingWindow,
+        SlidingLog,
+        Gcra,
+        Pacing,
+        Calendar(i32),
+    }
+
+    pub trait Sealed {
+        const KIND: Kind;
+    }
+
+    impl Sealed for super::TokenBucket {
+        const KIND: Kind = Kind::TokenBucket;
+    }
+
+    impl<const BURST: usize> Sealed for super::Burst<BURST> {
+        const KIND: Kind = Kind::Burst(BURST);
+    }
+
+    impl Sealed for super::Smooth {
+        const KIND: Kind = Kind::Smooth;
+    }
+
+    impl Sealed for super::SlidingWindow {
+        const KIND: Kind = Kind::SlidingWindow;
+    }
+
+    impl Sealed for super::SlidingLog {
+        const KIND: Kind = Kind::SlidingLog;
+    }
+
+    impl Sealed for super::Gcra {
+        const KIND: Kind = Kind::Gcra;
+    }
+
+    impl Sealed for super::Pacing {
+        const KIND: Kind = Kind::Pacing;
+    }
+
+    impl<const UTC_OFFSET_MINUTES: i32> Sealed for super::Calendar<UTC_OFFSET_MINUTES> {
+        const KIND: Kind = Kind::Calendar(UTC_OFFSET_MINUTES);
+    }
+}
+
+/// Returns the kind of the algorithm `A`.
+pub(crate) fn kind<A: Algorithm>() -> Kind {
+    A::KIND
+}
+
+/// Decides on a request of `key`, whose state in `states` is created with `new` if the key has none yet.
+pub(crate) fn decide_in<K, S>(
+    states: &Map<K, S>,
+    key: &K,
+    new: impl FnOnce() -> S,
+    decide: impl FnOnce(&mut S) -> Decision,
+) -> Decision
+where
+    K: Eq + Hash + Clone,
+{
+    // Look up existing states by reference so the key is only cloned for new states.
+    match states.get_mut(key) {
+        Some(mut state) => decide(&mut state),
+        None => decide(&mut states.entry(key.clone()).or_insert_with(new)),
+    }
+}
+
+/// The state of a [`SlidingWindow`] for one key: the requests counted in the current and the previous window.
+#[derive(Debug, Clone)]
+pub(crate) struct WindowCounter {
+    window_start: Instant,
+    per: Duration,
+    previous: usize,
+    current: usize,
+}
+
+impl WindowCounter {
+    /// Constructs a new `WindowCounter` with windows of `per` milliseconds, the first one starting at `now`.
+    pub(crate) fn new(per: u64, now: Instant) -> Self {
+        Self {
+            window_start: now,
+            per: Duration::from_millis(per),
+            previous: 0,
+            current: 0,
+        }
+    }
+
+    /// Counts `used` requests in the first window, as if they had been made already.
+    pub(crate) fn with_used(mut self, used: usize) -> Self {
+        self.current = used;
+        self
+    }
+
+    /// Attempts to count `cost` requests at once against a limit of `count`, reporting the state of the counter
+    /// afterwards. Either all or none of them are counted.
+    pub(crate) fn decide_at(&mut self, count: usize, cost: usize, now: Instant) -> Decision {
+        self.advance(now);
+        let allowed = self.estimate(now) + cost as f64 <= count as f64;
+        if allowed {
+            self.current = self.current.saturating_add(cost);
+        }
+        Decision {
+            allowed,
+            limit: count,
+            remaining: (count as f64 - self.estimate(now)).max(0.0) as usize,
+            reset: self
+                .per
+                .saturating_sub(now.saturating_duration_since(self.window_start)),
+        }
+    }
+
+    /// Uncounts `cost` previously counted requests, if they were counted in the current window.
+    pub(crate) fn refund(&mut self, cost: usize, now: Instant) {
+        self.advance(now);
+        self.current = self.current.saturating_sub(cost);
+    }
+
+    /// Moves on to the window containing `now`. Windows without requests in between forget all counts.
+    fn advance(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if self.per.is_zero() {
+            self.previous = 0;
+            self.current = 0;
+            self.window_start = now;
+            return;
+        }
+        let windows = elapsed.as_nanos() / self.per.as_nanos();
+        if windows == 0 {
+            return;
+        }
+        self.previous = if windows == 1 { self.current } else { 0 };
+        self.current = 0;
+        // Stay aligned to the first window, so the weight of the previous window is exact.
+        let passed = u32::try_from(windows).map_or(elapsed, |windows| self.per * windows);
+        self.window_start += passed;
+    }
+
+    /// Returns the weighted number of requests within the last window's length at `now`.
+    fn estimate(&self, now: Instant) -> f64 {
+        if self.per.is_zero() {
+            return self.current as f64;
+        }
+        let overlap = 1.0
+            - now
+                .saturating_duration_since(self.window_start)
+                .as_secs_f64()
+                / self.per.as_secs_f64();
+        self.previous as f64 * overlap.clamp(0.0, 1.0) + self.current as f64
+    }
+}
+
+/// Returns the milliseconds since the Unix epoch at `now`, relating instants to the system time once per process
+/// so that clocks replaced for tests move wall-clock time along.
+pub(crate) fn unix_millis(now: Instant) -> i128 {
+    static ANCHOR: OnceLock<(Instant, SystemTime)> = OnceLock::new();
+    let (instant, system) = *ANCHOR.get_or_init(|| (Instant::now(), SystemTime::now()));
+    let anchor = system
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as i128);
+    let nanos = match now.checked_duration_since(instant) {
+        Some(after) => anchor + after.as_nanos() as i128,
+        None => anchor - instant.saturating_duration_since(now).as_nanos() as i128,
+    };
+    nanos.div_euclid(1_000_000)
+}
+
+/// The state of a [`Calendar`] window for one key: the requests counted in the current wall-clock window.
+#[derive(Debug, Clone)]
+pub(crate) struct CalendarCounter {
+    per: i128,
+    offset: i128,
+    window: i128,
+    used: usize,
+}
+
+impl CalendarCounter {
+    /// Constructs a new `CalendarCounter` with windows of `per` milliseconds, shifted by `offset_minutes` from UTC.
+    pub(crate) fn new(per: u64, offset_minutes: i32, now: Instant) -> Self {
+        let mut counter = Self {
+            per: i128::from(per.max(1)),
+            offset: i128::from(offset_minutes) * 60_000,
+            window: 0,
+            used: 0,
+        };
+        counter.window = counter.position(now).0;
+        counter
+    }
+
+    /// Counts `used` requests in the current window, as if they had been made already.
+    pub(crate) fn with_used(mut self, used: usize) -> Self {
+        self.used = used;
+        self
+    }
+
+    /// Returns the window containing `now` and the time elapsed since it started, in milliseconds.
+    fn position(&self, now: Instant) -> (i128, i128) {
+        let local = unix_millis(now) + self.offset;
+        (local.div_euclid(self.per), local.rem_euclid(self.per))
+    }
+
+    /// Moves on to the window containing `now`, returning the milliseconds until the window ends.
+    fn advance(&mut self, now: Instant) -> u64 {
+        let (window, elapsed) = self.position(now);
+        if window != self.window {
+            self.window = window;
+            self.used = 0;
+        }
+        u64::try_from(self.per - elapsed).unwrap_or(u64::MAX)
+    }
+
+    /// Attempts to count `cost` requests at once against a limit of `count`, reporting the state of the counter
+    /// afterwards. Either all or none of them are counted.
+    pub(crate) fn decide_at(&mut self, count: usize, cost: usize, now: Instant) -> Decision {
+        let reset = self.advance(now);
+        let allowed = self.used.saturating_add(cost) <= count;
+        if allowed {
+            self.used += cost;
+        }
+        Decision {
+            allowed,
+            limit: count,
+            remaining: count.saturating_sub(self.used),
+            reset: Duration::from_millis(reset),
+        }
+    }
+
+    /// Uncounts `cost` previously counted requests, if they were counted in the current window.
+    pub(crate) fn refund(&mut self, cost: usize, now: Instant) {
+        self.advance(now);
+        self.used = self.used.saturating_sub(cost);
+    }
+}
+
+/// The state of a [`SlidingLog`] for one key: the times of the requests admitted within the last period, oldest
+/// first, along with their cost.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestLog {
+    per: Duration,
+    admitted: VecDeque<(Instant, usize)>,
+}
+
+impl RequestLog {
+    /// Constructs a new, empty `RequestLog` for a period of `per` milliseconds.
+    pub(crate) fn new(per: u64) -> Self {
+        Self {
+            per: Duration::from_millis(per),
+            admitted: VecDeque::new(),
+        }
+    }
+
+    /// Records `used` requests admitted at `now`, as if they had been made already.
+    pub(crate) fn with_used(mut self, used: usize, now: Instant) -> Self {
+        if used > 0 {
+            self.admitted.push_back((now, used));
+        }
+        self
+    }
+
+    /// Attempts to admit `cost` requests at once against a limit of `count`, reporting the state of the log
+    /// afterwards. Either all or none of them are admitted.
+    pub(crate) fn decide_at(&mut self, count: usize, cost: usize, now: Instant) -> Decision {
+        self.prune(now);
+        let used = self
+            .admitted
+            .iter()
+            .fold(0, |used: usize, (_, cost)| used.saturating_add(*cost));
+        let allowed = used.saturating_add(cost) <= count;
+        let used = if allowed && cost > 0 {
+            self.admitted.push_back((now, cost));
+            used + cost
+        } else {
+            used
+        };
+        Decision {
+            allowed,
+            limit: count,
+            remaining: count.saturating_sub(used),
+            // The next request slot frees up once the oldest admitted request leaves the period.
+            reset: self.admitted.front().map_or(Duration::ZERO, |(time, _)| {
+                (*time + self.per).saturating_duration_since(now)
+            }),
+        }
+    }
+
+    /// Forgets the most recently admitted `cost` requests.
+    pub(crate) fn refund(&mut self, mut cost: usize) {
+        while let Some((_, last)) = self.admitted.back_mut() {
+            if *last > cost {
+                *last -= cost;
+                return;
+            }
+            cost -= *last;
+            self.admitted.pop_back();
+        }
+    }
+
+    /// Drops the requests admitted a full period or more before `now`.
+    fn prune(&mut self, now: Instant) {
+        while self
+            .admitted
+            .front()
+            .is_some_and(|(time, _)| now.saturating_duration_since(*time) >= self.per)
+        {
+            self.admitted.pop_front();
+        }
+    }
+}
+
+/// The state of [`Gcra`] for one key: the theoretical arrival time of the next request, at which the key would
+/// have used up none of its burst.
+#[derive(Debug, Clone)]
+pub(crate) struct VirtualSchedule {
+    arrival: Instant,
+    per: Duration,
+}
+
+impl VirtualSchedule {
+    /// Constructs a new `VirtualSchedule` for a period of `per` milliseconds, with the full burst available at `now`.
+    pub(crate) fn new(per: u64, now: Instant) -> Self {
+        Self {
+            arrival: now,
+            per: Duration::from_millis(per),
+        }
+    }
+
+    /// Schedules `used` requests against a limit of `count`, as if they had been made already.
+    pub(crate) fn with_used(mut self, count: usize, used: usize) -> Self {
+        let used = self
+            .interval(count)
+            .saturating_mul(u32::try_from(used).unwrap_or(u32::MAX));
+        self.arrival = self.arrival.checked_add(used).unwrap_or(self.arrival);
+        self
+    }
+
+    /// Returns the time between two requests at a sustained rate of `count` requests per period.
+    fn interval(&self, count: usize) -> Duration {
+        let nanos = self.per.as_nanos() / count.max(1) as u128;
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    /// Attempts to schedule `cost` requests at once against a limit of `count`, reporting the state of the schedule
+    /// afterwards. Either all or none of them are scheduled.
+    pub(crate) fn decide_at(&mut self, count: usize, cost: usize, now: Instant) -> Decision {
+        let interval = self.interval(count);
+        let allowed = count > 0
+            && self
+                .arrival
+                .max(now)
+                .checked_add(interval.saturating_mul(u32::try_from(cost).unwrap_or(u32::MAX)))
+                .filter(|arrival| arrival.saturating_duration_since(now) <= self.per)
+                .map(|arrival| self.arrival = arrival)
+                .is_some();
+
+        let reset = self.arrival.saturating_duration_since(now);
+        let remaining = match self
+            .per
+            .saturating_sub(reset)
+            .as_nanos()
+            .checked_div(interval.as_nanos())
+        {
+            Some(remaining) => usize::try_from(remaining).unwrap_or(usize::MAX).min(count),
+            None => count,
+        };
+        Decision {
+            allowed,
+            limit: count,
+            remaining,
+            reset,
+        }
+    }
+
+    /// Attempts to schedule `cost` requests at once for [`Pacing`], spacing them `per / count` milliseconds apart
+    /// without bursts. Paced schedules count as a limit of one request per interval, also when refunded.
+    pub(crate) fn decide_paced_at(
+        &mut self,
+        per: u64,
+        count: usize,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        self.per = Duration::from_millis(per) / u32::try_from(count.max(1)).unwrap_or(u32::MAX);
+        self.decide_at(1, cost, now)
+    }
+
+    /// Unschedules `cost` previously scheduled requests against a limit of `count`, without granting more than the
+    /// full burst.
+    pub(crate) fn refund(&mut self, count: usize, cost: usize, now: Instant) {
+        let refunded = self
+            .interval(count)
+            .saturating_mul(u32::try_from(cost).unwrap_or(u32::MAX));
+        self.arrival = self
+            .arrival
+            .checked_sub(refunded)
+            .map_or(now, |arrival| arrival.max(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{Method, StatusCode};
+
+    #[test]
+    fn burst_exceeds_sustained_rate() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let buckets = crate::MemoryBackend::<Method>::default();
+        let acquire = |millis| buckets.acquire_burst_at(&Method::GET, 2, 1_000, 5, 1, at(millis));
+
+        for _ in 0..5 {
+            assert!(acquire(0).allowed);
+        }
+        assert!(!acquire(0).allowed);
+        // Every period only refills the sustained rate.
+        assert_eq!(acquire(1_000).remaining, 1);
+        assert!(acquire(1_000).allowed);
+        assert!(!acquire(1_000).allowed);
+        assert_eq!(acquire(10_000).remaining, 4);
+    }
+
+    #[test]
+    fn smooth_refill_accrues_fractions() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let buckets = crate::MemoryBackend::<Method>::default();
+        let acquire = |millis| buckets.acquire_smooth_at(&Method::GET, 4, 1_000, 1, at(millis));
+
+        for _ in 0..4 {
+            assert!(acquire(0).allowed);
+        }
+        let decision = acquire(100);
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_millis(900));
+        // A token accrues every 250 milliseconds; the 150 milliseconds spent at 400 ms count towards the next one.
+        assert_eq!(acquire(400).remaining, 0);
+        assert!(!acquire(450).allowed);
+        assert!(acquire(500).allowed);
+        assert_eq!(acquire(2_000).remaining, 3);
+    }
+
+    #[test]
+    fn sliding_window_weights_previous_window() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut counter = WindowCounter::new(1_000, start);
+
+        for _ in 0..10 {
+            assert!(counter.decide_at(10, 1, at(900)).allowed);
+        }
+        // Just after the boundary, most of the previous window still counts.
+        assert!(!counter.decide_at(10, 1, at(1_050)).allowed);
+        let decision = counter.decide_at(10, 1, at(1_500));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 4);
+        assert_eq!(decision.reset, Duration::from_millis(500));
+        // Two windows later, nothing counts anymore.
+        assert_eq!(counter.decide_at(10, 10, at(3_000)).remaining, 0);
+    }
+
+    #[test]
+    fn gcra_spaces_requests() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut schedule = VirtualSchedule::new(1_000, start);
+
+        for remaining in (0..4).rev() {
+            assert_eq!(schedule.decide_at(4, 1, at(0)).remaining, remaining);
+        }
+        let decision = schedule.decide_at(4, 1, at(100));
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_millis(900));
+        // Every 250 milliseconds, one more request is allowed.
+        assert!(schedule.decide_at(4, 1, at(250)).allowed);
+        assert!(!schedule.decide_at(4, 1, at(300)).allowed);
+        assert_eq!(schedule.decide_at(4, 1, at(1_250)).remaining, 3);
+    }
+
+    #[test]
+    fn pacing_allows_no_bursts() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut schedule = VirtualSchedule::new(1_000, start);
+        let mut pace = |millis| schedule.decide_paced_at(1_000, 5, 1, at(millis));
+
+        assert!(pace(0).allowed);
+        let decision = pace(0);
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_millis(200));
+        assert!(!pace(150).allowed);
+        assert!(pace(200).allowed);
+        // Idle time does not accumulate into a burst.
+        assert!(pace(5_000).allowed);
+        assert!(!pace(5_000).allowed);
+    }
+
+    #[test]
+    fn calendar_resets_at_boundaries() {
+        let now = Instant::now();
+        let mut counter = CalendarCounter::new(3_600_000, 0, now);
+        let (_, elapsed) = counter.position(now);
+        let boundary = now + Duration::from_millis(u64::try_from(3_600_000 - elapsed).unwrap_or(0));
+
+        assert!(counter.decide_at(2, 2, now).allowed);
+        let decision = counter.decide_at(2, 1, now);
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, boundary - now);
+        // The next hour starts afresh, however recently the previous one was used up.
+        assert_eq!(counter.decide_at(2, 1, boundary).remaining, 1);
+
+        // Offsets from UTC shift the boundaries of days.
+        let utc = CalendarCounter::new(86_400_000, 0, now);
+        let tokyo = CalendarCounter::new(86_400_000, 540, now);
+        assert_eq!(
+            (tokyo.position(now).1 - utc.position(now).1).rem_euclid(86_400_000),
+            540 * 60_000
+        );
+    }
+
+    #[test]
+    fn sliding_log_is_exact() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut log = RequestLog::new(1_000);
+
+        assert!(log.decide_at(3, 1, at(0)).allowed);
+        assert!(log.decide_at(3, 2, at(600)).allowed);
+        let decision = log.decide_at(3, 1, at(999));
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_millis(1));
+        // The first request left the period, the other two did not.
+        assert!(log.decide_at(3, 1, at(1_000)).allowed);
+        assert!(!log.decide_at(3, 1, at(1_599)).allowed);
+
+        log.refund(1);
+        assert_eq!(log.decide_at(3, 1, at(1_599)).remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn limit_selects_algorithm() {
+        async fn handler(_: Limit<2, 60_000, Method, SlidingWindow>) {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<Method>::default());
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        let response = server.get("/").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn gcra_and_pacing_keep_separate_schedules() {
+        async fn burst(_: Limit<3, 60_000, Method, Gcra>) {}
+        async fn paced(_: Limit<1, 60_000, Method, Pacing>) {}
+
+        let my_app = Router::new()
+            .route("/burst", get(burst))
+            .route("/paced", get(paced))
+            .with_state(LimitState::<Method>::default());
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        for _ in 0..3 {
+            assert_eq!(server.get("/burst").await.status_code(), StatusCode::OK);
+        }
+        assert_eq!(server.get("/paced").await.status_code(), StatusCode::OK);
+        for uri in ["/burst", "/paced"] {
+            let response = server.get(uri).await;
+            assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::map::Map;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of baseline time constants after which the averages of an idle key have decayed to nothing, and its
+/// tracker is evicted.
+const IDLE_BASELINES: u32 = 5;
+
+/// Describes a detected spike in a key's request rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spike {
+    /// The short-term request rate, in requests per second.
+    pub rate: f64,
+    /// The long-term baseline request rate, in requests per second.
+    pub baseline: f64,
+}
+
+type SpikeCallback<K> = Arc<dyn Fn(&K, Spike) + Send + Sync>;
+
+/// Exponentially decaying request counters for a single key.
+struct RateTracker {
+    short: f64,
+    long: f64,
+    first_seen: Instant,
+    last_seen: Instant,
+    spiking: bool,
+}
+
+/// Detects sudden jumps in per-key request rates.
+///
+/// For every key, a short-term and a long-term exponentially weighted moving average (EWMA) of the request
+/// rate are tracked. When the short-term rate exceeds the long-term baseline by more than the configured factor,
+/// the callback is invoked once, and again only after the rate has settled back below the threshold.
+///
+/// Keys idle for several baseline time constants are forgotten, so they are tracked as new keys when they return.
+pub struct SpikeDetector<K> {
+    trackers: Map<K, RateTracker>,
+    last_sweep: Mutex<Option<Instant>>,
+    factor: f64,
+    window: Duration,
+    baseline: Duration,
+    callback: SpikeCallback<K>,
+}
+
+impl<K> SpikeDetector<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs a new `SpikeDetector` that invokes `callback` whenever a key's short-term rate exceeds
+    /// `factor` times its baseline. Uses a 1 second short-term window and a 60 second baseline by default.
+    pub fn new(factor: f64, callback: impl Fn(&K, Spike) + Send + Sync + 'static) -> Self {
+        Self {
+            trackers: Map::new(),
+            last_sweep: Mutex::new(None),
+            factor,
+            window: Duration::from_secs(1),
+            baseline: Duration::from_secs(60),
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Sets the time constant of the short-term rate average.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the time constant of the long-term baseline average.
+    /// Keys are not reported until they have been seen for at least one short-term window.
+    pub fn baseline(mut self, baseline: Duration) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    /// Records a request for `key` at `now`, invoking the callback if this request starts a spike.
+    pub fn observe(&self, key: &K, now: Instant) {
+        self.sweep(now);
+        let spike = match self.trackers.get_mut(key) {
+            Some(mut tracker) => self.update(&mut tracker, now),
+            None => {
+                self.trackers.insert(
+                    key.clone(),
+                    RateTracker {
+                        short: 1.0,
+                        long: 1.0,
+                        first_seen: now,
+                        last_seen: now,
+                        spiking: false,
+                    },
+                );
+                None
+            }
+        };
+
+        if let Some(spike) = spike {
+            (self.callback)(key, spike);
+        }
+    }
+
+    /// Evicts the trackers of idle keys, at most once per baseline time constant.
+    fn sweep(&self, now: Instant) {
+        {
+            let mut last_sweep = self.last_sweep.lock().unwrap_or_else(|e| e.into_inner());
+            match *last_sweep {
+                Some(last) if now.saturating_duration_since(last) < self.baseline => return,
+                _ => *last_sweep = Some(now),
+            }
+        }
+        let idle = self.baseline * IDLE_BASELINES;
+        self.trackers
+            .retain(|_, tracker| now.saturating_duration_since(tracker.last_seen) < idle);
+    }
+
+    fn update(&self, tracker: &mut RateTracker, now: Instant) -> Option<Spike> {
+        let elapsed = now
+            .saturating_duration_since(tracker.last_seen)
+            .as_secs_f64();
+        let window = self.window.as_secs_f64();
+        let baseline = self.baseline.as_secs_f64();
+
+        tracker.short = tracker.short * (-elapsed / window).exp() + 1.0;
+        tracker.long = tracker.long * (-elapsed / baseline).exp() + 1.0;
+        tracker.last_seen = now;
+
+        // Averages are not meaningful until a key has been seen for at least one short-term window.
+        let age = now.saturating_duration_since(tracker.first_seen);
+        if age < self.window {
+            return None;
+        }
+
+        // Normalize by the expected counter value of a constant rate, which corrects the bias of young keys.
+        let age = age.as_secs_f64();
+        let rate = tracker.short / (window * (1.0 - (-age / window).exp()));
+        let baseline = tracker.long / (baseline * (1.0 - (-age / baseline).exp()));
+        let exceeded = rate > baseline * self.factor;
+
+        let starts_spike = exceeded && !tracker.spiking;
+        tracker.spiking = exceeded;
+        starts_spike.then_some(Spike { rate, baseline })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn reports_a_spike_once() {
+        let spikes = Arc::new(AtomicUsize::new(0));
+        let counter = spikes.clone();
+        let detector = SpikeDetector::new(3.0, move |_: &u32, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        // One request per second for a minute establishes the baseline.
+        let start = Instant::now();
+        for s in 0..60 {
+            detector.observe(&1, start + Duration::from_secs(s));
+        }
+        assert_eq!(spikes.load(Ordering::Relaxed), 0);
+
+        // Then 100 requests within a single second.
+        let burst = start + Duration::from_secs(60);
+        for ms in 0..100 {
+            detector.observe(&1, burst + Duration::from_millis(ms * 10));
+        }
+        assert_eq!(spikes.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn evicts_idle_keys() {
+        let detector = SpikeDetector::new(3.0, |_: &u32, _| {});
+
+        let start = Instant::now();
+        detector.observe(&1, start);
+        detector.observe(&2, start + Duration::from_secs(240));
+        assert_eq!(detector.trackers.len(), 2);
+
+        // Key 1 has been idle for five baselines by now, while key 2 is still recent.
+        detector.observe(&3, start + Duration::from_secs(300));
+        assert_eq!(detector.trackers.len(), 2);
+        assert!(detector.trackers.get(&1).is_none());
+    }
+}
@@ -0,0 +1,669 @@
+use crate::map::Map;
+use crate::{BucketSnapshot, Clock, Key, SystemClock, TokenBucket};
+use futures_util::future::join;
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The outcome of a rate limit check, as reported by a [`Backend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// Whether the request may proceed.
+    pub allowed: bool,
+    /// The number of requests allowed within the period.
+    pub limit: usize,
+    /// The number of requests still allowed within the current period.
+    pub remaining: usize,
+    /// The time until the bucket is refilled.
+    pub reset: Duration,
+}
+
+/// An error reported by a storage [`Backend`], e.g. a lost connection to a remote store.
+#[derive(Debug)]
+pub struct BackendError(Box<dyn Error + Send + Sync>);
+
+impl BackendError {
+    /// Wraps the underlying error of a backend.
+    pub fn new(error: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self(error.into())
+    }
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate limit backend error: {}", self.0)
+    }
+}
+
+impl Error for BackendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+/// How a [`LimitState`](crate::LimitState) handles requests its [`Backend`] failed to decide on, e.g. because the
+/// remote store is unreachable, trading availability against strictness.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Lets the request through, so a failing store does not take the application down with it.
+    #[default]
+    FailOpen,
+    /// Rejects the request with [`LimitRejection::BackendError`](crate::LimitRejection::BackendError).
+    FailClosed,
+    /// Decides the request against the state's in-memory buckets instead, limiting each instance separately
+    /// until the backend recovers.
+    FallbackToLocal,
+}
+
+/// Storage for rate limit buckets, deciding whether requests of a key may proceed.
+///
+/// By default, [`LimitState`](crate::LimitState) keeps its buckets in process memory.
+/// Installing a backend with [`LimitState::with_backend`](crate::LimitState::with_backend) moves them elsewhere,
+/// e.g. to a store shared by several instances of the application.
+#[async_trait::async_trait]
+pub trait Backend<K>: Send + Sync {
+    /// Attempts to take `cost` tokens for `key` from its bucket of `count` tokens, refilled every `per` milliseconds.
+    /// Either all or none of the tokens are taken.
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError>;
+
+    /// Returns `cost` tokens previously taken for `key`, e.g. when a request drawing on several buckets is rejected
+    /// by one of them. The default implementation does nothing.
+    async fn refund(
+        &self,
+        _key: &K,
+        _count: usize,
+        _per: u64,
+        _cost: usize,
+    ) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    /// Persists any state the backend holds back, e.g. buckets not yet written to disk or reconciled with a remote
+    /// store, such as when the application shuts down. The default implementation does nothing.
+    async fn flush(&self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, B> Backend<K> for Arc<B>
+where
+    K: Sync,
+    B: Backend<K> + ?Sized,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        (**self).acquire(key, count, per, cost).await
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        (**self).refund(key, count, per, cost).await
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        (**self).flush().await
+    }
+}
+
+/// A [`Backend`] keeping token buckets in a concurrent map in process memory.
+/// Clones share the same buckets.
+#[derive(Clone)]
+pub struct MemoryBackend<K> {
+    buckets: Arc<Map<K, TokenBucket>>,
+    clock: Arc<dyn Clock>,
+    grace: usize,
+    debt: usize,
+    carry: usize,
+    fill: f64,
+}
+
+impl<K> Default for MemoryBackend<K>
+where
+    K: Key,
+{
+    /// Constructs a new `MemoryBackend` without any buckets.
+    fn default() -> Self {
+        Self {
+            buckets: Arc::new(Map::new()),
+            clock: Arc::new(SystemClock),
+            grace: 0,
+            debt: 0,
+            carry: 0,
+            fill: 1.0,
+        }
+    }
+}
+
+impl<K> MemoryBackend<K>
+where
+    K: Key,
+{
+    /// Replaces the clock used to refill buckets.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Grants newly seen keys `grace` tokens beyond the limit, until their bucket is first refilled.
+    pub fn with_grace(mut self, grace: usize) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Lets the buckets of newly seen keys borrow up to `max` tokens from future refills once they are empty.
+    pub fn with_debt(mut self, max: usize) -> Self {
+        self.debt = max;
+        self
+    }
+
+    /// Lets the buckets of newly seen keys roll up to `max` unused tokens over into the next period, on top of
+    /// their refill.
+    pub fn with_carry_over(mut self, max: usize) -> Self {
+        self.carry = max;
+        self
+    }
+
+    /// Fills the buckets of newly seen keys to `fraction` (between `0.0` and `1.0`) of their capacity instead of
+    /// filling them completely.
+    pub fn with_initial_fill(mut self, fraction: f64) -> Self {
+        self.fill = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns the tokens a new bucket of `capacity` tokens starts with, not counting any grace.
+    pub(crate) fn initial_tokens(&self, capacity: usize) -> usize {
+        (capacity as f64 * self.fill) as usize
+    }
+
+    /// Constructs the bucket of a newly seen key, holding its initial tokens plus its grace, with room for the
+    /// tokens carried over.
+    fn new_bucket(&self, capacity: usize, per: u64, now: Instant) -> TokenBucket {
+        TokenBucket {
+            tokens: self.initial_tokens(capacity).saturating_add(self.grace),
+            capacity: capacity.saturating_add(self.carry),
+            max_debt: self.debt,
+            ..TokenBucket::new(capacity, per, now)
+        }
+    }
+
+    /// Takes `cost` tokens for `key` at the given point in time.
+    pub(crate) fn acquire_at(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        // Look up existing buckets by reference so the key is only cloned for new buckets.
+        match self.buckets.get_mut(key) {
+            Some(mut bucket) => bucket.decide_at(cost, now),
+            None => self
+                .buckets
+                .entry(key.clone())
+                .or_insert_with(|| self.new_bucket(count, per, now))
+                .decide_at(cost, now),
+        }
+    }
+
+    /// Takes `cost` tokens for `key` like [`acquire_at`](Self::acquire_at), first resizing an existing bucket to
+    /// `count` tokens if its capacity differs. Tokens beyond the new capacity are dropped.
+    pub(crate) fn acquire_resized_at(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        self.acquire_burst_at(key, count, per, count, cost, now)
+    }
+
+    /// Takes `cost` tokens for `key` from a bucket holding up to `burst` tokens, refilled by `count` tokens every
+    /// `per` milliseconds. An existing bucket is resized first if it differs.
+    pub(crate) fn acquire_burst_at(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        burst: usize,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        self.acquire_shaped_at(key, count, per, burst, false, cost, now)
+    }
+
+    /// Takes `cost` tokens for `key` from a bucket refilled continuously at `count` tokens per `per` milliseconds.
+    /// An existing bucket is converted first if it differs.
+    pub(crate) fn acquire_smooth_at(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        self.acquire_shaped_at(key, count, per, count, true, cost, now)
+    }
+
+    /// Takes `cost` tokens for `key` from a bucket of the given shape, reshaping an existing bucket if it differs.
+    #[allow(clippy::too_many_arguments)]
+    fn acquire_shaped_at(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        burst: usize,
+        smooth: bool,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        // Look up existing buckets by reference so the key is only cloned for new buckets.
+        match self.buckets.get_mut(key) {
+            Some(mut bucket) => {
+                let capacity = burst.saturating_add(self.carry);
+                if bucket.capacity != capacity || bucket.rate != count || bucket.smooth != smooth {
+                    bucket.resize(capacity, count);
+                    bucket.smooth = smooth;
+                }
+                bucket.decide_at(cost, now)
+            }
+            None => self
+                .buckets
+                .entry(key.clone())
+                .or_insert_with(|| TokenBucket {
+                    rate: count,
+                    smooth,
+                    ..self.new_bucket(burst, per, now)
+                })
+                .decide_at(cost, now),
+        }
+    }
+
+    /// Returns `cost` tokens to the bucket of `key`, if it exists.
+    pub(crate) fn refund_at(&self, key: &K, cost: usize, now: Instant) {
+        if let Some(mut bucket) = self.buckets.get_mut(key) {
+            bucket.refund(cost, now);
+        }
+    }
+
+    /// Returns the time until `cost` tokens could be taken from the bucket of `key`, if it exists and they fit.
+    pub(crate) fn retry_after_at(&self, key: &K, cost: usize, now: Instant) -> Option<Duration> {
+        self.buckets.get(key)?.retry_after_at(cost, now)
+    }
+
+    /// Replaces the bucket of `key`.
+    pub(crate) fn insert(&self, key: K, bucket: TokenBucket) {
+        self.buckets.insert(key, bucket);
+    }
+
+    /// Removes the bucket of `key`.
+    pub(crate) fn remove(&self, key: &K) {
+        self.buckets.remove(key);
+    }
+
+    /// Takes a snapshot of all buckets at the given point in time.
+    pub(crate) fn snapshot_at(&self, now: Instant) -> Vec<BucketSnapshot<K>> {
+        crate::snapshot::snapshot(&self.buckets, now)
+    }
+
+    /// Returns a reference to the buckets that does not keep them alive.
+    #[cfg(all(feature = "serde", feature = "tokio"))]
+    pub(crate) fn downgrade(&self) -> std::sync::Weak<Map<K, TokenBucket>> {
+        Arc::downgrade(&self.buckets)
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for MemoryBackend<K>
+where
+    K: Key,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        Ok(self.acquire_at(key, count, per, cost, self.clock.now()))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        _count: usize,
+        _per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        self.refund_at(key, cost, self.clock.now());
+        Ok(())
+    }
+}
+
+/// A [`Backend`] storing the buckets of limits with short periods in one backend and those of limits with long
+/// periods in another, e.g. keeping per-second limits in memory while persisting daily quotas in a database.
+pub struct PeriodSplitBackend<S, L> {
+    short: S,
+    long: L,
+    threshold: u64,
+}
+
+impl<S, L> PeriodSplitBackend<S, L> {
+    /// Constructs a new `PeriodSplitBackend` using `short` for limits with periods below `threshold` and `long`
+    /// for all others.
+    pub fn new(short: S, long: L, threshold: Duration) -> Self {
+        Self {
+            short,
+            long,
+            threshold: threshold.as_millis().try_into().unwrap_or(u64::MAX),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, S, L> Backend<K> for PeriodSplitBackend<S, L>
+where
+    K: Sync,
+    S: Backend<K>,
+    L: Backend<K>,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        if per < self.threshold {
+            self.short.acquire(key, count, per, cost).await
+        } else {
+            self.long.acquire(key, count, per, cost).await
+        }
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        if per < self.threshold {
+            self.short.refund(key, count, per, cost).await
+        } else {
+            self.long.refund(key, count, per, cost).await
+        }
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        let (short, long) = join(self.short.flush(), self.long.flush()).await;
+        short.and(long)
+    }
+}
+
+/// A [`Backend`] failing calls to the wrapped backend that take longer than a timeout, so a slow store never adds
+/// unbounded latency to requests. Timed out calls are handled by the state's [`FailurePolicy`], e.g. falling back
+/// to in-memory buckets with [`FailurePolicy::FallbackToLocal`].
+#[cfg(feature = "tokio")]
+pub struct TimeoutBackend<B> {
+    inner: B,
+    timeout: Duration,
+}
+
+#[cfg(feature = "tokio")]
+impl<B> TimeoutBackend<B> {
+    /// Constructs a new `TimeoutBackend` failing calls to `inner` that take longer than `timeout`.
+    pub fn new(inner: B, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    async fn timed<T>(
+        &self,
+        call: impl std::future::Future<Output = Result<T, BackendError>>,
+    ) -> Result<T, BackendError> {
+        tokio::time::timeout(self.timeout, call)
+            .await
+            .map_err(BackendError::new)?
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[async_trait::async_trait]
+impl<K, B> Backend<K> for TimeoutBackend<B>
+where
+    K: Sync,
+    B: Backend<K>,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        self.timed(self.inner.acquire(key, count, per, cost)).await
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        self.timed(self.inner.refund(key, count, per, cost)).await
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        self.timed(self.inner.flush()).await
+    }
+}
+
+/// The state of a token bucket as kept by remote stores, timed by wall-clock milliseconds since the Unix epoch
+/// so that all instances agree on it. Refilled like [`TokenBucket`].
+#[cfg_attr(
+    not(any(
+        feature = "dynamodb",
+        feature = "etcd",
+        feature = "memcached",
+        feature = "postgres",
+        feature = "sled"
+    )),
+    allow(dead_code)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StoredBucket {
+    pub(crate) tokens: u64,
+    pub(crate) last_refill: u64,
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "dynamodb",
+        feature = "etcd",
+        feature = "memcached",
+        feature = "postgres",
+        feature = "sled"
+    )),
+    allow(dead_code)
+)]
+impl StoredBucket {
+    /// Refills the bucket of `count` tokens per `per` milliseconds at `now`, then attempts to take `cost` tokens.
+    pub(crate) fn decide(&mut self, count: usize, per: u64, cost: usize, now: u64) -> Decision {
+        self.refill(count, per, now);
+        let allowed = self.tokens >= cost as u64;
+        if allowed {
+            self.tokens -= cost as u64;
+        }
+        Decision {
+            allowed,
+            limit: count,
+            remaining: usize::try_from(self.tokens).unwrap_or(usize::MAX),
+            reset: Duration::from_millis(per.saturating_sub(now.saturating_sub(self.last_refill))),
+        }
+    }
+
+    /// Returns `cost` tokens to the bucket of `count` tokens per `per` milliseconds at `now`.
+    pub(crate) fn refund(&mut self, count: usize, per: u64, cost: usize, now: u64) {
+        self.refill(count, per, now);
+        self.tokens = self.tokens.saturating_add(cost as u64).min(count as u64);
+    }
+
+    fn refill(&mut self, count: usize, per: u64, now: u64) {
+        let elapsed = now.saturating_sub(self.last_refill);
+        if elapsed < per {
+            return;
+        }
+        if per == 0 {
+            self.tokens = count as u64;
+            self.last_refill = now;
+            return;
+        }
+        let new_tokens = (elapsed / per).saturating_mul(count as u64);
+        self.tokens = self.tokens.saturating_add(new_tokens).min(count as u64);
+        self.last_refill = now - elapsed % per;
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "etcd", feature = "memcached", feature = "sled")),
+    allow(dead_code)
+)]
+impl StoredBucket {
+    /// The size of the encoded form.
+    pub(crate) const LEN: usize = 16;
+
+    /// Constructs a new, full bucket of `count` tokens at `now`.
+    pub(crate) fn full(count: usize, now: u64) -> Self {
+        Self {
+            tokens: count as u64,
+            last_refill: now,
+        }
+    }
+
+    /// Encodes the bucket as two little-endian integers.
+    pub(crate) fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..8].copy_from_slice(&self.tokens.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.last_refill.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a bucket encoded by [`to_bytes`](Self::to_bytes).
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let tokens = bytes.get(..8)?.try_into().ok()?;
+        let last_refill = bytes.get(8..Self::LEN)?.try_into().ok()?;
+        Some(Self {
+            tokens: u64::from_le_bytes(tokens),
+            last_refill: u64::from_le_bytes(last_refill),
+        })
+    }
+
+    /// Returns the current wall-clock time in milliseconds since the Unix epoch.
+    pub(crate) fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+    use http::Method;
+
+    #[test]
+    fn stored_bucket_refills_and_round_trips() {
+        let mut bucket = StoredBucket::full(2, 1_000);
+        assert!(bucket.decide(2, 100, 2, 1_050).allowed);
+        let decision = bucket.decide(2, 100, 1, 1_099);
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_millis(1));
+        assert!(bucket.decide(2, 100, 1, 1_150).allowed);
+        assert_eq!(bucket.last_refill, 1_100);
+
+        bucket.refund(2, 100, 5, 1_150);
+        assert_eq!(bucket.tokens, 2);
+        assert_eq!(StoredBucket::from_bytes(&bucket.to_bytes()), Some(bucket));
+        assert_eq!(StoredBucket::from_bytes(&[0; 8]), None);
+    }
+
+    #[test]
+    fn period_split_routes_by_period() {
+        let short = MemoryBackend::<Method>::default();
+        let long = MemoryBackend::<Method>::default();
+        let split = PeriodSplitBackend::new(short.clone(), long.clone(), Duration::from_secs(60));
+        let acquire = |per| {
+            split
+                .acquire(&Method::GET, 1, per, 1)
+                .now_or_never()
+                .and_then(Result::ok)
+                .is_some_and(|decision| decision.allowed)
+        };
+
+        assert!(acquire(1_000));
+        assert!(!acquire(1_000));
+        assert!(acquire(86_400_000));
+        assert_eq!(short.buckets.len(), 1);
+        assert_eq!(long.buckets.len(), 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn timeout_falls_back_to_local() {
+        struct Slow;
+
+        #[async_trait::async_trait]
+        impl Backend<Method> for Slow {
+            async fn acquire(
+                &self,
+                _key: &Method,
+                count: usize,
+                _per: u64,
+                _cost: usize,
+            ) -> Result<Decision, BackendError> {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(Decision {
+                    allowed: true,
+                    limit: count,
+                    remaining: count,
+                    reset: Duration::ZERO,
+                })
+            }
+        }
+
+        let state = crate::LimitState::default()
+            .with_backend(TimeoutBackend::new(Slow, Duration::from_millis(10)))
+            .with_failure_policy(FailurePolicy::FallbackToLocal);
+        let decision = state
+            .acquire(&Method::GET, 1, 60_000, 1)
+            .await
+            .expect("Fell back to local buckets");
+        assert!(decision.is_some_and(|decision| decision.allowed));
+        let decision = state
+            .acquire(&Method::GET, 1, 60_000, 1)
+            .await
+            .expect("Fell back to local buckets");
+        assert!(decision.is_some_and(|decision| !decision.allowed));
+    }
+}
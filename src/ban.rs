@@ -0,0 +1,321 @@
+use crate::map::Map;
+use std::hash::Hash;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A single entry of a [`BanList`], as exchanged with a [`BanStore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BanEntry<K> {
+    /// The banned key.
+    pub key: K,
+    /// The wall-clock time at which the ban is lifted, or `None` for a permanent ban.
+    pub until: Option<SystemTime>,
+}
+
+/// Storage used to persist a [`BanList`] so that bans survive process restarts.
+pub trait BanStore<K>: Send + Sync {
+    /// Loads all previously persisted ban entries.
+    fn load(&self) -> io::Result<Vec<BanEntry<K>>>;
+
+    /// Replaces the persisted ban entries with the given ones.
+    fn save(&self, entries: &[BanEntry<K>]) -> io::Result<()>;
+}
+
+/// Automatic ban policy: keys that are rejected `strikes` times in a row get banned for `duration`.
+#[derive(Debug, Clone, Copy)]
+struct AutoBan {
+    strikes: u32,
+    duration: Duration,
+}
+
+/// A deny list of keys that are rejected outright, regardless of their remaining rate limit.
+/// Keys can be banned manually or automatically after repeatedly exceeding their limit,
+/// and the list can be persisted to a [`BanStore`] so hard-banned keys stay banned across deploys. The list is
+/// saved whenever the [`LimitState`](crate::LimitState) consulting it is [flushed](crate::LimitState::flush).
+pub struct BanList<K> {
+    entries: Map<K, Option<SystemTime>>,
+    /// The number of consecutive rejections of each key, and the time of the last one.
+    strikes: Map<K, (u32, Instant)>,
+    last_sweep: Mutex<Option<Instant>>,
+    auto_ban: Option<AutoBan>,
+    store: Option<Box<dyn BanStore<K>>>,
+}
+
+impl<K> Default for BanList<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs an empty `BanList` without automatic bans or persistence.
+    fn default() -> Self {
+        Self {
+            entries: Map::new(),
+            strikes: Map::new(),
+            last_sweep: Mutex::new(None),
+            auto_ban: None,
+            store: None,
+        }
+    }
+}
+
+impl<K> BanList<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs a `BanList` backed by `store`, reloading all entries that have not yet expired.
+    pub fn load(store: impl BanStore<K> + 'static) -> io::Result<Self> {
+        let now = SystemTime::now();
+        let entries = Map::new();
+        for entry in store.load()? {
+            if entry.until.is_none_or(|until| until > now) {
+                entries.insert(entry.key, entry.until);
+            }
+        }
+
+        Ok(Self {
+            entries,
+            store: Some(Box::new(store)),
+            ..Default::default()
+        })
+    }
+
+    /// Enables automatic bans: a key rejected `strikes` times in a row is banned for `duration`. Strikes of keys
+    /// that were not rejected for `duration` are forgotten.
+    pub fn auto_ban(mut self, strikes: u32, duration: Duration) -> Self {
+        self.auto_ban = Some(AutoBan { strikes, duration });
+        self
+    }
+
+    /// Bans `key` for the given duration, or permanently if `duration` is `None`.
+    pub fn ban(&self, key: K, duration: Option<Duration>) {
+        let until = duration.map(|d| SystemTime::now() + d);
+        self.strikes.remove(&key);
+        self.entries.insert(key, until);
+    }
+
+    /// Lifts the ban on `key`, returning `true` if it was banned.
+    pub fn unban(&self, key: &K) -> bool {
+        self.strikes.remove(key);
+        self.entries.remove(key).is_some()
+    }
+
+    /// Returns `true` if `key` is currently banned. Expired bans are removed on access.
+    pub fn is_banned(&self, key: &K) -> bool {
+        let now = SystemTime::now();
+        match self.entries.get(key).map(|until| *until) {
+            None => false,
+            Some(None) => true,
+            Some(Some(until)) if until > now => true,
+            Some(Some(_)) => {
+                self.entries
+                    .remove_if(key, |_, until| until.is_some_and(|until| until <= now));
+                false
+            }
+        }
+    }
+
+    /// Returns all bans that have not yet expired.
+    pub fn entries(&self) -> Vec<BanEntry<K>> {
+        let now = SystemTime::now();
+        self.entries
+            .iter()
+            .filter(|entry| entry.value().is_none_or(|until| until > now))
+            .map(|entry| BanEntry {
+                key: entry.key().clone(),
+                until: *entry.value(),
+            })
+            .collect()
+    }
+
+    /// Persists all current bans to the configured store. Does nothing if the list has no store.
+    pub fn save(&self) -> io::Result<()> {
+        match &self.store {
+            Some(store) => store.save(&self.entries()),
+            None => Ok(()),
+        }
+    }
+
+    /// Records the outcome of a rate limit check for `key`, banning it once it strikes out.
+    pub(crate) fn record(&self, key: &K, allowed: bool) {
+        let Some(auto_ban) = self.auto_ban else {
+            return;
+        };
+
+        if allowed {
+            self.strikes.remove(key);
+            return;
+        }
+
+        let now = Instant::now();
+        self.sweep(auto_ban.duration, now);
+        let strikes = {
+            let mut strikes = self.strikes.entry(key.clone()).or_insert((0, now));
+            *strikes = (strikes.0 + 1, now);
+            strikes.0
+        };
+        if strikes >= auto_ban.strikes {
+            self.ban(key.clone(), Some(auto_ban.duration));
+        }
+    }
+
+    /// Forgets the strikes of keys not rejected within `window`, at most once per window.
+    fn sweep(&self, window: Duration, now: Instant) {
+        {
+            let mut last_sweep = self.last_sweep.lock().unwrap_or_else(|e| e.into_inner());
+            match *last_sweep {
+                Some(last) if now.saturating_duration_since(last) < window => return,
+                _ => *last_sweep = Some(now),
+            }
+        }
+        self.strikes
+            .retain(|_, (_, last)| now.saturating_duration_since(*last) < window);
+    }
+}
+
+/// A [`BanStore`] that persists ban entries as JSON in a file.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+pub struct FileBanStore {
+    path: std::path::PathBuf,
+    #[cfg(feature = "encryption")]
+    key: Option<crate::SnapshotKey>,
+}
+
+#[cfg(feature = "serde")]
+impl FileBanStore {
+    /// Constructs a new `FileBanStore` reading from and writing to `path`.
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            #[cfg(feature = "encryption")]
+            key: None,
+        }
+    }
+
+    /// Encrypts the file with `key`, so it does not leak the banned keys at rest.
+    #[cfg(feature = "encryption")]
+    pub fn encrypted(mut self, key: crate::SnapshotKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            return key.open(&bytes);
+        }
+        Ok(bytes)
+    }
+
+    fn encode(&self, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            return key.seal(&bytes);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K> BanStore<K> for FileBanStore
+where
+    K: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn load(&self) -> io::Result<Vec<BanEntry<K>>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&self.decode(bytes)?)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, entries: &[BanEntry<K>]) -> io::Result<()> {
+        // Write to a temporary file first so a crash never leaves a truncated list behind.
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, self.encode(serde_json::to_vec(entries)?)?)?;
+        std::fs::rename(tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::LimitState;
+    use http::Method;
+
+    struct MemoryStore<K>(Mutex<Vec<BanEntry<K>>>);
+
+    impl<K> Default for MemoryStore<K> {
+        fn default() -> Self {
+            Self(Mutex::new(Vec::new()))
+        }
+    }
+
+    impl<K> BanStore<K> for &'static MemoryStore<K>
+    where
+        K: Clone + Send + Sync,
+    {
+        fn load(&self) -> io::Result<Vec<BanEntry<K>>> {
+            Ok(self.0.lock().expect("poisoned").clone())
+        }
+
+        fn save(&self, entries: &[BanEntry<K>]) -> io::Result<()> {
+            *self.0.lock().expect("poisoned") = entries.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn persisted_bans_survive_reload() {
+        let store: &'static MemoryStore<u32> = Box::leak(Box::default());
+
+        let bans = BanList::load(store)
+            .expect("load")
+            .auto_ban(2, Duration::from_secs(60));
+        bans.ban(1, None);
+        bans.record(&2, false);
+        bans.record(&2, false);
+        bans.record(&3, false);
+        bans.record(&3, true);
+        bans.record(&3, false);
+        bans.save().expect("save");
+
+        let reloaded = BanList::load(store).expect("reload");
+        assert!(reloaded.is_banned(&1));
+        assert!(reloaded.is_banned(&2));
+        assert!(!reloaded.is_banned(&3));
+    }
+
+    #[tokio::test]
+    async fn flushing_saves_bans() {
+        let store: &'static MemoryStore<Method> = Box::leak(Box::default());
+        let bans = BanList::load(store)
+            .expect("load")
+            .auto_ban(1, Duration::from_secs(60));
+        let state = LimitState::default().with_ban_list(bans);
+        assert!(state.check(Method::GET, 1, 60_000));
+        assert!(!state.check(Method::GET, 1, 60_000));
+
+        state.flush().await.expect("flush");
+        assert!(BanList::load(store)
+            .expect("reload")
+            .is_banned(&Method::GET));
+    }
+
+    #[test]
+    fn forgets_stale_strikes() {
+        let bans = BanList::default().auto_ban(2, Duration::from_millis(20));
+        for key in 0..10 {
+            bans.record(&key, false);
+        }
+        assert_eq!(bans.strikes.len(), 10);
+
+        std::thread::sleep(Duration::from_millis(30));
+        bans.record(&10, false);
+        assert_eq!(bans.strikes.len(), 1);
+        bans.record(&0, false);
+        assert!(!bans.is_banned(&0));
+    }
+}
@@ -0,0 +1,28 @@
+use axum_core::response::{IntoResponse, Response};
+use http::StatusCode;
+use std::error::Error;
+use std::fmt::{Debug, Display};
+
+/// The error returned by [`LimitState::charge_batch`](crate::LimitState::charge_batch) when one of the keys of a
+/// batch exceeded its limit. The keys charged before it are refunded in that case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchLimitExceeded<K> {
+    /// The position of the rejected key within the batch.
+    pub index: usize,
+    /// The rejected key.
+    pub key: K,
+}
+
+impl<K> Display for BatchLimitExceeded<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate limit exceeded for batch element {}.", self.index)
+    }
+}
+
+impl<K: Debug> Error for BatchLimitExceeded<K> {}
+
+impl<K> IntoResponse for BatchLimitExceeded<K> {
+    fn into_response(self) -> Response {
+        (StatusCode::TOO_MANY_REQUESTS, self.to_string()).into_response()
+    }
+}
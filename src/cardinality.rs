@@ -0,0 +1,194 @@
+use crate::map::Map;
+use crate::rejection::Format;
+use crate::{Key, LimitRejection, Member, RateLimitExceeded};
+use axum_core::extract::{FromRef, FromRequestParts};
+use http::request::Parts;
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The distinct members of one parent key seen in its current window.
+struct Touched<K> {
+    start: Instant,
+    members: HashSet<K>,
+}
+
+/// Manages the distinct members every parent key touched, for [`CardinalityLimit`].
+/// Clones share the same state.
+pub struct CardinalityState<K>
+where
+    K: Member,
+{
+    touched: Arc<Map<K::Parent, Touched<K>>>,
+}
+
+impl<K> Clone for CardinalityState<K>
+where
+    K: Member,
+{
+    fn clone(&self) -> Self {
+        Self {
+            touched: self.touched.clone(),
+        }
+    }
+}
+
+impl<K> Default for CardinalityState<K>
+where
+    K: Member,
+{
+    fn default() -> Self {
+        Self {
+            touched: Arc::new(Map::new()),
+        }
+    }
+}
+
+impl<K> CardinalityState<K>
+where
+    K: Member,
+{
+    /// Records that the parent of `key` touched it at `now`, returning `false` if that would exceed `max` distinct
+    /// members in the window of `per` milliseconds. Members already touched in the window are always allowed.
+    pub(crate) fn touch(&self, key: K, max: usize, per: u64, now: Instant) -> bool {
+        let mut touched = self.touched.entry(key.parent()).or_insert_with(|| Touched {
+            start: now,
+            members: HashSet::new(),
+        });
+        if now.saturating_duration_since(touched.start) >= Duration::from_millis(per) {
+            touched.start = now;
+            touched.members.clear();
+        }
+        if touched.members.contains(&key) {
+            return true;
+        }
+        if touched.members.len() >= max {
+            return false;
+        }
+        touched.members.insert(key);
+        true
+    }
+}
+
+/// Limits the number of distinct keys each parent key may touch to `MAX` per window of `PER` milliseconds, e.g.
+/// one API key accessing at most 100 distinct resources per hour, however often it accesses each of them.
+///
+/// Requires a [`CardinalityState<K>`] in the router state. The window of a parent starts with the first key it
+/// touches. Requests for keys beyond the limit are rejected with `429 Too Many Requests`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CardinalityLimit<const MAX: usize, const PER: u64, K>(pub K::Extractor)
+where
+    K: Member;
+
+impl<const MAX: usize, const PER: u64, K> Deref for CardinalityLimit<MAX, PER, K>
+where
+    K: Member,
+{
+    type Target = K::Extractor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const MAX: usize, const PER: u64, K> DerefMut for CardinalityLimit<MAX, PER, K>
+where
+    K: Member,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<const MAX: usize, const PER: u64, K> CardinalityLimit<MAX, PER, K>
+where
+    K: Member,
+{
+    /// Consumes the limit and returns the inner extractor.
+    pub fn into_inner(self) -> K::Extractor {
+        self.0
+    }
+}
+
+#[async_trait::async_trait]
+impl<const MAX: usize, const PER: u64, K, S> FromRequestParts<S> for CardinalityLimit<MAX, PER, K>
+where
+    CardinalityState<K>: FromRef<S>,
+    S: Send + Sync,
+    K: Member,
+    K::Extractor: FromRequestParts<S> + Send,
+{
+    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key_extractor = match K::Extractor::from_request_parts(parts, state).await {
+            Ok(ke) => ke,
+            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
+        };
+
+        let cardinality_state: CardinalityState<K> = FromRef::from_ref(state);
+        let key = K::from_extractor(&key_extractor);
+        if cardinality_state.touch(key, MAX, PER, Instant::now()) {
+            Ok(Self(key_extractor))
+        } else {
+            Err(LimitRejection::RateLimitExceeded(
+                RateLimitExceeded::exhausted(MAX).negotiated(Format::negotiate(
+                    &parts.headers,
+                    false,
+                    false,
+                )),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{StatusCode, Uri};
+
+    /// A resource identified by a path like `/:api_key/:resource`, belonging to its API key.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Resource(String);
+
+    impl Key for Resource {
+        type Extractor = Uri;
+
+        fn from_extractor(extractor: &Self::Extractor) -> Self {
+            Resource(extractor.path().to_string())
+        }
+    }
+
+    impl Member for Resource {
+        type Parent = Uri;
+
+        fn parent(&self) -> Uri {
+            let api_key = self.0.split('/').nth(1).unwrap_or_default();
+            Uri::try_from(format!("/{api_key}")).unwrap_or_default()
+        }
+    }
+
+    #[tokio::test]
+    async fn bounds_distinct_keys() {
+        async fn handler(_: CardinalityLimit<2, 60_000, Resource>) {}
+
+        let my_app = Router::new()
+            .route("/:api_key/:resource", get(handler))
+            .with_state(CardinalityState::<Resource>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/key/a").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/key/b").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/key/a").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/key/c").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(server.get("/other/c").await.status_code(), StatusCode::OK);
+    }
+}
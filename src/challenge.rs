@@ -0,0 +1,57 @@
+use crate::map::Map;
+use axum_core::response::Response;
+use http::request::Parts;
+use std::hash::Hash;
+
+type ChallengeGenerator<K> = Box<dyn Fn(&Parts, &K) -> Response + Send + Sync>;
+
+/// A challenge flow for rejected requests, e.g. to let browser traffic recover from a rate limit by solving a CAPTCHA.
+///
+/// When installed on a [`LimitState`](crate::LimitState), requests exceeding their limit are answered with the
+/// response of the generator instead of `429 Too Many Requests`. Once the application has verified a solution, it
+/// reports it with [`LimitState::challenge_solved`](crate::LimitState::challenge_solved), granting the key a number
+/// of requests beyond its limit.
+pub struct Challenge<K> {
+    generator: ChallengeGenerator<K>,
+    allowance: usize,
+    grants: Map<K, usize>,
+}
+
+impl<K> Challenge<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs a new `Challenge` answering rejected requests with the response returned by `generator`.
+    /// A solved challenge grants ten requests beyond the limit by default.
+    pub fn new(generator: impl Fn(&Parts, &K) -> Response + Send + Sync + 'static) -> Self {
+        Self {
+            generator: Box::new(generator),
+            allowance: 10,
+            grants: Map::new(),
+        }
+    }
+
+    /// Sets the number of requests beyond the limit granted for every solved challenge.
+    pub fn allowance(mut self, requests: usize) -> Self {
+        self.allowance = requests;
+        self
+    }
+
+    /// Grants `key` the configured allowance.
+    pub(crate) fn solved(&self, key: K) {
+        *self.grants.entry(key).or_insert(0) += self.allowance;
+    }
+
+    /// Decides what to do with a rejected request: `None` if it may proceed on a granted allowance,
+    /// otherwise the challenge to respond with.
+    pub(crate) fn challenge(&self, parts: &Parts, key: &K) -> Option<Response> {
+        if let Some(mut remaining) = self.grants.get_mut(key) {
+            if *remaining > 0 {
+                *remaining -= 1;
+                return None;
+            }
+        }
+        self.grants.remove_if(key, |_, remaining| *remaining == 0);
+        Some((self.generator)(parts, key))
+    }
+}
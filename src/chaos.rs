@@ -0,0 +1,126 @@
+use crate::KeyHasher;
+use http::request::Parts;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+type ChaosFilter<K> = Arc<dyn Fn(&Parts, &K) -> bool + Send + Sync>;
+
+/// Fault injection for testing client resilience.
+///
+/// When installed on a [`LimitState`](crate::LimitState), requests are rejected at random with the configured
+/// probability, as if their rate limit had been exceeded. The rejection carries a `Retry-After` header so clients'
+/// backoff implementations can be verified under controlled conditions.
+pub struct Chaos<K> {
+    probability: f64,
+    retry_after: Duration,
+    filter: Option<ChaosFilter<K>>,
+    random: KeyHasher,
+    counter: AtomicU64,
+}
+
+impl<K> Chaos<K> {
+    /// Constructs a new `Chaos` rejecting requests with the given probability, between `0.0` and `1.0`.
+    /// Rejections ask clients to retry after one second by default.
+    pub fn new(probability: f64) -> Self {
+        let random = RandomState::new();
+        let seed = u128::from(random.hash_one(0u8)) << 64 | u128::from(random.hash_one(1u8));
+        Self {
+            probability: probability.clamp(0.0, 1.0),
+            retry_after: Duration::from_secs(1),
+            filter: None,
+            random: KeyHasher::new(seed),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Draws faults from a sequence determined by `seed` instead of a random one, so a failing run can be
+    /// reproduced.
+    pub fn seed(mut self, seed: u128) -> Self {
+        self.random = KeyHasher::new(seed);
+        self
+    }
+
+    /// Sets the delay reported in the `Retry-After` header of injected rejections.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Restricts fault injection to requests for which `filter` returns `true`,
+    /// e.g. to select particular keys or routes.
+    pub fn when(mut self, filter: impl Fn(&Parts, &K) -> bool + Send + Sync + 'static) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Decides whether to inject a rejection for this request, returning the retry delay if so.
+    pub(crate) fn inject(&self, parts: &Parts, key: &K) -> Option<Duration> {
+        if let Some(filter) = &self.filter {
+            if !filter(parts, key) {
+                return None;
+            }
+        }
+
+        // Hashing a counter with a randomly keyed hasher is plenty random for fault injection.
+        let sample = self
+            .random
+            .hash(&self.counter.fetch_add(1, Ordering::Relaxed));
+        let roll = (sample >> 11) as f64 / (1u64 << 53) as f64;
+        (roll < self.probability).then_some(self.retry_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request};
+
+    /// Returns the number of rejections `chaos` injects into `requests` requests.
+    fn injected(chaos: &Chaos<Method>, requests: usize) -> usize {
+        let (parts, ()) = Request::new(()).into_parts();
+        (0..requests)
+            .filter(|_| chaos.inject(&parts, &Method::GET).is_some())
+            .count()
+    }
+
+    #[test]
+    fn injects_at_its_probability() {
+        let chaos = Chaos::new(0.3).seed(42);
+        let rate = injected(&chaos, 10_000) as f64 / 10_000.0;
+        assert!((0.28..0.32).contains(&rate), "injected {rate}");
+
+        // The same seed injects the same faults.
+        let [first, second] = [7, 7].map(|seed| {
+            let chaos = Chaos::new(0.5).seed(seed);
+            let (parts, ()) = Request::new(()).into_parts();
+            (0..64)
+                .map(|_| chaos.inject(&parts, &Method::GET).is_some())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn never_injects_without_probability() {
+        assert_eq!(injected(&Chaos::new(0.0).seed(42), 10_000), 0);
+        assert_eq!(injected(&Chaos::new(-1.0).seed(42), 10_000), 0);
+    }
+
+    #[test]
+    fn injects_filtered_requests_only() {
+        let (parts, ()) = Request::new(()).into_parts();
+        let chaos = Chaos::new(1.0)
+            .seed(42)
+            .retry_after(Duration::from_secs(5))
+            .when(|_, method| method == Method::POST);
+        assert_eq!(
+            chaos.inject(&parts, &Method::POST),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(chaos.inject(&parts, &Method::GET), None);
+        assert_eq!(injected(&chaos, 100), 0);
+    }
+}
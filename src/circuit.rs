@@ -0,0 +1,290 @@
+use crate::{Backend, BackendError, Clock, Decision, SystemClock};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The state of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls are passed to the wrapped backend.
+    Closed,
+    /// The wrapped backend failed too often; calls fail immediately until the cool-down has passed.
+    Open,
+    /// The cool-down has passed; a single trial call decides whether the circuit closes or opens again.
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    failures: u32,
+    opened_at: Option<Instant>,
+    probing: bool,
+}
+
+/// A [`Backend`] that stops calling the wrapped backend for a cool-down period after a number of consecutive
+/// failures, so requests are not held up by a store that is down. Calls made while the circuit is open fail
+/// immediately and are handled by the state's [`FailurePolicy`](crate::FailurePolicy).
+///
+/// Once the cool-down has passed, a single call is let through to probe the backend: if it succeeds the circuit
+/// closes again, otherwise it stays open for another cool-down. The [`state`](Self::state) can be exported as a
+/// metric. Clones share the same circuit.
+#[derive(Clone)]
+pub struct CircuitBreaker<B> {
+    inner: B,
+    threshold: u32,
+    cool_down: Duration,
+    circuit: Arc<Mutex<Circuit>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<B> CircuitBreaker<B> {
+    /// Constructs a new `CircuitBreaker` opening after `threshold` consecutive failures of `inner`, for
+    /// `cool_down`.
+    pub fn new(inner: B, threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            inner,
+            threshold: threshold.max(1),
+            cool_down,
+            circuit: Arc::new(Mutex::new(Circuit::default())),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replaces the clock used to time the cool-down.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        let circuit = self.circuit.lock().unwrap_or_else(|e| e.into_inner());
+        match circuit.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if self.clock.now() < opened_at + self.cool_down => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Returns the number of consecutive failures of the wrapped backend.
+    pub fn failures(&self) -> u32 {
+        self.circuit
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .failures
+    }
+
+    /// Checks whether a call may be passed to the wrapped backend, claiming the trial call if the circuit is
+    /// half-open.
+    fn admit(&self) -> Result<Call<'_, B>, BackendError> {
+        let mut circuit = self.circuit.lock().unwrap_or_else(|e| e.into_inner());
+        match circuit.opened_at {
+            None => Ok(Call {
+                breaker: self,
+                probe: false,
+            }),
+            Some(opened_at)
+                if !circuit.probing && self.clock.now() >= opened_at + self.cool_down =>
+            {
+                circuit.probing = true;
+                Ok(Call {
+                    breaker: self,
+                    probe: true,
+                })
+            }
+            Some(_) => Err(BackendError::new("circuit breaker is open")),
+        }
+    }
+
+    /// Counts a failed call to the wrapped backend, opening the circuit if it was the trial call or one too many.
+    fn fail(&self, circuit: &mut Circuit) {
+        circuit.failures = circuit.failures.saturating_add(1);
+        if circuit.probing || circuit.failures >= self.threshold {
+            circuit.opened_at = Some(self.clock.now());
+            circuit.probing = false;
+        }
+    }
+}
+
+/// A call admitted by a [`CircuitBreaker`], whose outcome must be recorded.
+///
+/// If the trial call of a half-open circuit is dropped before its outcome is recorded, e.g. because the future
+/// making it was cancelled by a timeout or a disconnecting client, it counts as failed, so the circuit does not
+/// wait forever for an outcome that never comes.
+struct Call<'a, B> {
+    breaker: &'a CircuitBreaker<B>,
+    probe: bool,
+}
+
+impl<B> Call<'_, B> {
+    /// Records the outcome of the call.
+    fn record<T>(mut self, result: Result<T, BackendError>) -> Result<T, BackendError> {
+        self.probe = false;
+        let mut circuit = self
+            .breaker
+            .circuit
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(_) => *circuit = Circuit::default(),
+            Err(_) => self.breaker.fail(&mut circuit),
+        }
+        result
+    }
+}
+
+impl<B> Drop for Call<'_, B> {
+    fn drop(&mut self) {
+        if self.probe {
+            let mut circuit = self
+                .breaker
+                .circuit
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            self.breaker.fail(&mut circuit);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, B> Backend<K> for CircuitBreaker<B>
+where
+    K: Sync,
+    B: Backend<K>,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let call = self.admit()?;
+        call.record(self.inner.acquire(key, count, per, cost).await)
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let call = self.admit()?;
+        call.record(self.inner.refund(key, count, per, cost).await)
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        // Flushing is attempted even while the circuit is open, as it is usually the last chance to persist state.
+        self.inner.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+    use http::Method;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// A backend failing while `down` is set and never answering while `hung` is set, counting the calls it
+    /// receives.
+    #[derive(Default)]
+    struct Flaky {
+        down: AtomicBool,
+        hung: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Backend<Method> for Flaky {
+        async fn acquire(
+            &self,
+            _key: &Method,
+            count: usize,
+            _per: u64,
+            _cost: usize,
+        ) -> Result<Decision, BackendError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.hung.load(Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+            if self.down.load(Ordering::SeqCst) {
+                return Err(BackendError::new("down"));
+            }
+            Ok(Decision {
+                allowed: true,
+                limit: count,
+                remaining: count,
+                reset: Duration::ZERO,
+            })
+        }
+    }
+
+    #[derive(Clone)]
+    struct Manual(Arc<Mutex<Instant>>);
+
+    impl Clock for Manual {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let flaky = Arc::new(Flaky::default());
+        let clock = Manual(Arc::new(Mutex::new(Instant::now())));
+        let breaker =
+            CircuitBreaker::new(flaky.clone(), 2, Duration::from_secs(5)).with_clock(clock.clone());
+        let call = || {
+            breaker
+                .acquire(&Method::GET, 1, 1_000, 1)
+                .now_or_never()
+                .expect("Backend is synchronous")
+                .is_ok()
+        };
+
+        flaky.down.store(true, Ordering::SeqCst);
+        assert!(!call());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(!call());
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // The backend is no longer called while the circuit is open.
+        flaky.down.store(false, Ordering::SeqCst);
+        assert!(!call());
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 2);
+
+        *clock.0.lock().unwrap_or_else(|e| e.into_inner()) += Duration::from_secs(5);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(call());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.failures(), 0);
+    }
+
+    #[test]
+    fn dropped_probes_count_as_failures() {
+        let flaky = Arc::new(Flaky::default());
+        let clock = Manual(Arc::new(Mutex::new(Instant::now())));
+        let breaker =
+            CircuitBreaker::new(flaky.clone(), 1, Duration::from_secs(5)).with_clock(clock.clone());
+        let call = || breaker.acquire(&Method::GET, 1, 1_000, 1).now_or_never();
+
+        flaky.down.store(true, Ordering::SeqCst);
+        assert!(call().is_some_and(|result| result.is_err()));
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // The probe hangs and is dropped, e.g. by a timeout, which reopens the circuit.
+        flaky.down.store(false, Ordering::SeqCst);
+        flaky.hung.store(true, Ordering::SeqCst);
+        *clock.0.lock().unwrap_or_else(|e| e.into_inner()) += Duration::from_secs(5);
+        assert!(call().is_none());
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.failures(), 2);
+
+        // The next probe after another cool-down closes the circuit.
+        flaky.hung.store(false, Ordering::SeqCst);
+        *clock.0.lock().unwrap_or_else(|e| e.into_inner()) += Duration::from_secs(5);
+        assert!(call().is_some_and(|result| result.is_ok()));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
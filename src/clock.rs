@@ -0,0 +1,20 @@
+use std::time::Instant;
+
+/// A source of the current time for rate limit decisions.
+///
+/// [`LimitState`](crate::LimitState) uses the [`SystemClock`] by default; tests can substitute a clock they
+/// control (see `MockClock` behind the `test-util` feature) to exercise limits without sleeping.
+pub trait Clock: Send + Sync {
+    /// Returns the current point in time.
+    fn now(&self) -> Instant;
+}
+
+/// The standard monotonic clock, backed by [`Instant::now`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
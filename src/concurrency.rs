@@ -0,0 +1,165 @@
+use crate::map::Map;
+use crate::{Key, LimitRejection, LimitState, RateLimitExceeded};
+use axum_core::extract::{FromRef, FromRequestParts};
+use http::request::Parts;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// A slot among the requests of one key in flight, given back when dropped.
+pub(crate) struct InFlight<K>
+where
+    K: Key,
+{
+    key: K,
+    counts: Arc<Map<K, usize>>,
+}
+
+impl<K> InFlight<K>
+where
+    K: Key,
+{
+    /// Takes one of `max` slots for `key` in `counts`, unless all of them are taken.
+    pub(crate) fn enter(counts: &Arc<Map<K, usize>>, key: K, max: usize) -> Option<Self> {
+        if max == 0 {
+            return None;
+        }
+        let mut count = counts.entry(key.clone()).or_insert(0);
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+        drop(count);
+        Some(Self {
+            key,
+            counts: counts.clone(),
+        })
+    }
+}
+
+impl<K> Drop for InFlight<K>
+where
+    K: Key,
+{
+    fn drop(&mut self) {
+        if let Some(mut count) = self.counts.get_mut(&self.key) {
+            *count = count.saturating_sub(1);
+        }
+        self.counts.remove_if(&self.key, |_, count| *count == 0);
+    }
+}
+
+/// Bounds the requests of a key in flight at the same time to `MAX`, complementing rate limits for slow endpoints.
+///
+/// A request takes a slot when it is extracted, and gives it back once the extractor is dropped, normally when the
+/// handler returns; move the extractor into a streaming response body to hold the slot until the response is sent.
+/// Requests finding all slots taken are rejected with `429 Too Many Requests`. Bans, fault injection and other hooks
+/// of the [`LimitState`] do not apply.
+pub struct ConcurrencyLimit<const MAX: usize, K>
+where
+    K: Key,
+{
+    /// The extracted key data.
+    pub extractor: K::Extractor,
+    _slot: InFlight<K>,
+}
+
+impl<const MAX: usize, K> ConcurrencyLimit<MAX, K>
+where
+    K: Key,
+{
+    /// Returns the number of requests of a key allowed in flight at the same time.
+    pub const fn max() -> usize {
+        MAX
+    }
+}
+
+impl<const MAX: usize, K> fmt::Debug for ConcurrencyLimit<MAX, K>
+where
+    K: Key,
+    K::Extractor: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrencyLimit")
+            .field("extractor", &self.extractor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const MAX: usize, K> Deref for ConcurrencyLimit<MAX, K>
+where
+    K: Key,
+{
+    type Target = K::Extractor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.extractor
+    }
+}
+
+impl<const MAX: usize, K> DerefMut for ConcurrencyLimit<MAX, K>
+where
+    K: Key,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.extractor
+    }
+}
+
+#[async_trait::async_trait]
+impl<const MAX: usize, K, S> FromRequestParts<S> for ConcurrencyLimit<MAX, K>
+where
+    LimitState<K>: FromRef<S>,
+    S: Send + Sync,
+    K: Key,
+    K::Extractor: FromRequestParts<S> + Send,
+{
+    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let extractor = match K::Extractor::from_request_parts(parts, state).await {
+            Ok(ke) => ke,
+            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
+        };
+
+        let limit_state: LimitState<K> = FromRef::from_ref(state);
+        let key = K::from_extractor(&extractor);
+        let formatted = limit_state.format_key(&key);
+        match limit_state.enter(key, MAX) {
+            Some(slot) => Ok(Self {
+                extractor,
+                _slot: slot,
+            }),
+            None => Err(LimitRejection::RateLimitExceeded(
+                RateLimitExceeded::exhausted(MAX)
+                    .for_key(formatted)
+                    .negotiated(limit_state.format(parts)),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request};
+
+    #[tokio::test]
+    async fn bounds_requests_in_flight() {
+        let state = LimitState::<Method>::default();
+        let extract = || {
+            let state = state.clone();
+            let (mut parts, _) = Request::new(()).into_parts();
+            async move { ConcurrencyLimit::<2, Method>::from_request_parts(&mut parts, &state).await }
+        };
+
+        let first = extract().await.expect("first slot is free");
+        let _second = extract().await.expect("second slot is free");
+        assert!(matches!(
+            extract().await,
+            Err(LimitRejection::RateLimitExceeded(_))
+        ));
+        drop(first);
+        assert!(extract().await.is_ok());
+    }
+}
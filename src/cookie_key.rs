@@ -0,0 +1,193 @@
+use crate::rejection::{render, Format};
+use crate::Key;
+use axum_core::extract::FromRequestParts;
+use axum_core::response::Response;
+use cookie::Cookie;
+use http::header::COOKIE;
+use http::request::Parts;
+use http::{HeaderMap, StatusCode};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// Names the cookie a [`CookieKey`] is extracted from, usually implemented with [`cookie_key!`](crate::cookie_key).
+pub trait KeyCookie: Send + Sync + 'static {
+    /// The name of the cookie.
+    const NAME: &'static str;
+
+    /// Builds the response to a request without the cookie, `400 Bad Request` by default.
+    fn missing() -> Response {
+        render(
+            Format::default(),
+            StatusCode::BAD_REQUEST,
+            HeaderMap::new(),
+            &format!("Missing the `{}` cookie.", Self::NAME),
+            None,
+        )
+    }
+}
+
+/// The value of the cookie named by `C` as a key, e.g. `Limit<100, 60_000, CookieKey<SessionCookie>>` to limit each
+/// browser session. Requests without the cookie are rejected with [`KeyCookie::missing`].
+pub struct CookieKey<C> {
+    value: String,
+    _cookie: PhantomData<fn() -> C>,
+}
+
+impl<C> CookieKey<C> {
+    /// Returns the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl<C> Debug for CookieKey<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CookieKey").field(&self.value).finish()
+    }
+}
+
+impl<C> Clone for CookieKey<C> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            _cookie: PhantomData,
+        }
+    }
+}
+
+impl<C> PartialEq for CookieKey<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<C> Eq for CookieKey<C> {}
+
+impl<C> Hash for CookieKey<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+#[async_trait::async_trait]
+impl<C, S> FromRequestParts<S> for CookieKey<C>
+where
+    C: KeyCookie,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(Cookie::split_parse)
+            .filter_map(Result::ok)
+            .find(|cookie| cookie.name() == C::NAME)
+            .map(|cookie| Self {
+                value: cookie.value().to_owned(),
+                _cookie: PhantomData,
+            })
+            .ok_or_else(C::missing)
+    }
+}
+
+impl<C> Key for CookieKey<C>
+where
+    C: KeyCookie,
+{
+    type Extractor = Self;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        extractor.clone()
+    }
+}
+
+/// Declares a marker type naming a cookie for [`CookieKey`], optionally with the response to requests without it.
+///
+/// ```
+/// use axum::{http::StatusCode, routing::get, Router};
+/// use axum_limit::{cookie_key, CookieKey, Limit, LimitState};
+///
+/// cookie_key! {
+///     /// The session cookie.
+///     pub SessionCookie = "session" => (StatusCode::UNAUTHORIZED, "Please sign in.");
+/// }
+///
+/// async fn handler(_: Limit<100, 60_000, CookieKey<SessionCookie>>) {}
+///
+/// let _app: Router<()> = Router::new()
+///     .route("/", get(handler))
+///     .with_state(LimitState::<CookieKey<SessionCookie>>::default());
+/// ```
+#[macro_export]
+macro_rules! cookie_key {
+    ($(#[$meta:meta])* $vis:vis $name:ident = $cookie:literal $(;)?) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default)]
+        $vis struct $name;
+
+        impl $crate::KeyCookie for $name {
+            const NAME: &'static str = $cookie;
+        }
+    };
+    ($(#[$meta:meta])* $vis:vis $name:ident = $cookie:literal => $missing:expr $(;)?) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default)]
+        $vis struct $name;
+
+        impl $crate::KeyCookie for $name {
+            const NAME: &'static str = $cookie;
+
+            fn missing() -> $crate::__private::Response {
+                $crate::__private::IntoResponse::into_response($missing)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+
+    crate::cookie_key!(Session = "session");
+
+    #[tokio::test]
+    async fn limits_each_cookie_value() {
+        async fn handler(Limit(session): Limit<1, 60_000, CookieKey<Session>>) -> String {
+            session.value().to_owned()
+        }
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<CookieKey<Session>>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server
+            .get("/")
+            .add_header("cookie", "theme=dark; session=a")
+            .await;
+        assert_eq!(response.text(), "a");
+        server.get("/").add_header("cookie", "session=b").await;
+        server
+            .get("/")
+            .add_header("cookie", "session=a")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+        server
+            .get("/")
+            .add_header("cookie", "theme=dark")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,102 @@
+//! Rate limiting without axum.
+//!
+//! The token buckets behind the extractors can be used on their own, e.g. to apply the same policies in
+//! background jobs, gRPC services or command line tools. Nothing in this module depends on axum or HTTP types:
+//!
+//! ```
+//! use axum_limit::core::RateLimiter;
+//!
+//! let limiter = RateLimiter::new(2, 1000);
+//! assert!(limiter.check(&"job-42"));
+//! assert!(limiter.check(&"job-42"));
+//! assert!(!limiter.check(&"job-42"));
+//! assert!(limiter.check(&"job-43"));
+//! ```
+
+pub use crate::{Clock, Decision, SystemClock};
+
+use crate::map::Map;
+use crate::TokenBucket;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A limit of `count` requests per `per` milliseconds for each key, enforced by in-memory token buckets.
+/// Clones share the same buckets.
+#[derive(Clone)]
+pub struct RateLimiter<K> {
+    count: usize,
+    per: u64,
+    buckets: Arc<Map<K, TokenBucket>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K> RateLimiter<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs a new `RateLimiter` allowing `count` requests per `per` milliseconds for each key.
+    pub fn new(count: usize, per: u64) -> Self {
+        Self {
+            count,
+            per,
+            buckets: Arc::new(Map::new()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replaces the clock used to refill buckets.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Checks and updates the limit of `key`, returning `true` if the request can proceed.
+    pub fn check(&self, key: &K) -> bool {
+        self.decide(key, 1).allowed
+    }
+
+    /// Attempts to take `cost` tokens for `key` at once, reporting the state of its bucket afterwards. Either all or
+    /// none of the tokens are taken.
+    pub fn decide(&self, key: &K, cost: usize) -> Decision {
+        let now = self.clock.now();
+        // Look up existing buckets by reference so the key is only cloned for new buckets.
+        match self.buckets.get_mut(key) {
+            Some(mut bucket) => bucket.decide_at(cost, now),
+            None => self
+                .buckets
+                .entry(key.clone())
+                .or_insert_with(|| TokenBucket::new(self.count, self.per, now))
+                .decide_at(cost, now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone)]
+    struct Manual(Arc<Mutex<Instant>>);
+
+    impl Clock for Manual {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    #[test]
+    fn refills_after_period() {
+        let clock = Manual(Arc::new(Mutex::new(Instant::now())));
+        let limiter = RateLimiter::new(3, 1_000).with_clock(clock.clone());
+
+        assert!(limiter.decide(&1, 2).allowed);
+        let decision = limiter.decide(&1, 2);
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 1);
+
+        *clock.0.lock().unwrap_or_else(|e| e.into_inner()) += Duration::from_secs(1);
+        assert!(limiter.decide(&1, 3).allowed);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::upload::Charge;
+use crate::BoxFuture;
+use http::{Request, Response};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The true cost of a request in tokens, reported by the handler as a response extension to
+/// [`PostChargeLayer`], e.g. the number of rows scanned or kilobytes generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestCost(pub usize);
+
+/// Shared slot through which `Limit` extractors hand the layer a way to charge their key after the response.
+#[derive(Clone, Default)]
+pub(crate) struct ChargeSlot(Arc<Mutex<Vec<Charge>>>);
+
+impl ChargeSlot {
+    pub(crate) fn record(&self, charge: Charge) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(charge);
+    }
+
+    fn take(&self) -> Vec<Charge> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// A layer that charges the keys of a request for its actual cost once the handler has run.
+///
+/// Admitting a request takes a single token. If the response carries a [`RequestCost`] extension, the remaining
+/// cost is charged against the keys of all limits the request passed, reducing their budget for subsequent
+/// requests. A key that cannot afford the full cost has its bucket drained.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostChargeLayer;
+
+impl PostChargeLayer {
+    /// Constructs a new `PostChargeLayer`.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for PostChargeLayer {
+    type Service = PostCharge<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PostCharge { inner }
+    }
+}
+
+/// Middleware that charges requests for their reported cost. See [`PostChargeLayer`].
+#[derive(Debug, Clone, Copy)]
+pub struct PostCharge<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for PostCharge<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let slot = ChargeSlot::default();
+        req.extensions_mut().insert(slot.clone());
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await?;
+            let cost = response
+                .extensions()
+                .get::<RequestCost>()
+                .map_or(0, |c| c.0);
+            let extra = cost.saturating_sub(1);
+            if extra > 0 {
+                for charge in slot.take() {
+                    if let Some(decision) = charge(extra).await {
+                        if !decision.allowed && decision.remaining > 0 {
+                            charge(decision.remaining).await;
+                        }
+                    }
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use axum_test::TestServer;
+    use http::{Method, StatusCode};
+
+    #[tokio::test]
+    async fn charges_reported_cost() {
+        async fn handler(_: Limit<5, 60_000, Method>) -> (Extension<RequestCost>, &'static str) {
+            (Extension(RequestCost(4)), "expensive")
+        }
+
+        let my_app = Router::new()
+            .route("/report", get(handler))
+            .layer(PostChargeLayer::new())
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/report").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/report").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/report").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::map::Map;
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The replicated state of one bucket's counter for one window, as exchanged between [`CrdtBackend`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CounterState {
+    /// The identifier of the bucket.
+    pub bucket: String,
+    /// The period of the bucket, in milliseconds.
+    pub per: u64,
+    /// The index of the window since the Unix epoch, in periods.
+    pub window: u64,
+    /// The tokens taken within the window, by replica.
+    pub counts: HashMap<String, u64>,
+}
+
+/// A grow-only counter of the tokens each replica took from a bucket within one window.
+#[derive(Debug, Clone)]
+struct Counter {
+    per: u64,
+    window: u64,
+    counts: HashMap<String, u64>,
+}
+
+impl Counter {
+    /// Merges `other` into the counter: later windows replace earlier ones, and within the same window every
+    /// replica's count is the highest one seen. Merging is commutative, associative and idempotent, so replicas
+    /// converge regardless of the order and number of times states are delivered.
+    fn merge(&mut self, other: &CounterState) {
+        if other.window > self.window {
+            self.window = other.window;
+            self.counts.clone_from(&other.counts);
+        } else if other.window == self.window {
+            for (replica, &count) in &other.counts {
+                let own = self.counts.entry(replica.clone()).or_insert(0);
+                *own = (*own).max(count);
+            }
+        }
+    }
+}
+
+/// Returns the index of the window of `per` milliseconds containing the wall-clock time `now`.
+fn window_index(now: u64, per: u64) -> u64 {
+    now.checked_div(per).unwrap_or(now)
+}
+
+/// Returns the current wall-clock time in milliseconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// An eventually consistent [`Backend`] for multi-region deployments, enforcing generous global quotas, e.g. per day,
+/// without a single point of coordination.
+///
+/// Limits are counted in fixed windows of their period by G-counters: every replica only increments its own
+/// count, and decides against the sum of all counts it knows of. Replicas exchange their state over any
+/// replication channel, such as a message queue, by sending what [`export`](Self::export) returns to the other
+/// replicas, which [`merge`](Self::merge) it. Until states are delivered, the limit can be exceeded by what other
+/// replicas took in the meantime. Counters only grow, so refunds do nothing. Like the
+/// [`RedisBackend`](crate::RedisBackend), buckets are identified by the [`KeyHasher`] identifier of their key, so
+/// all replicas must share the same hasher.
+/// Clones share the same counters.
+#[derive(Clone)]
+pub struct CrdtBackend {
+    replica: Arc<str>,
+    hasher: KeyHasher,
+    counters: Arc<Map<String, Counter>>,
+}
+
+impl CrdtBackend {
+    /// Constructs a new `CrdtBackend` for the replica named `replica`, which must be unique among all replicas,
+    /// identifying keys with `hasher`.
+    pub fn new(replica: impl Into<String>, hasher: KeyHasher) -> Self {
+        Self {
+            replica: replica.into().into(),
+            hasher,
+            counters: Arc::new(Map::new()),
+        }
+    }
+
+    /// Returns the state of all counters of current windows, to be sent to the other replicas. Counters of past
+    /// windows are forgotten.
+    pub fn export(&self) -> Vec<CounterState> {
+        let now = now();
+        let buckets: Vec<String> = self
+            .counters
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        for bucket in buckets {
+            self.counters.remove_if(&bucket, |_, counter| {
+                counter.window < window_index(now, counter.per)
+            });
+        }
+        self.counters
+            .iter()
+            .map(|entry| CounterState {
+                bucket: entry.key().clone(),
+                per: entry.value().per,
+                window: entry.value().window,
+                counts: entry.value().counts.clone(),
+            })
+            .collect()
+    }
+
+    /// Merges the counter states received from another replica.
+    pub fn merge(&self, states: impl IntoIterator<Item = CounterState>) {
+        for state in states {
+            let mut counter = self
+                .counters
+                .entry(state.bucket.clone())
+                .or_insert_with(|| Counter {
+                    per: state.per,
+                    window: state.window,
+                    counts: HashMap::new(),
+                });
+            counter.merge(&state);
+        }
+    }
+
+    /// Returns the identifier of the bucket of `count` tokens per `per` milliseconds for `key`.
+    fn bucket_id<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!("{}:{count}:{per}", self.hasher.identify(key))
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for CrdtBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let now = now();
+        let window = window_index(now, per);
+        let mut counter = self
+            .counters
+            .entry(self.bucket_id(key, count, per))
+            .or_insert_with(|| Counter {
+                per,
+                window,
+                counts: HashMap::new(),
+            });
+        if counter.window < window {
+            counter.window = window;
+            counter.counts.clear();
+        }
+
+        let used = counter
+            .counts
+            .values()
+            .copied()
+            .fold(0, u64::saturating_add);
+        let allowed = used.saturating_add(cost as u64) <= count as u64;
+        let used = if allowed {
+            let own = counter.counts.entry(self.replica.to_string()).or_insert(0);
+            *own = own.saturating_add(cost as u64);
+            used.saturating_add(cost as u64)
+        } else {
+            used
+        };
+        Ok(Decision {
+            allowed,
+            limit: count,
+            remaining: (count as u64).saturating_sub(used) as usize,
+            reset: Duration::from_millis(per.saturating_sub(now.checked_rem(per).unwrap_or(0))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    #[test]
+    fn replicas_converge() {
+        let a = CrdtBackend::new("eu", KeyHasher::new(5));
+        let b = CrdtBackend::new("us", KeyHasher::new(5));
+        let allowed = |backend: &CrdtBackend| {
+            backend
+                .acquire(&"alice", 3, 86_400_000, 1)
+                .now_or_never()
+                .and_then(Result::ok)
+                .is_some_and(|decision| decision.allowed)
+        };
+
+        assert!(allowed(&a));
+        assert!(allowed(&a));
+        assert!(allowed(&b));
+        // Delivering a state twice, in either direction, does not count tokens twice.
+        b.merge(a.export());
+        b.merge(a.export());
+        a.merge(b.export());
+
+        assert!(!allowed(&a));
+        assert!(!allowed(&b));
+        assert_eq!(a.export(), b.export());
+    }
+}
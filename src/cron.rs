@@ -0,0 +1,311 @@
+use crate::Decision;
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// An error parsing a [`CronSchedule`], naming the offending part of the expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCron(String);
+
+impl Display for InvalidCron {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid cron expression: {}", self.0)
+    }
+}
+
+impl Error for InvalidCron {}
+
+/// A schedule of quota resets in the five-field cron syntax: minute, hour, day of month, month and day of week,
+/// e.g. `"0 0 * * MON"` for every Monday at midnight, or `"0 0 1 * *"` for the first day of every month.
+///
+/// Fields accept `*`, values, ranges such as `1-5`, steps such as `*/15` or `0-30/10`, and comma-separated lists of
+/// them. Months and days of week may also be given by their English three-letter names, and Sunday as either `0` or
+/// `7`. As usual for cron, a time matches if either the day of month or the day of week matches when both are
+/// restricted. Times are in UTC unless shifted with [`utc_offset`](Self::utc_offset).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+    offset: i64,
+}
+
+impl FromStr for CronSchedule {
+    type Err = InvalidCron;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(InvalidCron(format!(
+                "expected 5 fields, found {}",
+                fields.len()
+            )));
+        };
+        // Sunday is both 0 and 7.
+        let weekday_bits = field(weekdays, 0, 7, &WEEKDAYS, 0)?;
+        Ok(Self {
+            minutes: field(minutes, 0, 59, &[], 0)?,
+            hours: field(hours, 0, 23, &[], 0)?,
+            days: field(days, 1, 31, &[], 1)?,
+            months: field(months, 1, 12, &MONTHS, 1)?,
+            weekdays: (weekday_bits | weekday_bits >> 7) & 0x7f,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+            offset: 0,
+        })
+    }
+}
+
+/// Parses one field of a cron expression into a bit set of the values it matches, resolving `names` as values
+/// counted from `first_name`.
+fn field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<u64, InvalidCron> {
+    let value = |part: &str| -> Result<u32, InvalidCron> {
+        let upper = part.to_ascii_uppercase();
+        let value = match names.iter().position(|name| *name == upper) {
+            Some(index) => index as u32 + first_name,
+            None => part
+                .parse()
+                .map_err(|_| InvalidCron(format!("`{part}` is not a value")))?,
+        };
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(InvalidCron(format!("{value} is not within {min}-{max}")))
+        }
+    };
+
+    let mut bits = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(InvalidCron(format!("`{step}` is not a step"))),
+            },
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(InvalidCron(format!("`{range}` is an empty range")));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// Returns the year, month and day of the civil date `days` days after the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Returns the time `millis` milliseconds after the Unix epoch.
+pub(crate) fn system_time(millis: i128) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_millis(u64::try_from(millis).ok()?))
+}
+
+impl CronSchedule {
+    /// Constructs a `CronSchedule` firing at midnight on the first day of every month, `"0 0 1 * *"`.
+    pub fn monthly() -> Self {
+        Self {
+            minutes: 1,
+            hours: 1,
+            days: 1 << 1,
+            months: 0x1ffe,
+            weekdays: 0x7f,
+            any_day: false,
+            any_weekday: true,
+            offset: 0,
+        }
+    }
+
+    /// Shifts the schedule by a fixed offset from UTC of `minutes`, e.g. `-300` for US Eastern Standard Time.
+    /// Daylight saving time is not followed.
+    pub fn utc_offset(mut self, minutes: i32) -> Self {
+        self.offset = i64::from(minutes);
+        self
+    }
+
+    /// Returns the first time after `time` matching the schedule, if any within the next eight years.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let millis = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_millis() as i128,
+            Err(before) => -(before.duration().as_millis() as i128),
+        };
+        system_time(self.next_after_millis(millis)?)
+    }
+
+    /// Returns the first time after `millis` milliseconds since the Unix epoch matching the schedule, in the same
+    /// unit.
+    pub(crate) fn next_after_millis(&self, millis: i128) -> Option<i128> {
+        let local = i64::try_from(millis.div_euclid(60_000)).ok()? + self.offset + 1;
+        let (first_day, first_minute) = (local.div_euclid(1_440), local.rem_euclid(1_440));
+        // Eight years cover every combination of a day of month and a day of week, such as a Friday 29 February.
+        for day in first_day..first_day + 8 * 366 {
+            if !self.matches_day(day) {
+                continue;
+            }
+            let start = if day == first_day { first_minute } else { 0 };
+            if let Some(minute) = (start..1_440).find(|&minute| self.matches_minute(minute)) {
+                return Some(i128::from(day * 1_440 + minute - self.offset) * 60_000);
+            }
+        }
+        None
+    }
+
+    /// Decides whether the minute containing `millis` milliseconds since the Unix epoch matches the schedule.
+    pub(crate) fn matches_millis(&self, millis: i128) -> bool {
+        let Ok(local) = i64::try_from(millis.div_euclid(60_000)) else {
+            return false;
+        };
+        let local = local + self.offset;
+        self.matches_day(local.div_euclid(1_440)) && self.matches_minute(local.rem_euclid(1_440))
+    }
+
+    /// Decides whether the local day `day` days after the Unix epoch matches the schedule.
+    fn matches_day(&self, day: i64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        let weekday = (day + 4).rem_euclid(7);
+        let day_matches = self.days & 1 << day_of_month != 0;
+        let weekday_matches = self.weekdays & 1 << weekday != 0;
+        let matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        };
+        self.months & 1 << month != 0 && matches
+    }
+
+    /// Decides whether the local minute `minute` of a day matches the schedule.
+    fn matches_minute(&self, minute: i64) -> bool {
+        self.hours & 1 << (minute / 60) != 0 && self.minutes & 1 << (minute % 60) != 0
+    }
+}
+
+/// The requests of one key counted since the last scheduled reset, for
+/// [`LimitState::with_reset_schedule`](crate::LimitState::with_reset_schedule).
+#[derive(Debug, Clone)]
+pub(crate) struct ScheduledCounter {
+    next_reset: Option<i128>,
+    used: usize,
+}
+
+impl ScheduledCounter {
+    /// Constructs a new `ScheduledCounter` counting `used` requests until the next reset of `schedule` after `now`.
+    pub(crate) fn new(schedule: &CronSchedule, used: usize, now: Instant) -> Self {
+        Self {
+            next_reset: schedule.next_after_millis(crate::algorithm::unix_millis(now)),
+            used,
+        }
+    }
+
+    /// Resets the count if a scheduled reset passed by `now`, returning the milliseconds until the next one.
+    fn advance(&mut self, schedule: &CronSchedule, now: Instant) -> u64 {
+        let millis = crate::algorithm::unix_millis(now);
+        if self.next_reset.is_some_and(|next| next <= millis) {
+            self.used = 0;
+            self.next_reset = schedule.next_after_millis(millis);
+        }
+        self.next_reset
+            .map_or(0, |next| u64::try_from(next - millis).unwrap_or(u64::MAX))
+    }
+
+    /// Attempts to count `cost` requests at once against a limit of `count` until the next reset, reporting the
+    /// state of the counter afterwards. Either all or none of them are counted.
+    pub(crate) fn decide_at(
+        &mut self,
+        schedule: &CronSchedule,
+        count: usize,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        let reset = self.advance(schedule, now);
+        let allowed = self.used.saturating_add(cost) <= count;
+        if allowed {
+            self.used += cost;
+        }
+        Decision {
+            allowed,
+            limit: count,
+            remaining: count.saturating_sub(self.used),
+            reset: Duration::from_millis(reset),
+        }
+    }
+
+    /// Uncounts `cost` previously counted requests, if they were counted since the last reset.
+    pub(crate) fn refund(&mut self, schedule: &CronSchedule, cost: usize, now: Instant) {
+        self.advance(schedule, now);
+        self.used = self.used.saturating_sub(cost);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_next_reset() {
+        // Thursday, 1 January 1970, 00:00 UTC.
+        let at = |days: i128, minutes: i128| (days * 1_440 + minutes) * 60_000;
+        let weekly: CronSchedule = "0 0 * * MON".parse().expect("valid expression");
+        assert_eq!(weekly.next_after_millis(at(0, 0)), Some(at(4, 0)));
+        assert_eq!(weekly.next_after_millis(at(4, 0)), Some(at(11, 0)));
+
+        let monthly: CronSchedule = "0 0 1 * *".parse().expect("valid expression");
+        assert_eq!(monthly, CronSchedule::monthly());
+        assert_eq!(monthly.next_after_millis(at(0, 0)), Some(at(31, 0)));
+
+        let quarter_hours: CronSchedule = "*/15 9-17 * * 1-5".parse().expect("valid expression");
+        assert_eq!(
+            quarter_hours.next_after_millis(at(0, 9 * 60 + 1)),
+            Some(at(0, 9 * 60 + 15))
+        );
+        assert_eq!(
+            quarter_hours.next_after_millis(at(1, 17 * 60 + 45)),
+            Some(at(4, 9 * 60))
+        );
+
+        let tokyo = weekly.clone().utc_offset(540);
+        assert_eq!(tokyo.next_after_millis(at(0, 0)), Some(at(3, 15 * 60)));
+        assert!(tokyo.matches_millis(at(3, 15 * 60) + 59_999));
+        assert!(!tokyo.matches_millis(at(3, 15 * 60 + 1)));
+
+        assert!("0 0 * *".parse::<CronSchedule>().is_err());
+        assert!("61 0 * * *".parse::<CronSchedule>().is_err());
+    }
+}
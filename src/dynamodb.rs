@@ -0,0 +1,334 @@
+use crate::backend::StoredBucket;
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// An item of the table, by attribute name.
+type Item = HashMap<String, AttributeValue>;
+
+/// The DynamoDB requests buckets are updated with, implemented by the AWS SDK client.
+#[async_trait::async_trait]
+trait Store: Send + Sync {
+    /// Reads the item with the partition key `key` from `table` with a strongly consistent read.
+    async fn get(&self, table: &str, key: &str) -> Result<Option<Item>, BackendError>;
+
+    /// Writes `item` to `table` if the stored item's `version` is `version`, or no item exists for `None`.
+    /// Returns `false` if the condition failed.
+    async fn put(
+        &self,
+        table: &str,
+        item: Item,
+        version: Option<u64>,
+    ) -> Result<bool, BackendError>;
+}
+
+#[async_trait::async_trait]
+impl Store for Client {
+    async fn get(&self, table: &str, key: &str) -> Result<Option<Item>, BackendError> {
+        let stored = self
+            .get_item()
+            .table_name(table)
+            .key("pk", AttributeValue::S(key.to_string()))
+            .consistent_read(true)
+            .send()
+            .await
+            .map_err(BackendError::new)?;
+        Ok(stored.item)
+    }
+
+    async fn put(
+        &self,
+        table: &str,
+        item: Item,
+        version: Option<u64>,
+    ) -> Result<bool, BackendError> {
+        let put = self.put_item().table_name(table).set_item(Some(item));
+        let put = match version {
+            Some(version) => put
+                .condition_expression("version = :version")
+                .expression_attribute_values(":version", AttributeValue::N(version.to_string())),
+            None => put.condition_expression("attribute_not_exists(pk)"),
+        };
+        match put.send().await {
+            Ok(_) => Ok(true),
+            Err(error)
+                if error
+                    .as_service_error()
+                    .is_some_and(|error| error.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(error) => Err(BackendError::new(error)),
+        }
+    }
+}
+
+/// A [`Backend`] keeping token buckets in an AWS DynamoDB table, so serverless and ECS deployments can share limits
+/// without managing a cache cluster.
+///
+/// The table needs a string partition key named `pk`. Each bucket is an item holding its `tokens`, the time of its
+/// `last_refill` in Unix milliseconds and a `version`, updated with conditional writes: a concurrent update by
+/// another instance makes the update start over from the new state, up to a configurable number of
+/// [`attempts`](Self::attempts). Items also carry an `expires_at` attribute in Unix seconds, at which the bucket
+/// would be full again; enable time to live on it so DynamoDB removes idle buckets. Like the
+/// [`RedisBackend`](crate::RedisBackend), keys are stored by their [`KeyHasher`] identifier, e.g.
+/// `axum-limit:v1:5f3a…:10:1000`.
+#[derive(Clone)]
+pub struct DynamoDbBackend {
+    client: Arc<dyn Store>,
+    table: String,
+    hasher: KeyHasher,
+    prefix: String,
+    attempts: usize,
+}
+
+impl DynamoDbBackend {
+    /// Constructs a new `DynamoDbBackend` storing buckets in `table` through `client`, identifying keys with
+    /// `hasher`.
+    pub fn new(client: Client, table: impl Into<String>, hasher: KeyHasher) -> Self {
+        Self::with_store(Arc::new(client), table, hasher)
+    }
+
+    /// Constructs a new `DynamoDbBackend` storing buckets in `table` through `client`.
+    fn with_store(client: Arc<dyn Store>, table: impl Into<String>, hasher: KeyHasher) -> Self {
+        Self {
+            client,
+            table: table.into(),
+            hasher,
+            prefix: "axum-limit".to_string(),
+            attempts: 8,
+        }
+    }
+
+    /// Replaces the prefix of all bucket keys, `axum-limit` by default.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets how many times an update is attempted before giving up on contention, 8 by default.
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Returns the partition key of the bucket of `count` tokens per `per` milliseconds for `key`.
+    fn bucket_key<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!(
+            "{}:{}:{count}:{per}",
+            self.prefix,
+            self.hasher.identify(key)
+        )
+    }
+
+    /// Applies `update` to the stored bucket with a conditional write, returning its result once the bucket was
+    /// stored, or `None` if the bucket does not exist and `create` is `false`.
+    async fn update<T>(
+        &self,
+        key: &str,
+        count: usize,
+        per: u64,
+        create: bool,
+        update: impl Fn(&mut StoredBucket, u64) -> T,
+    ) -> Result<Option<T>, BackendError> {
+        for _ in 0..self.attempts {
+            let stored = self.client.get(&self.table, key).await?;
+            let now = StoredBucket::now();
+            let (mut bucket, version) = match &stored {
+                Some(item) => (
+                    StoredBucket {
+                        tokens: number(item, "tokens").unwrap_or(count as u64),
+                        last_refill: number(item, "last_refill").unwrap_or(now),
+                    },
+                    number(item, "version"),
+                ),
+                None if create => (StoredBucket::full(count, now), None),
+                None => return Ok(None),
+            };
+            let result = update(&mut bucket, now);
+
+            // Buckets are full again once a period has passed since their last refill.
+            let expires_at = bucket.last_refill.saturating_add(per).div_ceil(1000);
+            let item = HashMap::from([
+                ("pk".to_string(), AttributeValue::S(key.to_string())),
+                (
+                    "tokens".to_string(),
+                    AttributeValue::N(bucket.tokens.to_string()),
+                ),
+                (
+                    "last_refill".to_string(),
+                    AttributeValue::N(bucket.last_refill.to_string()),
+                ),
+                (
+                    "version".to_string(),
+                    AttributeValue::N(version.map_or(1, |version| version + 1).to_string()),
+                ),
+                (
+                    "expires_at".to_string(),
+                    AttributeValue::N(expires_at.to_string()),
+                ),
+            ]);
+            if self.client.put(&self.table, item, version).await? {
+                return Ok(Some(result));
+            }
+        }
+        Err(BackendError::new(format!(
+            "bucket {key} is contended, gave up after {} attempts",
+            self.attempts
+        )))
+    }
+}
+
+/// Reads the numeric attribute `name` of `item`.
+fn number(item: &Item, name: &str) -> Option<u64> {
+    item.get(name)?.as_n().ok()?.parse().ok()
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for DynamoDbBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(&key, count, per, true, |bucket, now| {
+            bucket.decide(count, per, cost, now)
+        })
+        .await?
+        .ok_or_else(|| BackendError::new("DynamoDB bucket disappeared"))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(&key, count, per, false, |bucket, now| {
+            bucket.refund(count, per, cost, now)
+        })
+        .await
+        .map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// An in-memory DynamoDB table, letting another instance update an item between the next reads and writes.
+    #[derive(Default)]
+    struct Fake {
+        items: Mutex<HashMap<String, Item>>,
+        /// The number of conditional writes that fail against a concurrent update.
+        contended: Mutex<usize>,
+        /// The number of writes attempted.
+        writes: Mutex<usize>,
+    }
+
+    impl Fake {
+        fn item(&self, key: &str) -> Option<Item> {
+            self.items
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(key)
+                .cloned()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Store for Fake {
+        async fn get(&self, _table: &str, key: &str) -> Result<Option<Item>, BackendError> {
+            Ok(self.item(key))
+        }
+
+        async fn put(
+            &self,
+            _table: &str,
+            item: Item,
+            version: Option<u64>,
+        ) -> Result<bool, BackendError> {
+            *self.writes.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+            let mut items = self.items.lock().unwrap_or_else(|e| e.into_inner());
+            let key = item["pk"].as_s().expect("string partition key").clone();
+            let mut contended = self.contended.lock().unwrap_or_else(|e| e.into_inner());
+            if *contended > 0 {
+                *contended -= 1;
+                return Ok(false);
+            }
+            if items.get(&key).and_then(|stored| number(stored, "version")) != version {
+                return Ok(false);
+            }
+            items.insert(key, item);
+            Ok(true)
+        }
+    }
+
+    fn backend(fake: &Arc<Fake>) -> DynamoDbBackend {
+        DynamoDbBackend::with_store(fake.clone(), "limits", KeyHasher::new(7))
+    }
+
+    #[tokio::test]
+    async fn retries_failed_conditions() {
+        let fake = Arc::new(Fake::default());
+        let backend = backend(&fake);
+
+        *fake.contended.lock().unwrap_or_else(|e| e.into_inner()) = 2;
+        let decision = Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .expect("the write succeeds");
+        assert_eq!(decision.remaining, 9);
+        assert_eq!(*fake.writes.lock().unwrap_or_else(|e| e.into_inner()), 3);
+
+        let decision = Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .expect("the write succeeds");
+        assert_eq!(decision.remaining, 8);
+        let key = backend.bucket_key("alice", 10, 60_000);
+        let item = fake.item(&key).expect("the bucket is stored");
+        assert_eq!(number(&item, "version"), Some(2));
+
+        *fake.contended.lock().unwrap_or_else(|e| e.into_inner()) = usize::MAX;
+        let backend = backend.attempts(2);
+        assert!(Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .is_err());
+        let item = fake.item(&key).expect("the bucket is stored");
+        assert_eq!(number(&item, "tokens"), Some(8));
+    }
+
+    #[tokio::test]
+    async fn expires_buckets_once_full() {
+        let fake = Arc::new(Fake::default());
+        let backend = backend(&fake);
+
+        Backend::acquire(&backend, &"alice", 10, 90_500, 1)
+            .await
+            .expect("the write succeeds");
+        let key = backend.bucket_key("alice", 10, 90_500);
+        let item = fake.item(&key).expect("the bucket is stored");
+        let last_refill = number(&item, "last_refill").expect("the refill is stored");
+        assert_eq!(
+            number(&item, "expires_at"),
+            Some((last_refill + 90_500).div_ceil(1000))
+        );
+
+        // Refunds never create buckets.
+        Backend::refund(&backend, &"bob", 10, 1_000, 1)
+            .await
+            .expect("the refund succeeds");
+        assert!(fake.item(&backend.bucket_key("bob", 10, 1_000)).is_none());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::upload::{charger, BoxError, Charge};
+use crate::{BoxFuture, Decision, Key, LimitState};
+use axum_core::body::Body;
+use axum_core::extract::FromRequestParts;
+use axum_core::response::{IntoResponse, Response};
+use bytes::Bytes;
+use http::Request;
+use http_body::{Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::Sleep;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// A layer that paces response bodies so each key receives at most a fixed number of bytes per second,
+/// e.g. to cap the egress of download endpoints per customer rather than their number of requests.
+///
+/// The key is extracted from the request parts like for [`Limit`](crate::Limit), so its extractor must not depend
+/// on the router state. Bytes are charged as tokens of `state`, so it should not be shared with request limits.
+/// Chunks larger than the per-second budget are split, and once the budget is used up, the body waits for the
+/// bucket to refill instead of failing.
+pub struct EgressLimitLayer<K>
+where
+    K: Key,
+{
+    state: LimitState<K>,
+    bytes_per_second: usize,
+}
+
+impl<K> Clone for EgressLimitLayer<K>
+where
+    K: Key,
+{
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            bytes_per_second: self.bytes_per_second,
+        }
+    }
+}
+
+impl<K> EgressLimitLayer<K>
+where
+    K: Key,
+{
+    /// Constructs a new `EgressLimitLayer` sending at most `bytes_per_second` bytes per second to each key.
+    pub fn new(state: LimitState<K>, bytes_per_second: usize) -> Self {
+        Self {
+            state,
+            bytes_per_second: bytes_per_second.max(1),
+        }
+    }
+}
+
+impl<S, K> Layer<S> for EgressLimitLayer<K>
+where
+    K: Key,
+{
+    type Service = EgressLimit<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EgressLimit {
+            inner,
+            config: self.clone(),
+            _key: PhantomData,
+        }
+    }
+}
+
+/// Middleware that paces response bodies per key. See [`EgressLimitLayer`].
+pub struct EgressLimit<S, K>
+where
+    K: Key,
+{
+    inner: S,
+    config: EgressLimitLayer<K>,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<S, K> Clone for EgressLimit<S, K>
+where
+    S: Clone,
+    K: Key,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<S, K> Service<Request<Body>> for EgressLimit<S, K>
+where
+    S: Service<Request<Body>> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send,
+    K: Key + 'static,
+    K::Extractor: FromRequestParts<()> + Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Take the service that was driven to readiness, leaving a fresh clone behind.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let key_extractor = match K::Extractor::from_request_parts(&mut parts, &()).await {
+                Ok(ke) => ke,
+                Err(rejection) => return Ok(rejection.into_response()),
+            };
+            let key = K::from_extractor(&key_extractor);
+
+            let response = inner
+                .call(Request::from_parts(parts, body))
+                .await?
+                .into_response();
+            let charge = charger(config.state, key, config.bytes_per_second, 1000);
+            Ok(response
+                .map(|body| Body::new(PacedBody::new(body, charge, config.bytes_per_second))))
+        })
+    }
+}
+
+pin_project! {
+    /// A response body sending at most a fixed number of bytes per second. See [`EgressLimitLayer`].
+    pub struct PacedBody<B> {
+        #[pin]
+        inner: B,
+        charge: Charge,
+        chunk_size: usize,
+        buffered: Option<Bytes>,
+        pending: Option<(usize, BoxFuture<Option<Decision>>)>,
+        sleep: Option<Pin<Box<Sleep>>>,
+    }
+}
+
+impl<B> PacedBody<B> {
+    fn new(inner: B, charge: Charge, chunk_size: usize) -> Self {
+        Self {
+            inner,
+            charge,
+            chunk_size,
+            buffered: None,
+            pending: None,
+            sleep: None,
+        }
+    }
+}
+
+impl<B> http_body::Body for PacedBody<B>
+where
+    B: http_body::Body<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            if let Some(sleep) = this.sleep.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+                *this.sleep = None;
+            }
+
+            if let Some((size, mut charge)) = this.pending.take() {
+                let Poll::Ready(decision) = charge.as_mut().poll(cx) else {
+                    *this.pending = Some((size, charge));
+                    return Poll::Pending;
+                };
+                match decision {
+                    // Wait for the bucket to refill, then charge the same piece again.
+                    Some(decision) if !decision.allowed => {
+                        let wait = decision.reset.max(Duration::from_millis(1));
+                        *this.sleep = Some(Box::pin(tokio::time::sleep(wait)));
+                    }
+                    _ => {
+                        let Some(buffered) = this.buffered.as_mut() else {
+                            continue;
+                        };
+                        let piece = buffered.split_to(size);
+                        if buffered.is_empty() {
+                            *this.buffered = None;
+                        }
+                        return Poll::Ready(Some(Ok(Frame::data(piece))));
+                    }
+                }
+                continue;
+            }
+
+            if let Some(buffered) = this.buffered.as_ref() {
+                let size = buffered.len().min(*this.chunk_size);
+                *this.pending = Some((size, (this.charge)(size)));
+                continue;
+            }
+
+            let frame = match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            };
+            match frame.into_data() {
+                Ok(data) if !data.is_empty() => *this.buffered = Some(data),
+                Ok(data) => return Poll::Ready(Some(Ok(Frame::data(data)))),
+                Err(frame) => return Poll::Ready(Some(Ok(frame))),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.buffered.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self.buffered.as_ref().map_or(0, |b| b.len() as u64);
+        let inner = self.inner.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + buffered);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{StatusCode, Uri};
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn paces_downloads() {
+        async fn handler() -> String {
+            "a".repeat(1500)
+        }
+
+        let my_app = Router::new()
+            .route("/download", get(handler))
+            .layer(EgressLimitLayer::new(LimitState::<Uri>::default(), 1000));
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let start = Instant::now();
+        let response = server.get("/download").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text().len(), 1500);
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::backend::StoredBucket;
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use etcd_client::{Client, Compare, CompareOp, PutOptions, Txn, TxnOp};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The etcd requests buckets are updated with, implemented by the `etcd-client` client.
+#[async_trait::async_trait]
+trait Store: Send + Sync {
+    /// Returns the value stored at `key` and its modification revision, if any.
+    async fn get(&self, key: &str) -> Result<Option<(Vec<u8>, i64)>, etcd_client::Error>;
+
+    /// Grants a lease expiring after `ttl` seconds, returning its ID.
+    async fn grant(&self, ttl: i64) -> Result<i64, etcd_client::Error>;
+
+    /// Stores `value` at `key` attached to `lease` in a transaction, if the key was last modified at `revision`, or
+    /// does not exist for `None`. Returns whether the transaction succeeded.
+    async fn put_if(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        lease: i64,
+        revision: Option<i64>,
+    ) -> Result<bool, etcd_client::Error>;
+}
+
+#[async_trait::async_trait]
+impl Store for Client {
+    async fn get(&self, key: &str) -> Result<Option<(Vec<u8>, i64)>, etcd_client::Error> {
+        let stored = Client::get(&mut self.clone(), key, None).await?;
+        Ok(stored
+            .kvs()
+            .first()
+            .map(|kv| (kv.value().to_vec(), kv.mod_revision())))
+    }
+
+    async fn grant(&self, ttl: i64) -> Result<i64, etcd_client::Error> {
+        Ok(self.clone().lease_grant(ttl, None).await?.id())
+    }
+
+    async fn put_if(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        lease: i64,
+        revision: Option<i64>,
+    ) -> Result<bool, etcd_client::Error> {
+        let guard = match revision {
+            Some(revision) => Compare::mod_revision(key, CompareOp::Equal, revision),
+            None => Compare::create_revision(key, CompareOp::Equal, 0),
+        };
+        let put = TxnOp::put(key, value, Some(PutOptions::new().with_lease(lease)));
+        let txn = Txn::new().when([guard]).and_then([put]);
+        Ok(self.clone().txn(txn).await?.succeeded())
+    }
+}
+
+/// A [`Backend`] keeping token buckets in etcd, so all instances of an application share the same limits without
+/// running Redis, e.g. in Kubernetes clusters that already operate etcd.
+///
+/// Buckets are updated by transactions comparing the bucket's revision: a concurrent update by another instance
+/// makes the update start over from the new state, up to a configurable number of [`attempts`](Self::attempts).
+/// Every write attaches the bucket to a lease expiring once it would be full again, so idle keys are removed by
+/// etcd itself. Like the [`RedisBackend`](crate::RedisBackend), keys are stored by their [`KeyHasher`] identifier,
+/// e.g. `axum-limit/v1:5f3a…/10/1000`.
+#[derive(Clone)]
+pub struct EtcdBackend {
+    client: Arc<dyn Store>,
+    hasher: KeyHasher,
+    prefix: String,
+    attempts: usize,
+}
+
+impl EtcdBackend {
+    /// Constructs a new `EtcdBackend` storing buckets through `client`, identifying keys with `hasher`.
+    pub fn new(client: Client, hasher: KeyHasher) -> Self {
+        Self::with_store(Arc::new(client), hasher)
+    }
+
+    /// Constructs a new `EtcdBackend` storing buckets in `client`.
+    fn with_store(client: Arc<dyn Store>, hasher: KeyHasher) -> Self {
+        Self {
+            client,
+            hasher,
+            prefix: "axum-limit".to_string(),
+            attempts: 8,
+        }
+    }
+
+    /// Replaces the prefix of all bucket keys, `axum-limit` by default.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets how many times an update is attempted before giving up on contention, 8 by default.
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Returns the etcd key of the bucket of `count` tokens per `per` milliseconds for `key`.
+    fn bucket_key<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!(
+            "{}/{}/{count}/{per}",
+            self.prefix,
+            self.hasher.identify(key)
+        )
+    }
+
+    /// Applies `update` to the stored bucket in a transaction, returning its result once the bucket was stored, or
+    /// `None` if the bucket does not exist and `create` is `false`.
+    async fn update<T>(
+        &self,
+        key: &str,
+        count: usize,
+        per: u64,
+        create: bool,
+        update: impl Fn(&mut StoredBucket, u64) -> T,
+    ) -> Result<Option<T>, BackendError> {
+        // Buckets are full again once a period has passed since their last update.
+        let ttl = i64::try_from(per.div_ceil(1000).max(1)).unwrap_or(i64::MAX);
+
+        for _ in 0..self.attempts {
+            let stored = self.client.get(key).await.map_err(BackendError::new)?;
+            let now = StoredBucket::now();
+            let (mut bucket, revision) = match stored {
+                Some((bytes, revision)) => (
+                    StoredBucket::from_bytes(&bytes)
+                        .unwrap_or_else(|| StoredBucket::full(count, now)),
+                    Some(revision),
+                ),
+                None if create => (StoredBucket::full(count, now), None),
+                None => return Ok(None),
+            };
+            let result = update(&mut bucket, now);
+
+            let lease = self.client.grant(ttl).await.map_err(BackendError::new)?;
+            let value = bucket.to_bytes().to_vec();
+            if self
+                .client
+                .put_if(key, value, lease, revision)
+                .await
+                .map_err(BackendError::new)?
+            {
+                return Ok(Some(result));
+            }
+            // The unused lease expires on its own.
+        }
+        Err(BackendError::new(format!(
+            "bucket {key} is contended, gave up after {} attempts",
+            self.attempts
+        )))
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for EtcdBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(&key, count, per, true, |bucket, now| {
+            bucket.decide(count, per, cost, now)
+        })
+        .await?
+        .ok_or_else(|| BackendError::new("etcd bucket disappeared"))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(&key, count, per, false, |bucket, now| {
+            bucket.refund(count, per, cost, now)
+        })
+        .await
+        .map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// An in-memory etcd, letting another instance update a bucket between the next reads and writes.
+    #[derive(Default)]
+    struct Fake {
+        state: Mutex<FakeState>,
+    }
+
+    #[derive(Default)]
+    struct FakeState {
+        revision: i64,
+        /// The value, modification revision and lease of every key.
+        values: HashMap<String, (Vec<u8>, i64, i64)>,
+        /// The TTL of every lease granted.
+        leases: Vec<i64>,
+        /// The number of transactions that lose against a concurrent update.
+        contended: usize,
+    }
+
+    impl Fake {
+        fn state(&self) -> std::sync::MutexGuard<'_, FakeState> {
+            self.state.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Store for Fake {
+        async fn get(&self, key: &str) -> Result<Option<(Vec<u8>, i64)>, etcd_client::Error> {
+            let state = self.state();
+            Ok(state
+                .values
+                .get(key)
+                .map(|(value, revision, _)| (value.clone(), *revision)))
+        }
+
+        async fn grant(&self, ttl: i64) -> Result<i64, etcd_client::Error> {
+            let mut state = self.state();
+            state.leases.push(ttl);
+            Ok(state.leases.len() as i64)
+        }
+
+        async fn put_if(
+            &self,
+            key: &str,
+            value: Vec<u8>,
+            lease: i64,
+            revision: Option<i64>,
+        ) -> Result<bool, etcd_client::Error> {
+            let mut state = self.state();
+            if state.contended > 0 {
+                state.contended -= 1;
+                state.revision += 1;
+                let revision = state.revision;
+                if let Some(stored) = state.values.get_mut(key) {
+                    stored.1 = revision;
+                }
+            }
+            let current = state.values.get(key).map(|(_, revision, _)| *revision);
+            if current != revision {
+                return Ok(false);
+            }
+            state.revision += 1;
+            let revision = state.revision;
+            state
+                .values
+                .insert(key.to_string(), (value, revision, lease));
+            Ok(true)
+        }
+    }
+
+    fn backend(fake: &Arc<Fake>) -> EtcdBackend {
+        EtcdBackend::with_store(fake.clone(), KeyHasher::new(7))
+    }
+
+    #[tokio::test]
+    async fn retries_conflicting_transactions() {
+        let fake = Arc::new(Fake::default());
+        let backend = backend(&fake);
+
+        let decision = Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .expect("the transaction succeeds");
+        assert_eq!(decision.remaining, 9);
+
+        // Two transactions compare against a revision that another instance has moved on from.
+        fake.state().contended = 2;
+        let decision = Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .expect("the transaction succeeds");
+        assert_eq!(decision.remaining, 8);
+        assert_eq!(fake.state().leases.len(), 4);
+
+        fake.state().contended = usize::MAX;
+        let backend = backend.attempts(2);
+        assert!(Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn attaches_buckets_to_leases() {
+        let fake = Arc::new(Fake::default());
+        let backend = backend(&fake);
+
+        Backend::acquire(&backend, &"alice", 10, 90_500, 1)
+            .await
+            .expect("the transaction succeeds");
+        let key = backend.bucket_key("alice", 10, 90_500);
+        // Buckets expire once a period, rounded up to whole seconds, has passed.
+        assert_eq!(fake.state().leases, [91]);
+        assert_eq!(fake.state().values[&key].2, 1);
+
+        // Refunds never create buckets.
+        Backend::refund(&backend, &"bob", 10, 1_000, 1)
+            .await
+            .expect("the refund succeeds");
+        assert_eq!(fake.state().values.len(), 1);
+    }
+}
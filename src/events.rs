@@ -0,0 +1,98 @@
+use crate::Decision;
+use http::request::Parts;
+use http::HeaderName;
+
+type EventHandler<K> = Box<dyn Fn(&LimitEvent<'_, K>) + Send + Sync>;
+type RequestIdSource = Box<dyn Fn(&Parts) -> Option<String> + Send + Sync>;
+
+/// The header tower-http's `SetRequestIdLayer` uses by default, read for request IDs unless configured otherwise.
+static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// How a rate limited request was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitOutcome {
+    /// The request was allowed to proceed.
+    Allowed,
+    /// The request was rejected for exceeding its rate limit.
+    Rejected,
+    /// The request was rejected because its key is banned.
+    Banned,
+    /// The request was rejected by fault injection.
+    ChaosInjected,
+    /// The request was answered with a challenge.
+    Challenged,
+    /// The request was rejected because the backend failed to decide on it.
+    BackendError,
+    /// The request was shed while the system is overloaded.
+    Overloaded,
+}
+
+/// A rate limit decision, as reported to the handler of [`LimitEvents`].
+#[derive(Debug)]
+pub struct LimitEvent<'a, K> {
+    /// The key the limit was applied by.
+    pub key: &'a K,
+    /// How the request was handled.
+    pub outcome: LimitOutcome,
+    /// The state of the key's bucket, if it was charged and the backend did not fail.
+    pub decision: Option<Decision>,
+    /// The HTTP method of the request.
+    pub method: &'a http::Method,
+    /// The path of the request.
+    pub path: &'a str,
+    /// The identifier of the request, for joining decisions with application logs.
+    pub request_id: Option<&'a str>,
+}
+
+/// A hook notified of every rate limit decision, e.g. to log or trace them.
+///
+/// Each event carries the request's identifier, taken from its `x-request-id` header by default. That is where
+/// tower-http's `SetRequestIdLayer` puts generated IDs, so the layer only needs to wrap the rate limited routes.
+pub struct LimitEvents<K> {
+    handler: EventHandler<K>,
+    request_id: RequestIdSource,
+}
+
+impl<K> LimitEvents<K> {
+    /// Constructs a new `LimitEvents` calling `handler` for every decision.
+    pub fn new(handler: impl Fn(&LimitEvent<'_, K>) + Send + Sync + 'static) -> Self {
+        Self {
+            handler: Box::new(handler),
+            request_id: Box::new(|parts| {
+                parts
+                    .headers
+                    .get(&X_REQUEST_ID)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            }),
+        }
+    }
+
+    /// Replaces how request IDs are determined, e.g. to read them from a request extension.
+    pub fn request_id(
+        mut self,
+        source: impl Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.request_id = Box::new(source);
+        self
+    }
+
+    /// Reports the decision for a request to the handler.
+    pub(crate) fn emit(
+        &self,
+        parts: &Parts,
+        key: &K,
+        outcome: LimitOutcome,
+        decision: Option<Decision>,
+    ) {
+        let request_id = (self.request_id)(parts);
+        (self.handler)(&LimitEvent {
+            key,
+            outcome,
+            decision,
+            method: &parts.method,
+            path: parts.uri.path(),
+            request_id: request_id.as_deref(),
+        });
+    }
+}
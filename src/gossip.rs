@@ -0,0 +1,304 @@
+use crate::map::Map;
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::{ToSocketAddrs, UdpSocket};
+
+/// The most windows sent in a single datagram, keeping datagrams well below the maximum UDP payload.
+const WINDOWS_PER_DATAGRAM: usize = 256;
+
+/// The tokens an instance took from a bucket within one fixed window of its period.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    per: u64,
+    index: u64,
+    used: usize,
+}
+
+impl Window {
+    /// Returns whether the window has ended at `now`.
+    fn is_past(&self, now: u64) -> bool {
+        self.index < window_index(now, self.per)
+    }
+}
+
+/// Returns the index of the window of `per` milliseconds containing `now`.
+fn window_index(now: u64, per: u64) -> u64 {
+    now.checked_div(per).unwrap_or(now)
+}
+
+/// Returns the current wall-clock time in milliseconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+struct Shared {
+    socket: UdpSocket,
+    peers: Mutex<HashSet<SocketAddr>>,
+    local: Map<String, Window>,
+    remote: Map<String, HashMap<SocketAddr, Window>>,
+}
+
+/// A [`Backend`] for small clusters without a shared store, approximately enforcing a global limit by gossiping
+/// the tokens each instance took to its peers over UDP.
+///
+/// Limits are counted in fixed windows of their period. Every instance decides locally against the tokens it took
+/// plus those its peers reported for the current window, and sends its own counts to every peer on each round
+/// started by [`spawn_gossip`](Self::spawn_gossip). Counts are absolute, so lost datagrams are made up for by the
+/// next round; between rounds, the limit can be exceeded by what the other instances took since their last report.
+/// Datagrams are only accepted from configured peers. Like the [`RedisBackend`](crate::RedisBackend), buckets are
+/// identified by the [`KeyHasher`] identifier of their key, so all instances must share the same hasher.
+/// Clones share the same socket and counts.
+#[derive(Clone)]
+pub struct GossipBackend {
+    shared: Arc<Shared>,
+    hasher: KeyHasher,
+}
+
+impl GossipBackend {
+    /// Binds a new `GossipBackend` to the UDP address `addr`, identifying keys with `hasher`. Peers are added with
+    /// [`add_peer`](Self::add_peer).
+    pub async fn bind(addr: impl ToSocketAddrs, hasher: KeyHasher) -> io::Result<Self> {
+        Ok(Self {
+            shared: Arc::new(Shared {
+                socket: UdpSocket::bind(addr).await?,
+                peers: Mutex::new(HashSet::new()),
+                local: Map::new(),
+                remote: Map::new(),
+            }),
+            hasher,
+        })
+    }
+
+    /// Returns the address the backend receives gossip on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.shared.socket.local_addr()
+    }
+
+    /// Adds `peer` to the instances gossip is exchanged with.
+    pub fn add_peer(&self, peer: SocketAddr) {
+        self.shared.peers().insert(peer);
+    }
+
+    /// Removes `peer` from the instances gossip is exchanged with, forgetting the tokens it reported.
+    pub fn remove_peer(&self, peer: SocketAddr) {
+        self.shared.peers().remove(&peer);
+        for id in self.shared.remote_ids() {
+            if let Some(mut windows) = self.shared.remote.get_mut(&id) {
+                windows.remove(&peer);
+            }
+        }
+    }
+
+    /// Returns the instances gossip is exchanged with.
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.shared.peers().iter().copied().collect()
+    }
+
+    /// Starts a task receiving gossip from peers and sending this instance's counts to them every `interval`,
+    /// until the backend and all of its clones are dropped. Shorter intervals converge faster at the cost of more
+    /// traffic.
+    pub fn spawn_gossip(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let shared: Weak<Shared> = Arc::downgrade(&self.shared);
+        tokio::spawn(async move {
+            let mut buffer = vec![0; u16::MAX as usize];
+            let mut next_round = tokio::time::Instant::now();
+            loop {
+                let Some(shared) = shared.upgrade() else {
+                    return;
+                };
+                let received =
+                    tokio::time::timeout_at(next_round, shared.socket.recv_from(&mut buffer)).await;
+                match received {
+                    Ok(Ok((len, peer))) => shared.merge(peer, &buffer[..len]),
+                    Ok(Err(_)) => {}
+                    Err(_) => {
+                        shared.round().await;
+                        next_round += interval;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Returns the identifier of the bucket of `count` tokens per `per` milliseconds for `key`.
+    fn bucket_id<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!("{}:{count}:{per}", self.hasher.identify(key))
+    }
+}
+
+impl Shared {
+    fn peers(&self) -> std::sync::MutexGuard<'_, HashSet<SocketAddr>> {
+        self.peers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the identifiers of all buckets peers reported on. Collected up front, as the map must not be
+    /// modified while it is iterated.
+    fn remote_ids(&self) -> Vec<String> {
+        self.remote
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Returns the tokens the peers reported for the window `index` of bucket `id`.
+    fn remote_used(&self, id: &String, index: u64) -> usize {
+        self.remote.get(id).map_or(0, |windows| {
+            windows
+                .values()
+                .filter(|window| window.index == index)
+                .map(|window| window.used)
+                .sum()
+        })
+    }
+
+    /// Records the counts reported by `peer`, ignoring datagrams from unknown senders.
+    fn merge(&self, peer: SocketAddr, datagram: &[u8]) {
+        if !self.peers().contains(&peer) {
+            return;
+        }
+        let Ok(windows) = serde_json::from_slice::<Vec<(String, u64, u64, usize)>>(datagram) else {
+            return;
+        };
+        for (id, per, index, used) in windows {
+            let mut windows = self.remote.entry(id).or_default();
+            let window = windows.entry(peer).or_insert(Window {
+                per,
+                index,
+                used: 0,
+            });
+            // Datagrams may be reordered; never go back to an older window or a lower count.
+            if index > window.index || (index == window.index && used > window.used) {
+                *window = Window { per, index, used };
+            }
+        }
+    }
+
+    /// Forgets past windows, then sends the counts of the current ones to every peer.
+    async fn round(&self) {
+        let now = now();
+        let local: Vec<String> = self.local.iter().map(|entry| entry.key().clone()).collect();
+        for id in local {
+            self.local.remove_if(&id, |_, window| window.is_past(now));
+        }
+        for id in self.remote_ids() {
+            if let Some(mut windows) = self.remote.get_mut(&id) {
+                windows.retain(|_, window| !window.is_past(now));
+            }
+            self.remote.remove_if(&id, |_, windows| windows.is_empty());
+        }
+
+        let windows: Vec<_> = self
+            .local
+            .iter()
+            .map(|entry| {
+                let window = entry.value();
+                (entry.key().clone(), window.per, window.index, window.used)
+            })
+            .collect();
+        let peers: Vec<_> = self.peers().iter().copied().collect();
+        for chunk in windows.chunks(WINDOWS_PER_DATAGRAM) {
+            let Ok(datagram) = serde_json::to_vec(chunk) else {
+                continue;
+            };
+            for peer in &peers {
+                // Lost datagrams are made up for by the next round.
+                let _ = self.socket.send_to(&datagram, peer).await;
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for GossipBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let id = self.bucket_id(key, count, per);
+        let now = now();
+        let index = window_index(now, per);
+        let remote = self.shared.remote_used(&id, index);
+
+        let mut window = self.shared.local.entry(id).or_insert(Window {
+            per,
+            index,
+            used: 0,
+        });
+        if window.index != index {
+            *window = Window {
+                per,
+                index,
+                used: 0,
+            };
+        }
+        let allowed = window.used.saturating_add(remote).saturating_add(cost) <= count;
+        if allowed {
+            window.used += cost;
+        }
+        Ok(Decision {
+            allowed,
+            limit: count,
+            remaining: count.saturating_sub(window.used.saturating_add(remote)),
+            reset: Duration::from_millis(per.saturating_sub(now.checked_rem(per).unwrap_or(0))),
+        })
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let id = self.bucket_id(key, count, per);
+        if let Some(mut window) = self.shared.local.get_mut(&id) {
+            if window.index == window_index(now(), per) {
+                window.used = window.used.saturating_sub(cost);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn peers_share_their_counts() {
+        let bind = || GossipBackend::bind("127.0.0.1:0", KeyHasher::new(3));
+        let a = bind().await.expect("Failed to bind");
+        let b = bind().await.expect("Failed to bind");
+        a.add_peer(b.local_addr().expect("No address"));
+        b.add_peer(a.local_addr().expect("No address"));
+        a.spawn_gossip(Duration::from_millis(10));
+        b.spawn_gossip(Duration::from_millis(10));
+
+        let allowed = |backend: &GossipBackend| {
+            let backend = backend.clone();
+            async move {
+                let decision = backend.acquire(&"alice", 3, 3_600_000, 1).await;
+                decision.expect("Failed to acquire").allowed
+            }
+        };
+        assert!(allowed(&a).await);
+        assert!(allowed(&a).await);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(allowed(&b).await);
+        assert!(!allowed(&b).await);
+    }
+}
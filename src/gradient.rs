@@ -0,0 +1,263 @@
+use crate::rejection::Format;
+use crate::{BoxFuture, LimitRejection, RateLimitExceeded};
+use axum_core::body::Body;
+use axum_core::extract::{FromRef, FromRequestParts};
+use axum_core::response::{IntoResponse, Response};
+use http::request::Parts;
+use http::Request;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The state of a [`GradientLimiter`], guarded by its mutex.
+struct Gradient {
+    limit: f64,
+    in_flight: usize,
+    long_rtt: Option<f64>,
+}
+
+/// An in-flight limit estimated from observed latency, in the spirit of the gradient limiters of Netflix's
+/// concurrency-limits, for services whose capacity varies with load.
+///
+/// Every completed request is timed. While its latency stays close to the long-term average, the limit grows by
+/// about its square root per request, probing for spare capacity; as latency rises above the average, a sign of
+/// requests queueing up, the limit shrinks proportionally. The limit only grows while at least half of it is in
+/// use, so idle periods do not inflate it.
+///
+/// The limit is global, not per key. Apply it with the [`GradientLimitLayer`], or with the [`GradientPermit`]
+/// extractor if the limiter can be retrieved from the router state. Clones share the same limit.
+#[derive(Clone)]
+pub struct GradientLimiter {
+    state: Arc<Mutex<Gradient>>,
+    min: usize,
+    max: usize,
+    smoothing: f64,
+    window: f64,
+}
+
+impl GradientLimiter {
+    /// Constructs a new `GradientLimiter` starting at `initial` requests in flight. By default, the limit stays
+    /// between one and a thousand requests, and the long-term average latency follows the last hundred requests.
+    pub fn new(initial: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(Gradient {
+                limit: initial.max(1) as f64,
+                in_flight: 0,
+                long_rtt: None,
+            })),
+            min: 1,
+            max: 1000,
+            smoothing: 0.2,
+            window: 100.0,
+        }
+    }
+
+    /// Sets the lowest limit the estimate can shrink to, at least one request.
+    pub fn min_limit(mut self, min: usize) -> Self {
+        self.min = min.max(1);
+        self
+    }
+
+    /// Sets the highest limit the estimate can grow to.
+    pub fn max_limit(mut self, max: usize) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Sets how strongly each sample moves the limit, between `0.0` and `1.0`.
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns the current estimate of the number of requests allowed in flight.
+    pub fn limit(&self) -> usize {
+        self.lock().limit as usize
+    }
+
+    /// Returns the number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.lock().in_flight
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Gradient> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Admits a request unless the limit is reached, timing it until the returned permit is dropped.
+    pub(crate) fn acquire(&self) -> Option<Permit> {
+        let mut state = self.lock();
+        if state.in_flight >= state.limit as usize {
+            return None;
+        }
+        state.in_flight += 1;
+        Some(Permit {
+            limiter: self.clone(),
+            start: Instant::now(),
+        })
+    }
+
+    /// Adjusts the limit to a request that completed after `rtt`.
+    fn sample(&self, rtt: Duration) {
+        let rtt = rtt.as_secs_f64();
+        let mut state = self.lock();
+        let in_flight = state.in_flight;
+        state.in_flight = in_flight.saturating_sub(1);
+
+        let long_rtt = match state.long_rtt {
+            Some(long_rtt) => long_rtt + (rtt - long_rtt) / self.window,
+            None => rtt,
+        };
+        state.long_rtt = Some(long_rtt);
+        if rtt <= 0.0 {
+            return;
+        }
+
+        let gradient = (long_rtt / rtt).clamp(0.5, 1.0);
+        let estimate = state.limit * gradient + state.limit.sqrt();
+        if estimate > state.limit && in_flight * 2 < state.limit as usize {
+            return;
+        }
+        let limit = state.limit * (1.0 - self.smoothing) + estimate * self.smoothing;
+        state.limit = limit.clamp(self.min as f64, self.max.max(self.min) as f64);
+    }
+}
+
+/// A request admitted by a [`GradientLimiter`], timed until it is dropped.
+pub(crate) struct Permit {
+    limiter: GradientLimiter,
+    start: Instant,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.sample(self.start.elapsed());
+    }
+}
+
+/// An extractor admitting a request against a [`GradientLimiter`] retrieved from the router state.
+///
+/// The request is timed until the extractor is dropped, normally when the handler returns. Requests beyond the
+/// current limit are rejected with `429 Too Many Requests`.
+pub struct GradientPermit {
+    _permit: Permit,
+}
+
+impl std::fmt::Debug for GradientPermit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GradientPermit").finish_non_exhaustive()
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for GradientPermit
+where
+    GradientLimiter: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = LimitRejection<Infallible>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let limiter = GradientLimiter::from_ref(state);
+        match limiter.acquire() {
+            Some(permit) => Ok(Self { _permit: permit }),
+            None => Err(LimitRejection::RateLimitExceeded(
+                RateLimitExceeded::exhausted(limiter.limit()).negotiated(Format::negotiate(
+                    &parts.headers,
+                    false,
+                    false,
+                )),
+            )),
+        }
+    }
+}
+
+/// A layer admitting requests against a [`GradientLimiter`], timing them until their response is ready and
+/// rejecting requests beyond the current limit with `429 Too Many Requests`.
+#[derive(Clone)]
+pub struct GradientLimitLayer {
+    limiter: GradientLimiter,
+}
+
+impl GradientLimitLayer {
+    /// Constructs a new `GradientLimitLayer` enforcing `limiter`.
+    pub fn new(limiter: GradientLimiter) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for GradientLimitLayer {
+    type Service = GradientLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GradientLimit {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+/// Middleware that bounds requests in flight by an adaptive limit. See [`GradientLimitLayer`].
+#[derive(Clone)]
+pub struct GradientLimit<S> {
+    inner: S,
+    limiter: GradientLimiter,
+}
+
+impl<S> Service<Request<Body>> for GradientLimit<S>
+where
+    S: Service<Request<Body>>,
+    S::Response: IntoResponse,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let Some(permit) = self.limiter.acquire() else {
+            let rejection =
+                LimitRejection::<Infallible>::RateLimitExceeded(
+                    RateLimitExceeded::exhausted(self.limiter.limit())
+                        .negotiated(Format::negotiate(req.headers(), false, false)),
+                );
+            return Box::pin(async move { Ok(rejection.into_response()) });
+        };
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = future.await?.into_response();
+            drop(permit);
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adapts_to_latency() {
+        let limiter = GradientLimiter::new(4).smoothing(1.0);
+        let permits: Vec<_> = (0..4).filter_map(|_| limiter.acquire()).collect();
+        assert_eq!(permits.len(), 4);
+        assert!(limiter.acquire().is_none());
+
+        // Requests at steady latency probe for more capacity.
+        limiter.sample(Duration::from_millis(10));
+        assert_eq!(limiter.limit(), 6);
+
+        // A latency far above the long-term average shrinks the limit.
+        limiter.sample(Duration::from_millis(100));
+        assert!(limiter.limit() < 6);
+        assert_eq!(limiter.in_flight(), 2);
+        drop(permits);
+    }
+}
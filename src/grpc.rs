@@ -0,0 +1,141 @@
+use axum_core::response::{IntoResponse, Response};
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use std::time::Duration;
+
+static GRPC_STATUS: HeaderName = HeaderName::from_static("grpc-status");
+static GRPC_MESSAGE: HeaderName = HeaderName::from_static("grpc-message");
+static GRPC_STATUS_DETAILS: HeaderName = HeaderName::from_static("grpc-status-details-bin");
+
+/// The type URL of `google.rpc.RetryInfo` details.
+const RETRY_INFO: &str = "type.googleapis.com/google.rpc.RetryInfo";
+
+/// Decides whether `headers` belong to a gRPC request, e.g. of a tonic service served through axum.
+pub(crate) fn is_grpc(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/grpc"))
+}
+
+/// Returns the gRPC status code corresponding to the HTTP status of a rejection.
+fn code(status: StatusCode) -> u64 {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => 8,    // RESOURCE_EXHAUSTED
+        StatusCode::FORBIDDEN => 7,            // PERMISSION_DENIED
+        StatusCode::SERVICE_UNAVAILABLE => 14, // UNAVAILABLE
+        _ => 2,                                // UNKNOWN
+    }
+}
+
+/// Appends `value` to `buf` as a protobuf varint.
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Appends the length-delimited field `field` holding `bytes` to `buf`.
+fn bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(buf, field << 3 | 2);
+    varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Encodes a `google.rpc.Status` with `code` and `message`, carrying a `google.rpc.RetryInfo` if the delay to retry
+/// after is known.
+fn status_details(code: u64, message: &str, retry_after: Option<Duration>) -> Vec<u8> {
+    let mut status = Vec::new();
+    varint(&mut status, 1 << 3);
+    varint(&mut status, code);
+    bytes_field(&mut status, 2, message.as_bytes());
+    if let Some(retry_after) = retry_after {
+        let mut delay = Vec::new();
+        varint(&mut delay, 1 << 3);
+        varint(&mut delay, retry_after.as_secs());
+        if retry_after.subsec_nanos() > 0 {
+            varint(&mut delay, 2 << 3);
+            varint(&mut delay, u64::from(retry_after.subsec_nanos()));
+        }
+        let mut retry_info = Vec::new();
+        bytes_field(&mut retry_info, 1, &delay);
+        let mut any = Vec::new();
+        bytes_field(&mut any, 1, RETRY_INFO.as_bytes());
+        bytes_field(&mut any, 2, &retry_info);
+        bytes_field(&mut status, 3, &any);
+    }
+    status
+}
+
+/// Encodes `bytes` in unpadded standard base64, as gRPC binary metadata.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | u32::from(byte) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// Renders a rejection with `status` as a trailers-only gRPC response, e.g. `RESOURCE_EXHAUSTED` for
+/// `429 Too Many Requests`, with `message` and a `google.rpc.RetryInfo` if the delay to retry after is known.
+pub(crate) fn render(
+    status: StatusCode,
+    mut headers: HeaderMap,
+    message: &str,
+    retry_after: Option<Duration>,
+) -> Response {
+    let code = code(status);
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    headers.insert(GRPC_STATUS.clone(), code.into());
+    // Messages of rejections are plain ASCII, needing no percent-encoding.
+    if let Ok(value) = HeaderValue::from_str(message) {
+        headers.insert(GRPC_MESSAGE.clone(), value);
+    }
+    let details = base64(&status_details(code, message, retry_after));
+    if let Ok(value) = HeaderValue::from_str(&details) {
+        headers.insert(GRPC_STATUS_DETAILS.clone(), value);
+    }
+    (StatusCode::OK, headers).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_status_details() {
+        assert_eq!(base64(b"f"), "Zg");
+        assert_eq!(base64(b"fo"), "Zm8");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+
+        let details = status_details(8, "Slow", Some(Duration::from_millis(1_500)));
+        let mut expected = vec![0x08, 8, 0x12, 4];
+        expected.extend_from_slice(b"Slow");
+        expected.extend_from_slice(&[0x1a, 54, 0x0a, 40]);
+        expected.extend_from_slice(RETRY_INFO.as_bytes());
+        // One second and the varint of 500,000,000 nanoseconds.
+        expected.extend_from_slice(&[
+            0x12, 10, 0x0a, 8, 0x08, 1, 0x10, 0x80, 0xca, 0xb5, 0xee, 0x01,
+        ]);
+        assert_eq!(details, expected);
+
+        let response = render(
+            StatusCode::TOO_MANY_REQUESTS,
+            HeaderMap::new(),
+            "Rate limit exceeded.",
+            None,
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[&GRPC_STATUS], "8");
+        assert_eq!(response.headers()[&GRPC_MESSAGE], "Rate limit exceeded.");
+    }
+}
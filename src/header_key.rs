@@ -0,0 +1,189 @@
+use crate::rejection::{render, Format};
+use crate::Key;
+use axum_core::extract::FromRequestParts;
+use axum_core::response::Response;
+use http::request::Parts;
+use http::{HeaderMap, HeaderValue, StatusCode};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// Names the header a [`HeaderKey`] is extracted from, usually implemented with [`header_key!`](crate::header_key).
+///
+/// Const generics cannot be strings, so every header is named by a marker type instead.
+pub trait KeyHeader: Send + Sync + 'static {
+    /// The name of the header, in lowercase.
+    const NAME: &'static str;
+
+    /// Builds the response to a request without the header, `400 Bad Request` by default.
+    fn missing() -> Response {
+        render(
+            Format::default(),
+            StatusCode::BAD_REQUEST,
+            HeaderMap::new(),
+            &format!("Missing the `{}` header.", Self::NAME),
+            None,
+        )
+    }
+}
+
+/// The value of the header named by `H` as a key, e.g. `Limit<100, 60_000, HeaderKey<ApiKey>>` to limit each API
+/// key. Requests without the header are rejected with [`KeyHeader::missing`].
+pub struct HeaderKey<H> {
+    value: HeaderValue,
+    _header: PhantomData<fn() -> H>,
+}
+
+impl<H> HeaderKey<H> {
+    /// Returns the value of the header.
+    pub fn value(&self) -> &HeaderValue {
+        &self.value
+    }
+}
+
+impl<H> Debug for HeaderKey<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HeaderKey").field(&self.value).finish()
+    }
+}
+
+impl<H> Clone for HeaderKey<H> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            _header: PhantomData,
+        }
+    }
+}
+
+impl<H> PartialEq for HeaderKey<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<H> Eq for HeaderKey<H> {}
+
+impl<H> Hash for HeaderKey<H> {
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        self.value.hash(state);
+    }
+}
+
+#[async_trait::async_trait]
+impl<H, S> FromRequestParts<S> for HeaderKey<H>
+where
+    H: KeyHeader,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(H::NAME) {
+            Some(value) => Ok(Self {
+                value: value.clone(),
+                _header: PhantomData,
+            }),
+            None => Err(H::missing()),
+        }
+    }
+}
+
+impl<H> Key for HeaderKey<H>
+where
+    H: KeyHeader,
+{
+    type Extractor = Self;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        extractor.clone()
+    }
+}
+
+/// Declares a marker type naming a header for [`HeaderKey`], optionally with the response to requests without it.
+///
+/// ```
+/// use axum::{http::StatusCode, routing::get, Router};
+/// use axum_limit::{header_key, HeaderKey, Limit, LimitState};
+///
+/// header_key! {
+///     /// The `X-Api-Key` header.
+///     pub ApiKey = "x-api-key" => (StatusCode::UNAUTHORIZED, "An API key is required.");
+/// }
+///
+/// async fn handler(_: Limit<100, 60_000, HeaderKey<ApiKey>>) {}
+///
+/// let _app: Router<()> = Router::new()
+///     .route("/", get(handler))
+///     .with_state(LimitState::<HeaderKey<ApiKey>>::default());
+/// ```
+#[macro_export]
+macro_rules! header_key {
+    ($(#[$meta:meta])* $vis:vis $name:ident = $header:literal $(;)?) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default)]
+        $vis struct $name;
+
+        impl $crate::KeyHeader for $name {
+            const NAME: &'static str = $header;
+        }
+    };
+    ($(#[$meta:meta])* $vis:vis $name:ident = $header:literal => $missing:expr $(;)?) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default)]
+        $vis struct $name;
+
+        impl $crate::KeyHeader for $name {
+            const NAME: &'static str = $header;
+
+            fn missing() -> $crate::__private::Response {
+                $crate::__private::IntoResponse::into_response($missing)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+
+    crate::header_key!(ApiKey = "x-api-key");
+    crate::header_key!(Tenant = "x-tenant" => StatusCode::UNAUTHORIZED);
+
+    #[tokio::test]
+    async fn limits_each_header_value() {
+        async fn api(_: Limit<1, 60_000, HeaderKey<ApiKey>>) {}
+        async fn tenant(_: Limit<1, 60_000, HeaderKey<Tenant>>) {}
+
+        let my_app = Router::new()
+            .route("/api", get(api))
+            .with_state(LimitState::<HeaderKey<ApiKey>>::default())
+            .route("/tenant", get(tenant))
+            .with_state(LimitState::<HeaderKey<Tenant>>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        server.get("/api").add_header("x-api-key", "a").await;
+        server.get("/api").add_header("x-api-key", "b").await;
+        server
+            .get("/api")
+            .add_header("x-api-key", "a")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+        server
+            .get("/api")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+        server
+            .get("/tenant")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{Decision, RateLimitHeaders};
+use http::{HeaderName, HeaderValue, Request, Response};
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Header emitted by [`LimitWarningLayer`] when a key is close to exhausting its limit.
+pub static X_RATELIMIT_WARNING: HeaderName = HeaderName::from_static("x-ratelimit-warning");
+
+/// Returns the fraction of the limit that remains after a decision.
+fn ratio(decision: &Decision) -> f64 {
+    if decision.limit == 0 {
+        0.0
+    } else {
+        decision.remaining as f64 / decision.limit as f64
+    }
+}
+
+/// Marks responses to requests admitted beyond the warning threshold of their limit.
+///
+/// Inserted into the response extensions by [`LimitWarningLayer`], e.g. for middleware to act on early
+/// back-pressure, along with an `X-RateLimit-Warning` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoftLimitWarning {
+    /// The number of requests allowed within the period.
+    pub limit: usize,
+    /// The number of requests still allowed within the current period.
+    pub remaining: usize,
+}
+
+/// The quota of an admitted request, with the period its limit applies to and the headers describing it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Quota {
+    pub(crate) decision: Decision,
+    pub(crate) window: Duration,
+    pub(crate) headers: RateLimitHeaders,
+}
+
+/// Formats the `X-RateLimit-Warning` header describing the remaining quota of `decision`.
+fn warning(decision: &Decision) -> Option<HeaderValue> {
+    let warning = format!(
+        "{} of {} requests remaining",
+        decision.remaining, decision.limit
+    );
+    HeaderValue::from_str(&warning).ok()
+}
+
+/// Shared slot through which `Limit` extractors report quotas back to the layers wrapping the handler.
+/// When a handler uses several limits, the most constrained quota is kept.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct QuotaSlot(Arc<Mutex<Option<Quota>>>);
+
+impl QuotaSlot {
+    /// Returns the slot of `req`, installing a new one unless a layer further out already did.
+    fn install<B>(req: &mut Request<B>) -> Self {
+        let slot = req
+            .extensions()
+            .get::<QuotaSlot>()
+            .cloned()
+            .unwrap_or_default();
+        req.extensions_mut().insert(slot.clone());
+        slot
+    }
+
+    pub(crate) fn record(&self, quota: Quota) {
+        let mut slot = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_none_or(|current| ratio(&quota.decision) < ratio(&current.decision)) {
+            *slot = Some(quota);
+        }
+    }
+
+    fn get(&self) -> Option<Quota> {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The point from which a [`LimitWarningLayer`] warns.
+#[derive(Debug, Clone, Copy)]
+enum Threshold {
+    /// Once fewer than this fraction of the limit's requests remain.
+    Remaining(f64),
+    /// Once at least this fraction of the limit's requests are used.
+    Used(f64),
+}
+
+impl Threshold {
+    fn crossed(self, decision: &Decision) -> bool {
+        match self {
+            Self::Remaining(threshold) => ratio(decision) < threshold,
+            Self::Used(threshold) => {
+                let used = decision.limit.saturating_sub(decision.remaining);
+                used as f64 >= decision.limit as f64 * threshold
+            }
+        }
+    }
+}
+
+/// A layer that adds an `X-RateLimit-Warning` header and a [`SoftLimitWarning`] extension to successful responses
+/// once the quota of the request's key crosses a threshold, so clients can slow down before being rejected.
+#[derive(Debug, Clone, Copy)]
+pub struct LimitWarningLayer {
+    threshold: Threshold,
+}
+
+impl LimitWarningLayer {
+    /// Constructs a new `LimitWarningLayer` that warns once fewer than `threshold` (between `0.0` and `1.0`)
+    /// of the limit's requests remain, e.g. `0.2` to warn when less than 20% is left.
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold: Threshold::Remaining(threshold),
+        }
+    }
+
+    /// Constructs a new `LimitWarningLayer` that warns once `threshold` (between `0.0` and `1.0`) of the limit's
+    /// requests are used, e.g. `0.8` for a soft limit at 80% of `COUNT`.
+    pub fn used(threshold: f64) -> Self {
+        Self {
+            threshold: Threshold::Used(threshold.clamp(0.0, 1.0)),
+        }
+    }
+}
+
+impl<S> Layer<S> for LimitWarningLayer {
+    type Service = LimitWarning<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LimitWarning {
+            inner,
+            threshold: self.threshold,
+        }
+    }
+}
+
+/// Middleware that adds `X-RateLimit-Warning` headers. See [`LimitWarningLayer`].
+#[derive(Debug, Clone, Copy)]
+pub struct LimitWarning<S> {
+    inner: S,
+    threshold: Threshold,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for LimitWarning<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = LimitWarningFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let slot = QuotaSlot::install(&mut req);
+        LimitWarningFuture {
+            inner: self.inner.call(req),
+            slot,
+            threshold: self.threshold,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future of [`LimitWarning`].
+    pub struct LimitWarningFuture<F> {
+        #[pin]
+        inner: F,
+        slot: QuotaSlot,
+        threshold: Threshold,
+    }
+}
+
+impl<F, ResBody, E> Future for LimitWarningFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut response = ready!(this.inner.poll(cx))?;
+        if let Some(Quota { decision, .. }) = this.slot.get() {
+            if response.status().is_success() && this.threshold.crossed(&decision) {
+                if let Some(value) = warning(&decision) {
+                    response
+                        .headers_mut()
+                        .insert(X_RATELIMIT_WARNING.clone(), value);
+                }
+                response.extensions_mut().insert(SoftLimitWarning {
+                    limit: decision.limit,
+                    remaining: decision.remaining,
+                });
+            }
+        }
+        Poll::Ready(Ok(response))
+    }
+}
+
+/// A layer that adds headers describing the quota of the request's key to successful responses, as rejections
+/// carry them, so clients can pace themselves before being rejected.
+///
+/// Headers follow the [`RateLimitHeaders`] selected by the [`LimitState`](crate::LimitState) of the limit, and
+/// describe the most constrained quota when a handler uses several limits.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuotaHeadersLayer;
+
+impl QuotaHeadersLayer {
+    /// Constructs a new `QuotaHeadersLayer`.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for QuotaHeadersLayer {
+    type Service = QuotaHeaders<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        QuotaHeaders { inner }
+    }
+}
+
+/// Middleware that adds quota headers to successful responses. See [`QuotaHeadersLayer`].
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaHeaders<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for QuotaHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = QuotaHeadersFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let slot = QuotaSlot::install(&mut req);
+        QuotaHeadersFuture {
+            inner: self.inner.call(req),
+            slot,
+        }
+    }
+}
+
+pin_project! {
+    /// Response future of [`QuotaHeaders`].
+    pub struct QuotaHeadersFuture<F> {
+        #[pin]
+        inner: F,
+        slot: QuotaSlot,
+    }
+}
+
+impl<F, ResBody, E> Future for QuotaHeadersFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut response = ready!(this.inner.poll(cx))?;
+        if let Some(quota) = this.slot.get() {
+            if response.status().is_success() {
+                quota
+                    .headers
+                    .insert(response.headers_mut(), &quota.decision, quota.window);
+            }
+        }
+        Poll::Ready(Ok(response))
+    }
+}
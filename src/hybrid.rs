@@ -0,0 +1,261 @@
+use crate::map::Map;
+use crate::{Backend, BackendError, Clock, Decision, Key, RedisBackend, SystemClock, TokenBucket};
+use ::redis::aio::ConnectionLike;
+use ::redis::Script;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Duration;
+
+/// Adds `ARGV[1]` tokens consumed by one instance to the window counter at `KEYS[1]`, which expires `ARGV[2]`
+/// milliseconds after it was created, and returns the tokens consumed by all instances within the window.
+const RECONCILE: &str = r"
+local used = redis.call('INCRBY', KEYS[1], ARGV[1])
+if redis.call('PTTL', KEYS[1]) < 0 then
+    redis.call('PEXPIRE', KEYS[1], math.max(tonumber(ARGV[2]), 1))
+end
+return used
+";
+
+fn reconcile_script() -> &'static Script {
+    static SCRIPT: OnceLock<Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| Script::new(RECONCILE))
+}
+
+/// A key's local bucket and the tokens taken from it since it was last reconciled.
+#[derive(Clone)]
+struct LocalBucket {
+    count: usize,
+    per: u64,
+    bucket: TokenBucket,
+    pending: usize,
+}
+
+type Buckets<K> = Map<K, LocalBucket>;
+
+/// A [`Backend`] deciding against local buckets for latency, while reconciling the tokens taken from them with
+/// Redis so the limit is approximately enforced across all instances.
+///
+/// Tokens taken locally are added to a per-period counter in Redis either by a background task started with
+/// [`spawn_sync`](Self::spawn_sync), or as soon as a key's unreconciled tokens reach the [`drift`](Self::drift)
+/// bound. After each reconciliation, a local bucket holds at most the tokens no instance has used yet, so the
+/// limit can be exceeded by at most the drift bound of every instance, plus whatever was taken between two
+/// reconciliations.
+/// Clones share the same buckets.
+#[derive(Clone)]
+pub struct HybridBackend<K, C> {
+    buckets: Arc<Buckets<K>>,
+    remote: RedisBackend<C>,
+    drift: usize,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K, C> HybridBackend<K, C>
+where
+    K: Key + 'static,
+    C: ConnectionLike + Clone + Send + Sync + 'static,
+{
+    /// Constructs a new `HybridBackend` reconciling local buckets through `remote`.
+    pub fn new(remote: RedisBackend<C>) -> Self {
+        Self {
+            buckets: Arc::new(Map::new()),
+            remote,
+            drift: 16,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets how many tokens a key may take locally before it is reconciled immediately, 16 by default.
+    pub fn drift(mut self, drift: usize) -> Self {
+        self.drift = drift.max(1);
+        self
+    }
+
+    /// Replaces the clock used to refill local buckets.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Reconciles the tokens taken from every local bucket since its last reconciliation.
+    pub async fn sync(&self) -> Result<(), BackendError> {
+        Self::sync_buckets(&self.buckets, &self.remote).await
+    }
+
+    /// Starts a task calling [`sync`](Self::sync) every `interval`, until the backend and all of its clones are
+    /// dropped. Failed reconciliations are retried on the next tick.
+    pub fn spawn_sync(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let buckets: Weak<Buckets<K>> = Arc::downgrade(&self.buckets);
+        let remote = self.remote.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let Some(buckets) = buckets.upgrade() else {
+                    return;
+                };
+                let _ = Self::sync_buckets(&buckets, &remote).await;
+            }
+        })
+    }
+
+    async fn sync_buckets(
+        buckets: &Buckets<K>,
+        remote: &RedisBackend<C>,
+    ) -> Result<(), BackendError> {
+        let keys: Vec<K> = buckets
+            .iter()
+            .filter(|entry| entry.value().pending > 0)
+            .map(|entry| entry.key().clone())
+            .collect();
+        let mut result = Ok(());
+        for key in keys {
+            if let Err(error) = Self::reconcile(buckets, remote, &key).await {
+                result = Err(error);
+            }
+        }
+        result
+    }
+
+    /// Adds the tokens taken from `key`'s local bucket to its counter in Redis, and removes the tokens used by
+    /// other instances from the local bucket.
+    async fn reconcile(
+        buckets: &Buckets<K>,
+        remote: &RedisBackend<C>,
+        key: &K,
+    ) -> Result<(), BackendError> {
+        let Some((count, per, pending)) = buckets.get_mut(key).map(|mut local| {
+            let pending = std::mem::take(&mut local.pending);
+            (local.count, local.per, pending)
+        }) else {
+            return Ok(());
+        };
+
+        let mut connection = remote.connection.clone();
+        let used: Result<usize, _> = reconcile_script()
+            .key(format!("{}:window", remote.bucket_key(key, count, per)))
+            .arg(pending)
+            .arg(per)
+            .invoke_async(&mut connection)
+            .await;
+        match used {
+            Ok(used) => {
+                if let Some(mut local) = buckets.get_mut(key) {
+                    local.bucket.tokens = local.bucket.tokens.min(count.saturating_sub(used));
+                }
+                Ok(())
+            }
+            Err(error) => {
+                // Keep the tokens so the next reconciliation reports them.
+                if let Some(mut local) = buckets.get_mut(key) {
+                    local.pending += pending;
+                }
+                Err(BackendError::new(error))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, C> Backend<K> for HybridBackend<K, C>
+where
+    K: Key + 'static,
+    C: ConnectionLike + Clone + Send + Sync + 'static,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let now = self.clock.now();
+        let (decision, drifted) = {
+            let mut local = match self.buckets.get_mut(key) {
+                Some(local) => local,
+                None => self
+                    .buckets
+                    .entry(key.clone())
+                    .or_insert_with(|| LocalBucket {
+                        count,
+                        per,
+                        bucket: TokenBucket::new(count, per, now),
+                        pending: 0,
+                    }),
+            };
+            let decision = local.bucket.decide_at(cost, now);
+            if decision.allowed {
+                local.pending += cost;
+            }
+            (decision, local.pending >= self.drift)
+        };
+        if drifted {
+            // The decision stands; a failed reconciliation is retried with the next one.
+            let _ = Self::reconcile(&self.buckets, &self.remote, key).await;
+        }
+        Ok(decision)
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        _count: usize,
+        _per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let now = self.clock.now();
+        if let Some(mut local) = self.buckets.get_mut(key) {
+            local.bucket.refund(cost, now);
+            local.pending = local.pending.saturating_sub(cost);
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        self.sync().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyHasher;
+    use ::redis::{Cmd, Pipeline, RedisFuture, Value};
+    use http::Method;
+
+    /// A connection answering every command as if all instances together had used up the limit of 10 tokens.
+    #[derive(Clone)]
+    struct Busy;
+
+    impl ConnectionLike for Busy {
+        fn req_packed_command<'a>(&'a mut self, _cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+            Box::pin(async { Ok(Value::Int(10)) })
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            _cmd: &'a Pipeline,
+            _offset: usize,
+            _count: usize,
+        ) -> RedisFuture<'a, Vec<Value>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn reconciles_with_other_instances() {
+        let backend = HybridBackend::new(RedisBackend::new(Busy, KeyHasher::new(1))).drift(2);
+        let allowed = |backend: HybridBackend<Method, Busy>| async move {
+            let decision = backend.acquire(&Method::GET, 10, 60_000, 1).await;
+            decision.expect("Failed to acquire").allowed
+        };
+
+        assert!(allowed(backend.clone()).await);
+        // Reaching the drift bound reconciles with the other instances' usage.
+        assert!(allowed(backend.clone()).await);
+        assert!(!allowed(backend.clone()).await);
+        backend.sync().await.expect("Failed to sync");
+    }
+}
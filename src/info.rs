@@ -0,0 +1,101 @@
+use crate::Decision;
+use axum_core::extract::FromRequestParts;
+use http::request::Parts;
+use http::StatusCode;
+use std::time::Duration;
+
+/// The quota of the request's key after a successful [`Limit`](crate::Limit) extraction, so handlers can include it
+/// in their own response bodies.
+///
+/// Inserted into the request extensions, and extracted from them like any other extractor, listed after the limit
+/// in the handler's arguments. When a handler uses several limits, the most constrained quota is kept. Extraction
+/// fails with `500 Internal Server Error` if no limit was extracted before; extract an `Option<RateLimitInfo>`
+/// where the quota may be unknown, e.g. if the backend failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    /// The number of requests allowed within the period.
+    pub limit: usize,
+    /// The number of requests still allowed within the current period.
+    pub remaining: usize,
+    /// The time until the quota is replenished.
+    pub reset: Duration,
+}
+
+impl From<Decision> for RateLimitInfo {
+    fn from(decision: Decision) -> Self {
+        Self {
+            limit: decision.limit,
+            remaining: decision.remaining,
+            reset: decision.reset,
+        }
+    }
+}
+
+impl RateLimitInfo {
+    /// Returns the fraction of the limit that remains.
+    fn ratio(&self) -> f64 {
+        if self.limit == 0 {
+            0.0
+        } else {
+            self.remaining as f64 / self.limit as f64
+        }
+    }
+
+    /// Records the quota of an admitted request in `parts`, unless a more constrained one is already recorded.
+    pub(crate) fn record(parts: &mut Parts, decision: Option<Decision>) {
+        let Some(info) = decision.map(Self::from) else {
+            return;
+        };
+        match parts.extensions.get::<Self>() {
+            Some(current) if current.ratio() <= info.ratio() => {}
+            _ => {
+                parts.extensions.insert(info);
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for RateLimitInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Self>().copied().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "No rate limit was extracted before.",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitStates};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{Method, Uri};
+
+    #[tokio::test]
+    async fn exposes_quota_to_handlers() {
+        async fn handler(
+            _: Limit<3, 60_000, Uri>,
+            _: Limit<10, 60_000, Method>,
+            info: RateLimitInfo,
+        ) -> String {
+            format!("{} of {}", info.remaining, info.limit)
+        }
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitStates::new());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/").await.text(), "2 of 3");
+        assert_eq!(server.get("/").await.text(), "1 of 3");
+    }
+}
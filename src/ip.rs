@@ -0,0 +1,420 @@
+use crate::Key;
+use axum::extract::rejection::ExtensionRejection;
+use axum::extract::ConnectInfo;
+use axum_core::extract::FromRequestParts;
+use http::request::Parts;
+use http::{HeaderMap, HeaderName};
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+
+static CF_CONNECTING_IP: HeaderName = HeaderName::from_static("cf-connecting-ip");
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// The IP address of the connected peer as a key, so `Limit<100, 60_000, ClientIp>` limits each client address.
+///
+/// The address is extracted from the `ConnectInfo<SocketAddr>` of the connection, which requires serving the
+/// application with `into_make_service_with_connect_info::<SocketAddr>()`; extraction fails otherwise. Behind a
+/// reverse proxy or load balancer, this is the address of the proxy rather than of the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+impl Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl From<ClientIp> for IpAddr {
+    fn from(ClientIp(addr): ClientIp) -> Self {
+        addr
+    }
+}
+
+impl Key for ClientIp {
+    type Extractor = ConnectInfo<SocketAddr>;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        Self(extractor.0.ip())
+    }
+}
+
+/// The header a [`TrustedProxies`] reads the addresses a request was forwarded for from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// The de facto standard `X-Forwarded-For`, a list of addresses appended to by every proxy.
+    #[default]
+    XForwardedFor,
+    /// The `for` parameters of the standard `Forwarded` header of RFC 7239.
+    Forwarded,
+    /// The single address set by Cloudflare in `CF-Connecting-IP`.
+    CfConnectingIp,
+}
+
+impl ForwardedHeader {
+    /// Returns the addresses `headers` were forwarded for, from the client to the last proxy, stopping at the first
+    /// one that fails to parse.
+    fn chain(self, headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+        let name = match self {
+            Self::XForwardedFor => &X_FORWARDED_FOR,
+            Self::Forwarded => &http::header::FORWARDED,
+            Self::CfConnectingIp => &CF_CONNECTING_IP,
+        };
+        let values = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim);
+        match self {
+            Self::XForwardedFor | Self::CfConnectingIp => values.map(parse_node).collect(),
+            Self::Forwarded => values
+                .map(|element| {
+                    element
+                        .split(';')
+                        .filter_map(|pair| pair.trim().split_once('='))
+                        .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                        .and_then(|(_, node)| parse_node(node.trim_matches('"')))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parses a forwarded node, an address optionally followed by a port, with IPv6 addresses in brackets if so.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(addr) = node.parse::<IpAddr>() {
+        return Some(addr);
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// Returns `addr` with all but its first `prefix` bits cleared, IPv4-mapped IPv6 addresses treated as IPv4.
+fn truncate(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr.to_canonical() {
+        IpAddr::V4(addr) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix.min(32)))
+                .unwrap_or(0);
+            IpAddr::V4((u32::from(addr) & mask).into())
+        }
+        IpAddr::V6(addr) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix.min(128)))
+                .unwrap_or(0);
+            IpAddr::V6((u128::from(addr) & mask).into())
+        }
+    }
+}
+
+/// The reverse proxies and load balancers trusted to report the address of the client, for the [`ForwardedIp`] key.
+///
+/// Installed as a request extension, e.g. with `Router::layer(Extension(trusted_proxies))`. Without it, no proxy
+/// is trusted and every request is keyed by the address of its peer.
+#[derive(Debug, Clone)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+    hops: usize,
+    header: ForwardedHeader,
+}
+
+impl Default for TrustedProxies {
+    fn default() -> Self {
+        Self {
+            networks: Vec::new(),
+            hops: 1,
+            header: ForwardedHeader::default(),
+        }
+    }
+}
+
+impl TrustedProxies {
+    /// Constructs a new `TrustedProxies` trusting no proxy, reading `X-Forwarded-For` across a single hop.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts the proxy at `addr`.
+    pub fn trust(self, addr: IpAddr) -> Self {
+        let prefix = if addr.to_canonical().is_ipv4() {
+            32
+        } else {
+            128
+        };
+        self.trust_network(addr, prefix)
+    }
+
+    /// Trusts all proxies within the network of `addr` and its first `prefix` bits, e.g. `10.0.0.0` and `8`.
+    pub fn trust_network(mut self, addr: IpAddr, prefix: u8) -> Self {
+        self.networks.push((truncate(addr, prefix), prefix));
+        self
+    }
+
+    /// Follows the forwarded addresses across at most `hops` trusted proxies in front of the application, one by
+    /// default.
+    pub fn with_hops(mut self, hops: usize) -> Self {
+        self.hops = hops.max(1);
+        self
+    }
+
+    /// Reads the forwarded addresses from `header` instead of `X-Forwarded-For`.
+    pub fn with_header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Decides whether the proxy at `addr` is trusted.
+    fn trusts(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        self.networks.iter().any(|&(network, prefix)| {
+            network.is_ipv4() == addr.is_ipv4() && truncate(addr, prefix) == network
+        })
+    }
+
+    /// Returns the address of the client of a request from `peer` with `headers`.
+    ///
+    /// Starting from the peer, forwarded addresses are followed from the last proxy towards the client only as long
+    /// as the address they were received from is trusted, so clients cannot spoof their address by sending the
+    /// header themselves.
+    fn client(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer;
+        if !self.trusts(peer) {
+            return client;
+        }
+        for (hop, addr) in self.header.chain(headers).into_iter().rev().enumerate() {
+            let Some(addr) = addr else {
+                break;
+            };
+            client = addr;
+            if hop + 1 >= self.hops || !self.trusts(addr) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// The IP address of the client as a key, as reported by the [`TrustedProxies`] in front of the application, so
+/// per-IP limits behind load balancers apply to the real client.
+///
+/// Like [`ClientIp`], this requires the `ConnectInfo<SocketAddr>` of the connection. Forwarding headers are only
+/// honored when the peer is a trusted proxy; otherwise, or if the headers are missing or malformed, the key is the
+/// address of the nearest untrusted hop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ForwardedIp(pub IpAddr);
+
+impl Display for ForwardedIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for ForwardedIp
+where
+    S: Send + Sync,
+{
+    type Rejection = ExtensionRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state).await?;
+        let client = match parts.extensions.get::<TrustedProxies>() {
+            Some(proxies) => proxies.client(peer.ip(), &parts.headers),
+            None => peer.ip(),
+        };
+        Ok(Self(client))
+    }
+}
+
+impl From<ForwardedIp> for IpAddr {
+    fn from(ForwardedIp(addr): ForwardedIp) -> Self {
+        addr
+    }
+}
+
+impl Key for ForwardedIp {
+    type Extractor = ForwardedIp;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        *extractor
+    }
+}
+
+/// The subnet of an IP address key `K`, such as [`ClientIp`] or [`ForwardedIp`], as a key, so all clients within a
+/// subnet share one bucket instead of getting fresh quota for every address they rotate through.
+///
+/// IPv4 addresses are truncated to their first `V4` bits and IPv6 addresses to their first `V6` bits, `/24` and
+/// `/64` by default, e.g. `Limit<100, 60_000, Subnet<ForwardedIp>>`. IPv4-mapped IPv6 addresses count as IPv4.
+pub struct Subnet<K, const V4: u8 = 24, const V6: u8 = 64> {
+    network: IpAddr,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<K, const V4: u8, const V6: u8> Subnet<K, V4, V6> {
+    /// Returns the subnet of `addr`.
+    pub fn new(addr: IpAddr) -> Self {
+        let prefix = if addr.to_canonical().is_ipv4() {
+            V4
+        } else {
+            V6
+        };
+        Self {
+            network: truncate(addr, prefix),
+            _key: PhantomData,
+        }
+    }
+
+    /// Returns the address of the subnet, with all bits beyond its prefix cleared.
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    /// Returns the length of the subnet's prefix in bits.
+    pub fn prefix(&self) -> u8 {
+        if self.network.is_ipv4() {
+            V4.min(32)
+        } else {
+            V6.min(128)
+        }
+    }
+}
+
+impl<K, const V4: u8, const V6: u8> Debug for Subnet<K, V4, V6> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Subnet").field(&self.network).finish()
+    }
+}
+
+impl<K, const V4: u8, const V6: u8> Display for Subnet<K, V4, V6> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix())
+    }
+}
+
+impl<K, const V4: u8, const V6: u8> Clone for Subnet<K, V4, V6> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, const V4: u8, const V6: u8> Copy for Subnet<K, V4, V6> {}
+
+impl<K, const V4: u8, const V6: u8> PartialEq for Subnet<K, V4, V6> {
+    fn eq(&self, other: &Self) -> bool {
+        self.network == other.network
+    }
+}
+
+impl<K, const V4: u8, const V6: u8> Eq for Subnet<K, V4, V6> {}
+
+impl<K, const V4: u8, const V6: u8> Hash for Subnet<K, V4, V6> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.network.hash(state);
+    }
+}
+
+impl<K, const V4: u8, const V6: u8> Key for Subnet<K, V4, V6>
+where
+    K: Key,
+    IpAddr: From<K>,
+{
+    type Extractor = K::Extractor;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        Self::new(K::from_extractor(extractor).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServerConfig;
+    use http::StatusCode;
+
+    #[tokio::test]
+    async fn limits_each_client_ip() {
+        async fn handler(Limit(ConnectInfo(peer)): Limit<1, 60_000, ClientIp>) -> String {
+            ClientIp::from_extractor(&ConnectInfo(peer)).to_string()
+        }
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<ClientIp>::default())
+            .into_make_service_with_connect_info::<SocketAddr>();
+
+        let server = TestServerConfig::builder()
+            .http_transport()
+            .build_server(my_app)
+            .expect("Failed to create test server");
+
+        assert_eq!(server.get("/").await.text(), "127.0.0.1");
+        server
+            .get("/")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn follows_trusted_proxies() {
+        let ip = |addr: &str| addr.parse::<IpAddr>().expect("valid address");
+        let headers = |name: &str, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                HeaderName::try_from(name).expect("valid name"),
+                value.parse().expect("valid value"),
+            );
+            headers
+        };
+        let proxies = TrustedProxies::new().trust_network(ip("10.0.0.0"), 8);
+        let forwarded = headers("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.0.0.2");
+
+        // Headers from untrusted peers are ignored.
+        assert_eq!(proxies.client(ip("9.9.9.9"), &forwarded), ip("9.9.9.9"));
+        assert_eq!(proxies.client(ip("10.0.0.1"), &forwarded), ip("10.0.0.2"));
+        let proxies = proxies.with_hops(3);
+        assert_eq!(proxies.client(ip("10.0.0.1"), &forwarded), ip("1.2.3.4"));
+        assert_eq!(
+            proxies.client(ip("::ffff:10.0.0.1"), &HeaderMap::new()),
+            ip("::ffff:10.0.0.1")
+        );
+
+        let proxies = proxies.with_header(ForwardedHeader::Forwarded);
+        let forwarded = headers(
+            "forwarded",
+            "for=\"[2001:db8::1]:4711\";proto=https, for=10.1.2.3:80",
+        );
+        assert_eq!(
+            proxies.client(ip("10.0.0.1"), &forwarded),
+            ip("2001:db8::1")
+        );
+
+        let proxies = TrustedProxies::new()
+            .trust(ip("10.0.0.1"))
+            .with_header(ForwardedHeader::CfConnectingIp);
+        let forwarded = headers("cf-connecting-ip", "1.2.3.4");
+        assert_eq!(proxies.client(ip("10.0.0.1"), &forwarded), ip("1.2.3.4"));
+        assert_eq!(proxies.client(ip("10.0.0.2"), &forwarded), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn aggregates_subnets() {
+        let subnet =
+            |addr: &str| Subnet::<ClientIp>::new(addr.parse().expect("valid address")).to_string();
+        assert_eq!(subnet("1.2.3.4"), "1.2.3.0/24");
+        assert_eq!(subnet("::ffff:1.2.3.4"), "1.2.3.0/24");
+        assert_eq!(subnet("2001:db8:1:2:3:4:5:6"), "2001:db8:1:2::/64");
+        assert_eq!(
+            Subnet::<ClientIp, 16, 48>::new("1.2.3.4".parse().expect("valid address")),
+            Subnet::new("1.2.99.99".parse().expect("valid address"))
+        );
+        assert_eq!(
+            Subnet::<ClientIp, 40, 200>::new("1.2.3.4".parse().expect("valid address")).prefix(),
+            32
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::rejection::{render, Format};
+use crate::Key;
+use axum_core::extract::FromRequestParts;
+use axum_core::response::Response;
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::request::Parts;
+use http::{HeaderMap, HeaderValue, StatusCode};
+use jsonwebtoken::{DecodingKey, Validation};
+use serde_json::Value;
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+
+/// The key and validation rules of the JSON Web Tokens identifying [`JwtClaim`] keys, and the claim to key them by.
+///
+/// Installed as a request extension, e.g. with `Router::layer(Extension(decoder))`. Clones share the same key.
+#[derive(Clone)]
+pub struct JwtDecoder {
+    key: Arc<DecodingKey>,
+    validation: Arc<Validation>,
+    claim: Arc<str>,
+}
+
+impl Debug for JwtDecoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtDecoder")
+            .field("algorithms", &self.validation.algorithms)
+            .field("claim", &self.claim)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtDecoder {
+    /// Constructs a new `JwtDecoder` verifying tokens with `key` according to `validation`, such as their
+    /// algorithms, expiry and audience, and keying them by their `sub` claim.
+    pub fn new(key: DecodingKey, validation: Validation) -> Self {
+        Self {
+            key: Arc::new(key),
+            validation: Arc::new(validation),
+            claim: Arc::from("sub"),
+        }
+    }
+
+    /// Keys tokens by `claim` instead of `sub`, e.g. `"tenant_id"`.
+    pub fn with_claim(mut self, claim: impl Into<String>) -> Self {
+        self.claim = Arc::from(claim.into());
+        self
+    }
+
+    /// Returns the value of the claim of a valid `token`, strings as they are and other values as JSON.
+    fn claim(&self, token: &str) -> Option<String> {
+        let data = jsonwebtoken::decode::<Value>(token, &self.key, &self.validation).ok()?;
+        match data.claims.get(&*self.claim)? {
+            Value::String(value) => Some(value.clone()),
+            Value::Null => None,
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+/// A claim of the JSON Web Token a request is authorized with as a key, `sub` by default, so
+/// `Limit<100, 60_000, JwtClaim>` limits each user directly from their bearer token.
+///
+/// Tokens are read from the `Authorization: Bearer` header and verified by the [`JwtDecoder`] in the request
+/// extensions. Requests without a valid token carrying the claim are rejected with `401 Unauthorized`, and with
+/// `500 Internal Server Error` if no decoder is installed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JwtClaim(pub String);
+
+impl Display for JwtClaim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for JwtClaim
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(decoder) = parts.extensions.get::<JwtDecoder>() else {
+            return Err(render(
+                Format::default(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                HeaderMap::new(),
+                "No JWT decoder was installed.",
+                None,
+            ));
+        };
+        parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| decoder.claim(token.trim()))
+            .map(Self)
+            .ok_or_else(|| {
+                let mut headers = HeaderMap::new();
+                headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                render(
+                    Format::default(),
+                    StatusCode::UNAUTHORIZED,
+                    headers,
+                    "A valid bearer token is required.",
+                    None,
+                )
+            })
+    }
+}
+
+impl Key for JwtClaim {
+    type Extractor = Self;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        extractor.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use axum_test::TestServer;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    #[tokio::test]
+    async fn limits_each_claim() {
+        async fn handler(Limit(claim): Limit<1, 60_000, JwtClaim>) -> String {
+            claim.to_string()
+        }
+
+        let token = |sub: &str| {
+            let claims = serde_json::json!({ "sub": sub, "exp": u32::MAX });
+            let token = jsonwebtoken::encode(
+                &Header::default(),
+                &claims,
+                &EncodingKey::from_secret(b"secret"),
+            )
+            .expect("valid claims");
+            format!("Bearer {token}")
+        };
+        let decoder = JwtDecoder::new(DecodingKey::from_secret(b"secret"), Validation::default());
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<JwtClaim>::default())
+            .layer(Extension(decoder));
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server
+            .get("/")
+            .add_header("authorization", token("alice"))
+            .await;
+        assert_eq!(response.text(), "alice");
+        server
+            .get("/")
+            .add_header("authorization", token("bob"))
+            .await;
+        server
+            .get("/")
+            .add_header("authorization", token("alice"))
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+        server
+            .get("/")
+            .add_header("authorization", "Bearer forged")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,5 +1,66 @@
+use crate::rejection::{render, Format};
 use crate::Key;
-use http::{Method, Uri, Version};
+use axum_core::extract::FromRequestParts;
+use axum_core::response::Response;
+use http::request::Parts;
+use http::{header, HeaderMap, Method, StatusCode, Uri, Version};
+use std::fmt::{self, Display};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A marker type naming a group of routes that share one budget.
+///
+/// Groups are keys without any request data, so combining one with another key gives every key a single bucket
+/// across all routes of the group:
+///
+/// ```
+/// use axum::{routing::get, Router};
+/// use axum_limit::{Group, Limit, LimitState};
+/// use http::Method;
+///
+/// #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// struct SearchGroup;
+///
+/// impl Group for SearchGroup {}
+///
+/// // Both search endpoints combined allow 100 requests per minute and method.
+/// async fn search_users(_: Limit<100, 60_000, (Method, SearchGroup)>) {}
+/// async fn search_posts(_: Limit<100, 60_000, (Method, SearchGroup)>) {}
+///
+/// let _app: Router<()> = Router::new()
+///     .route("/search/users", get(search_users))
+///     .route("/search/posts", get(search_posts))
+///     .with_state(LimitState::<(Method, SearchGroup)>::default());
+/// ```
+pub trait Group: Eq + Hash + Clone + Default + Send + Sync {}
+
+impl<G> Key for G
+where
+    G: Group,
+{
+    type Extractor = ();
+
+    fn from_extractor(_: &Self::Extractor) -> Self {
+        G::default()
+    }
+}
+
+/// A built-in [`Group`] distinguished by a const parameter, for giving otherwise identical limits on different
+/// routes independent budgets.
+///
+/// Handlers using the same key type, e.g. `Limit<10, 1000, UserId>`, share buckets through the same
+/// `LimitState<UserId>`, even if their limits differ. Scoping the key, as in `Limit<10, 1000, (UserId, Scope<1>)>`,
+/// makes the sharing explicit: only handlers with the same scope share a budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Scope<const ID: u32>;
+
+impl<const ID: u32> Group for Scope<ID> {}
+
+/// The unit key, giving every route limited by `Limit<100, 1000, ()>` a single bucket shared by all callers.
+///
+/// All routes using the unit key share the same `LimitState<()>`, so their limits draw on one budget; use a
+/// [`Scope`] instead to give an endpoint a budget of its own.
+impl Group for () {}
 
 impl Key for Uri {
     type Extractor = Uri;
@@ -25,6 +86,88 @@ impl Key for Version {
     }
 }
 
+/// The route template a request matched as a key, e.g. `/users/:id`, so limits apply per endpoint rather than per
+/// URI as with the [`Uri`] key, which gives `/users/1` and `/users/2` separate buckets.
+///
+/// The template is extracted from axum's `MatchedPath`, which is unavailable in middleware layered outside of the
+/// router.
+#[cfg(feature = "axum")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RoutePath(Arc<str>);
+
+#[cfg(feature = "axum")]
+impl RoutePath {
+    /// Returns the route template.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(feature = "axum")]
+impl Key for RoutePath {
+    type Extractor = axum::extract::MatchedPath;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        Self(Arc::from(extractor.as_str()))
+    }
+}
+
+/// The host a request is addressed to as a key, so routers serving several domains limit each virtual host
+/// independently, e.g. `Limit<1000, 60_000, Host>`.
+///
+/// The host is read from the `Host` header, or the authority of the URI for HTTP/2 requests, without its port and
+/// in lowercase. Requests without either are rejected with `400 Bad Request`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Host(Arc<str>);
+
+impl Host {
+    /// Returns the host, e.g. `example.com`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for Host
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .headers
+            .get(header::HOST)
+            .and_then(|value| value.to_str().ok())
+            .or_else(|| parts.uri.host())
+            .and_then(|host| host.parse::<http::uri::Authority>().ok())
+            .map(|authority| Self(Arc::from(authority.host().to_ascii_lowercase())))
+            .ok_or_else(|| {
+                render(
+                    Format::default(),
+                    StatusCode::BAD_REQUEST,
+                    HeaderMap::new(),
+                    "Missing the `Host` header.",
+                    None,
+                )
+            })
+    }
+}
+
+impl Key for Host {
+    type Extractor = Self;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        extractor.clone()
+    }
+}
+
 macro_rules! impl_key_for_tuple {
     ($($name:ident),+) => {
         #[allow(non_snake_case)]
@@ -0,0 +1,270 @@
+use crate::map::Map;
+use crate::{Backend, BackendError, Clock, Decision, Key, SystemClock};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Tokens an instance took from the shared store ahead of time, valid until the store's bucket is refilled.
+#[derive(Clone)]
+struct Lease {
+    tokens: usize,
+    remote_remaining: usize,
+    expires: Instant,
+    refreshing: bool,
+}
+
+type Leases<K> = Map<(K, usize, u64), Lease>;
+
+/// A [`Backend`] taking tokens from a shared backend in batches, serving requests from the leased tokens locally
+/// so most requests do not wait for a round-trip to the store.
+///
+/// When a key's lease runs low, the next batch is taken by a background task; only requests finding the lease
+/// empty call the store themselves. All tokens come from the shared backend, so the global limit is never exceeded;
+/// instead, up to a batch of tokens per instance and key may go unused until the store's bucket is refilled. Leased
+/// tokens are returned to the store by [`flush`](Backend::flush).
+/// Clones share the same leases.
+pub struct LeasingBackend<K, B> {
+    inner: Arc<B>,
+    leases: Arc<Leases<K>>,
+    batch: usize,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K, B> Clone for LeasingBackend<K, B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            leases: self.leases.clone(),
+            batch: self.batch,
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl<K, B> LeasingBackend<K, B>
+where
+    K: Key + 'static,
+    B: Backend<K> + 'static,
+{
+    /// Constructs a new `LeasingBackend` leasing batches of 10 tokens from `inner`.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            leases: Arc::new(Map::new()),
+            batch: 10,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets how many tokens are leased at once, 10 by default. Larger batches save more round-trips, but leave more
+    /// tokens unused when traffic moves to other instances.
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    /// Replaces the clock used to expire leases.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Takes `cost` tokens from the shared backend, leasing the rest of a batch along with them if possible.
+    async fn fetch(
+        &self,
+        id: (K, usize, u64),
+        cost: usize,
+        now: Instant,
+    ) -> Result<Decision, BackendError> {
+        let (key, count, per) = &id;
+        let amount = self.batch.max(cost);
+        let mut decision = self.inner.acquire(key, *count, *per, amount).await?;
+        let mut leased = amount - cost;
+        if !decision.allowed && amount > cost {
+            // Too few tokens are left for a whole batch; take only what this request needs.
+            decision = self.inner.acquire(key, *count, *per, cost).await?;
+            leased = 0;
+        }
+        if !decision.allowed {
+            return Ok(decision);
+        }
+
+        let mut lease = self.leases.entry(id).or_insert(Lease {
+            tokens: 0,
+            remote_remaining: 0,
+            expires: now,
+            refreshing: false,
+        });
+        lease.tokens += leased;
+        lease.remote_remaining = decision.remaining;
+        lease.expires = now + decision.reset;
+        Ok(Decision {
+            remaining: decision.remaining.saturating_add(lease.tokens),
+            ..decision
+        })
+    }
+
+    /// Leases another batch for `id` in the background.
+    fn refresh(&self, id: (K, usize, u64)) {
+        let backend = self.clone();
+        tokio::spawn(async move {
+            let (key, count, per) = &id;
+            let decision = backend
+                .inner
+                .acquire(key, *count, *per, backend.batch)
+                .await;
+            let now = backend.clock.now();
+            if let Some(mut lease) = backend.leases.get_mut(&id) {
+                match decision {
+                    Ok(decision) if decision.allowed => {
+                        if lease.expires <= now {
+                            lease.tokens = 0;
+                        }
+                        lease.tokens += backend.batch;
+                        lease.remote_remaining = decision.remaining;
+                        lease.expires = now + decision.reset;
+                        lease.refreshing = false;
+                    }
+                    // The store is exhausted until the lease expires; do not ask again before.
+                    Ok(_) => {}
+                    Err(_) => lease.refreshing = false,
+                }
+            }
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, B> Backend<K> for LeasingBackend<K, B>
+where
+    K: Key + 'static,
+    B: Backend<K> + 'static,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let now = self.clock.now();
+        let id = (key.clone(), count, per);
+        let served = self.leases.get_mut(&id).and_then(|mut lease| {
+            if lease.expires <= now {
+                lease.tokens = 0;
+                lease.refreshing = false;
+            }
+            if lease.tokens < cost {
+                return None;
+            }
+            lease.tokens -= cost;
+            let refresh = !lease.refreshing && lease.tokens <= self.batch / 4;
+            lease.refreshing |= refresh;
+            let decision = Decision {
+                allowed: true,
+                limit: count,
+                remaining: lease.remote_remaining.saturating_add(lease.tokens),
+                reset: lease.expires.saturating_duration_since(now),
+            };
+            Some((decision, refresh))
+        });
+        match served {
+            Some((decision, refresh)) => {
+                if refresh {
+                    self.refresh(id);
+                }
+                Ok(decision)
+            }
+            None => self.fetch(id, cost, now).await,
+        }
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let now = self.clock.now();
+        let returned = self
+            .leases
+            .get_mut(&(key.clone(), count, per))
+            .filter(|lease| lease.expires > now)
+            .map(|mut lease| lease.tokens += cost)
+            .is_some();
+        if returned {
+            return Ok(());
+        }
+        // Without a valid lease, the tokens are owed to the store.
+        self.inner.refund(key, count, per, cost).await
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        let now = self.clock.now();
+        let ids: Vec<_> = self
+            .leases
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        let mut result = Ok(());
+        for id in ids {
+            // Tokens of expired leases were returned by the store's refill already.
+            let tokens = self.leases.get_mut(&id).map_or(0, |mut lease| {
+                let tokens = std::mem::take(&mut lease.tokens);
+                if lease.expires > now {
+                    tokens
+                } else {
+                    0
+                }
+            });
+            if tokens > 0 {
+                let (key, count, per) = &id;
+                if let Err(error) = self.inner.refund(key, *count, *per, tokens).await {
+                    result = Err(error);
+                }
+            }
+        }
+        result.and(self.inner.flush().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryBackend;
+    use http::Method;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A backend counting the calls made to the wrapped one.
+    #[derive(Default)]
+    struct Counting(MemoryBackend<Method>, AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl Backend<Method> for Counting {
+        async fn acquire(
+            &self,
+            key: &Method,
+            count: usize,
+            per: u64,
+            cost: usize,
+        ) -> Result<Decision, BackendError> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.acquire(key, count, per, cost).await
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_leased_tokens_locally() {
+        let store = Arc::new(Counting::default());
+        let backend = LeasingBackend::new(store.clone()).batch(5);
+
+        for _ in 0..10 {
+            let decision = backend.acquire(&Method::GET, 10, 60_000, 1).await;
+            assert!(decision.expect("Failed to acquire").allowed);
+            tokio::task::yield_now().await;
+        }
+        let decision = backend.acquire(&Method::GET, 10, 60_000, 1).await;
+        assert!(!decision.expect("Failed to acquire").allowed);
+        assert!(store.1.load(Ordering::SeqCst) <= 5);
+    }
+}
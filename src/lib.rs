@@ -1,60 +1,315 @@
 #![doc = include_str!("../README.md")]
 #![deny(unsafe_code, missing_docs, clippy::unwrap_used)]
 
+#[cfg(feature = "axum")]
+mod admin;
+mod aimd;
+pub mod algorithm;
+mod anomaly;
+mod backend;
+mod ban;
+mod batch;
+mod cardinality;
+mod challenge;
+mod chaos;
+mod circuit;
+mod clock;
+mod concurrency;
+#[cfg(feature = "cookie")]
+mod cookie_key;
+pub mod core;
+mod cost;
+mod crdt;
+mod cron;
+#[cfg(feature = "dynamodb")]
+mod dynamodb;
+#[cfg(feature = "tokio")]
+mod egress;
+#[cfg(feature = "encryption")]
+mod encryption;
+#[cfg(feature = "etcd")]
+mod etcd;
+mod events;
+#[cfg(feature = "gossip")]
+mod gossip;
+mod gradient;
+mod grpc;
+mod hashing;
+mod header_key;
+mod headers;
+#[cfg(feature = "redis")]
+mod hybrid;
+mod info;
+#[cfg(feature = "axum")]
+mod ip;
+#[cfg(feature = "jwt")]
+mod jwt;
 mod key;
+#[cfg(feature = "tokio")]
+mod leasing;
+mod lockout;
+#[cfg(feature = "tracing")]
+mod logging;
+#[cfg(feature = "axum-login")]
+mod login;
+mod map;
+#[cfg(feature = "memcached")]
+mod memcached;
+mod migration;
+#[cfg(feature = "moka")]
+mod moka;
+mod nested;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod priority;
+mod query_key;
+mod quota;
+mod read_write;
+#[cfg(feature = "redis")]
+mod redis;
+mod registry;
+mod rejection;
+pub mod replay;
+#[cfg(feature = "tower-sessions")]
+mod session;
+mod sharded;
+mod shedding;
+mod sketch;
+#[cfg(feature = "sled")]
+mod sled;
+mod snapshot;
+mod soft;
+mod states;
+#[cfg(feature = "tokio")]
+mod tarpit;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod timetable;
+mod upload;
+#[cfg(all(test, feature = "model-check"))]
+mod verification;
 
+#[cfg(feature = "axum")]
+pub use admin::{AdminRole, AdminRouter};
+pub use aimd::Aimd;
+pub use anomaly::{Spike, SpikeDetector};
+#[cfg(feature = "tokio")]
+pub use backend::TimeoutBackend;
+pub use backend::{
+    Backend, BackendError, Decision, FailurePolicy, MemoryBackend, PeriodSplitBackend,
+};
+#[cfg(feature = "serde")]
+pub use ban::FileBanStore;
+pub use ban::{BanEntry, BanList, BanStore};
+pub use batch::BatchLimitExceeded;
+pub use cardinality::{CardinalityLimit, CardinalityState};
+pub use challenge::Challenge;
+pub use chaos::Chaos;
+pub use circuit::{CircuitBreaker, CircuitState};
+pub use clock::{Clock, SystemClock};
+pub use concurrency::ConcurrencyLimit;
+#[cfg(feature = "cookie")]
+pub use cookie_key::{CookieKey, KeyCookie};
+pub use cost::{PostCharge, PostChargeLayer, RequestCost};
+pub use crdt::{CounterState, CrdtBackend};
+pub use cron::{CronSchedule, InvalidCron};
+#[cfg(feature = "dynamodb")]
+pub use dynamodb::DynamoDbBackend;
+#[cfg(feature = "tokio")]
+pub use egress::{EgressLimit, EgressLimitLayer, PacedBody};
+#[cfg(feature = "encryption")]
+pub use encryption::SnapshotKey;
+#[cfg(feature = "etcd")]
+pub use etcd::EtcdBackend;
+pub use events::{LimitEvent, LimitEvents, LimitOutcome};
+#[cfg(feature = "gossip")]
+pub use gossip::GossipBackend;
+pub use gradient::{GradientLimit, GradientLimitLayer, GradientLimiter, GradientPermit};
+pub use hashing::KeyHasher;
+pub use header_key::{HeaderKey, KeyHeader};
+pub use headers::{
+    LimitWarning, LimitWarningFuture, LimitWarningLayer, QuotaHeaders, QuotaHeadersFuture,
+    QuotaHeadersLayer, SoftLimitWarning, X_RATELIMIT_WARNING,
+};
+#[cfg(feature = "redis")]
+pub use hybrid::HybridBackend;
+pub use info::RateLimitInfo;
+#[cfg(feature = "axum")]
+pub use ip::{ClientIp, ForwardedHeader, ForwardedIp, Subnet, TrustedProxies};
+#[cfg(feature = "jwt")]
+pub use jwt::{JwtClaim, JwtDecoder};
+#[cfg(feature = "axum")]
+pub use key::RoutePath;
+pub use key::{Group, Host, Scope};
+#[cfg(feature = "tokio")]
+pub use leasing::LeasingBackend;
+pub use lockout::Lockout;
+#[cfg(feature = "tracing")]
+pub use logging::DecisionLog;
+#[cfg(feature = "axum-login")]
+pub use login::LoginUser;
+#[cfg(feature = "memcached")]
+pub use memcached::MemcachedBackend;
+pub use migration::{MigratingBackend, MigrationPhase};
+#[cfg(feature = "moka")]
+pub use moka::MokaBackend;
+pub use nested::{Global, GlobalLimit, Member, NestedLimit};
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresBackend;
+pub use priority::Priority;
+pub use query_key::{KeyParam, QueryKey};
+pub use quota::{QuotaBackend, QuotaStore};
+pub use read_write::{Access, ReadWriteLimit};
+#[cfg(feature = "redis")]
+pub use redis::RedisBackend;
+pub use registry::{DeclaredLimit, LimitRegistry};
+pub use rejection::{
+    RateLimitExceeded, RateLimitHeaders, Refusal, RATELIMIT, RATELIMIT_POLICY, X_RATELIMIT_LIMIT,
+    X_RATELIMIT_REMAINING, X_RATELIMIT_RESET,
+};
+#[cfg(feature = "tower-sessions")]
+pub use session::SessionKey;
+pub use sharded::ShardedBackend;
+pub use shedding::{Load, LoadShedder};
+pub use sketch::SketchBackend;
+#[cfg(feature = "sled")]
+pub use sled::SledBackend;
+pub use snapshot::BucketSnapshot;
+#[cfg(feature = "serde")]
+pub use snapshot::SnapshotFile;
+pub use soft::SoftLimit;
+pub use states::LimitStates;
+#[cfg(feature = "axum")]
+pub use states::LimitedRouter;
+#[cfg(feature = "tokio")]
+pub use tarpit::Tarpit;
+pub use timetable::Timetable;
+pub use upload::{ChargedBody, UploadLimit, UploadLimitExceeded, UploadLimitLayer};
+
+/// Items used by the expansions of this crate's macros.
+#[doc(hidden)]
+pub mod __private {
+    pub use axum_core::response::{IntoResponse, Response};
+}
+
+use crate::map::Map;
+use algorithm::{
+    Algorithm, CalendarCounter, KeyExtractor, RequestLog, VirtualSchedule, WindowCounter,
+};
 use axum_core::extract::{FromRef, FromRequestParts};
 use axum_core::response::{IntoResponse, Response};
-use dashmap::DashMap;
+use cost::ChargeSlot;
+use cron::ScheduledCounter;
+use headers::{Quota, QuotaSlot};
 use http::request::Parts;
 use http::StatusCode;
+use rejection::{Format, Responder};
+use std::collections::HashSet;
+use std::convert::Infallible;
 use std::error::Error;
 use std::fmt::Display;
 use std::hash::Hash;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A boxed future, as returned by the services and hooks of this crate.
+pub(crate) type BoxFuture<T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send>>;
 
 /// Represents a rate limit configuration with generic parameters for count and time period.
 /// This struct uses generics to allow flexible integration with any extractor that implements the `Key` trait.
-#[derive(Debug, Clone, Copy, Default)]
-pub struct Limit<const COUNT: usize, const PER: u64, K>(pub K::Extractor)
+/// The [`Algorithm`] enforcing the limit defaults to the [`TokenBucket`](algorithm::TokenBucket).
+///
+/// The quota of the key after the request is inserted into the request extensions as a [`RateLimitInfo`], which
+/// handlers extract after the limit.
+pub struct Limit<const COUNT: usize, const PER: u64, K, A = algorithm::TokenBucket>(
+    pub <K as KeyExtractor<A>>::Extractor,
+)
+where
+    K: Key,
+    A: Algorithm;
+
+impl<const COUNT: usize, const PER: u64, K, A> std::fmt::Debug for Limit<COUNT, PER, K, A>
+where
+    K: Key,
+    K::Extractor: std::fmt::Debug,
+    A: Algorithm,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Limit").field(&self.0).finish()
+    }
+}
+
+impl<const COUNT: usize, const PER: u64, K, A> Clone for Limit<COUNT, PER, K, A>
+where
+    K: Key,
+    K::Extractor: Clone,
+    A: Algorithm,
+{
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<const COUNT: usize, const PER: u64, K, A> Copy for Limit<COUNT, PER, K, A>
+where
+    K: Key,
+    K::Extractor: Copy,
+    A: Algorithm,
+{
+}
+
+impl<const COUNT: usize, const PER: u64, K, A> Default for Limit<COUNT, PER, K, A>
 where
-    K: Key;
+    K: Key,
+    K::Extractor: Default,
+    A: Algorithm,
+{
+    fn default() -> Self {
+        Self(K::Extractor::default())
+    }
+}
 
 /// Rate limit configured to apply per second.
-pub type LimitPerSecond<const COUNT: usize, K> = Limit<COUNT, 1000, K>;
+pub type LimitPerSecond<const COUNT: usize, K, A = algorithm::TokenBucket> =
+    Limit<COUNT, 1000, K, A>;
 
 /// Rate limit configured to apply per minute.
-pub type LimitPerMinute<const COUNT: usize, K> = Limit<COUNT, 60_000, K>;
+pub type LimitPerMinute<const COUNT: usize, K, A = algorithm::TokenBucket> =
+    Limit<COUNT, 60_000, K, A>;
 
 /// Rate limit configured to apply per hour.
-pub type LimitPerHour<const COUNT: usize, K> = Limit<COUNT, 3_600_000, K>;
+pub type LimitPerHour<const COUNT: usize, K, A = algorithm::TokenBucket> =
+    Limit<COUNT, 3_600_000, K, A>;
 
 /// Rate limit configured to apply per day.
-pub type LimitPerDay<const COUNT: usize, K> = Limit<COUNT, 86_400_000, K>;
+pub type LimitPerDay<const COUNT: usize, K, A = algorithm::TokenBucket> =
+    Limit<COUNT, 86_400_000, K, A>;
 
-impl<const COUNT: usize, const PER: u64, K> AsRef<K::Extractor> for Limit<COUNT, PER, K>
+impl<const COUNT: usize, const PER: u64, K, A> AsRef<K::Extractor> for Limit<COUNT, PER, K, A>
 where
     K: Key,
+    A: Algorithm,
 {
     fn as_ref(&self) -> &K::Extractor {
         &self.0
     }
 }
 
-impl<const COUNT: usize, const PER: u64, K> AsMut<K::Extractor> for Limit<COUNT, PER, K>
+impl<const COUNT: usize, const PER: u64, K, A> AsMut<K::Extractor> for Limit<COUNT, PER, K, A>
 where
     K: Key,
+    A: Algorithm,
 {
     fn as_mut(&mut self) -> &mut K::Extractor {
         &mut self.0
     }
 }
 
-impl<const COUNT: usize, const PER: u64, K> Deref for Limit<COUNT, PER, K>
+impl<const COUNT: usize, const PER: u64, K, A> Deref for Limit<COUNT, PER, K, A>
 where
     K: Key,
+    A: Algorithm,
 {
     type Target = K::Extractor;
 
@@ -63,18 +318,20 @@ where
     }
 }
 
-impl<const COUNT: usize, const PER: u64, K> DerefMut for Limit<COUNT, PER, K>
+impl<const COUNT: usize, const PER: u64, K, A> DerefMut for Limit<COUNT, PER, K, A>
 where
     K: Key,
+    A: Algorithm,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
 
-impl<const COUNT: usize, const PER: u64, K> Display for Limit<COUNT, PER, K>
+impl<const COUNT: usize, const PER: u64, K, A> Display for Limit<COUNT, PER, K, A>
 where
     K: Key,
+    A: Algorithm,
     K::Extractor: Display,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -82,9 +339,10 @@ where
     }
 }
 
-impl<const COUNT: usize, const PER: u64, K> Limit<COUNT, PER, K>
+impl<const COUNT: usize, const PER: u64, K, A> Limit<COUNT, PER, K, A>
 where
     K: Key,
+    A: Algorithm,
 {
     /// Returns the count of requests allowed within the specified period.
     pub const fn count() -> usize {
@@ -104,74 +362,325 @@ where
 
 /// Trait defining the requirements for a key extractor, which is used to uniquely identify limit subjects
 /// and extract rate limit parameters dynamically in request processing.
+///
+/// Keys are `Clone` because the state stores its own copy of each key next to the one being checked, e.g. to
+/// count the strikes of a [`BanList`] or to report a key to the [`Lockout`] and event hooks.
 #[async_trait::async_trait]
-pub trait Key: Eq + Hash + Send + Sync {
+pub trait Key: Eq + Hash + Clone + Send + Sync {
     /// The `Extractor` associated type represents a component capable of extracting key-specific information from request parts.
     /// This information is then used to manage and enforce rate limits dynamically within the application.
     type Extractor;
     /// Creates an instance of `Self` from the provided extractor reference, allowing extraction of key data.
     fn from_extractor(extractor: &Self::Extractor) -> Self;
+
+    /// Builds the response to a request of this key exceeding its rate limit, including lockouts and [`Chaos`]
+    /// rejections, e.g. with tenant-specific branding or language. Returns `None` by default, leaving the response
+    /// to the [`LimitState::with_rejection_response`] responder or the default `429 Too Many Requests`. Bans, load
+    /// shedding and backend failures are not rate limit rejections and keep their default responses.
+    fn rejection(&self, exceeded: &RateLimitExceeded) -> Option<Response> {
+        let _ = exceeded;
+        None
+    }
 }
 
 /// Implements a token bucket for rate limiting.
 /// This struct manages the tokens for rate limiting, providing methods to acquire and refill tokens based on time elapsed.
+#[derive(Clone)]
 struct TokenBucket {
     tokens: usize,
+    capacity: usize,
+    rate: usize,
+    smooth: bool,
+    debt: usize,
+    max_debt: usize,
     last_refill_time: Instant,
     refill_duration: Duration,
 }
 
 impl TokenBucket {
-    /// Constructs a new `TokenBucket` with a specific number of tokens and a refill period.
-    fn new(tokens: impl Into<usize>, per: impl Into<u64>) -> Self {
+    /// Constructs a new, full `TokenBucket` with a specific number of tokens and a refill period, starting at `now`.
+    fn new(tokens: impl Into<usize>, per: impl Into<u64>, now: Instant) -> Self {
+        let tokens = tokens.into();
         Self {
-            tokens: tokens.into(),
-            last_refill_time: Instant::now(),
+            tokens,
+            capacity: tokens,
+            rate: tokens,
+            smooth: false,
+            debt: 0,
+            max_debt: 0,
+            last_refill_time: now,
             refill_duration: Duration::from_millis(per.into()),
         }
     }
 
-    /// Attempts to acquire a token. Returns `true` if a token was successfully acquired.
-    fn try_acquire(&mut self) -> bool {
-        self.refill();
-        if self.tokens > 0 {
-            self.tokens -= 1;
+    /// Constructs a new `TokenBucket` holding only `remaining` of its `capacity` tokens, starting at `now`.
+    fn with_remaining(capacity: usize, per: u64, remaining: usize, now: Instant) -> Self {
+        Self {
+            tokens: remaining.min(capacity),
+            ..Self::new(capacity, per, now)
+        }
+    }
+
+    /// Changes the capacity of the bucket to `capacity` tokens and its refill to `rate` tokens per period.
+    /// Tokens beyond the new capacity are dropped.
+    fn resize(&mut self, capacity: usize, rate: usize) {
+        self.capacity = capacity;
+        self.rate = rate;
+        self.tokens = self.tokens.min(capacity);
+    }
+
+    /// Attempts to acquire a token at the given point in time. Returns `true` if a token was successfully acquired.
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        self.try_acquire_many_at(1, now)
+    }
+
+    /// Attempts to acquire `cost` tokens at once at the given point in time. Either all or none are acquired.
+    /// Tokens missing from the bucket are borrowed from future refills, as long as the debt stays within its bound.
+    fn try_acquire_many_at(&mut self, cost: usize, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= cost {
+            self.tokens -= cost;
+            true
+        } else if cost - self.tokens <= self.max_debt.saturating_sub(self.debt) {
+            self.debt += cost - self.tokens;
+            self.tokens = 0;
             true
         } else {
             false
         }
     }
 
+    /// Attempts to acquire `cost` tokens at the given point in time, reporting the state of the bucket afterwards.
+    fn decide_at(&mut self, cost: usize, now: Instant) -> Decision {
+        let allowed = self.try_acquire_many_at(cost, now);
+        Decision {
+            allowed,
+            limit: self.capacity,
+            remaining: self.tokens,
+            reset: self.reset_at(now),
+        }
+    }
+
+    /// Returns the time from `now` until the bucket is refilled: until the next period for whole-period refills,
+    /// or until the bucket is full again for smooth refills.
+    fn reset_at(&self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last_refill_time);
+        if !self.smooth || self.rate == 0 {
+            return self.refill_duration.saturating_sub(elapsed);
+        }
+        let missing = (self.capacity.saturating_sub(self.tokens) + self.debt) as u128;
+        let nanos = (missing * self.refill_duration.as_nanos() / self.rate as u128)
+            .saturating_sub(elapsed.as_nanos());
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    /// Returns the time from `now` until `cost` tokens could be acquired at once, zero if they can already be, or
+    /// `None` if they never fit into the bucket.
+    fn retry_after_at(&self, cost: usize, now: Instant) -> Option<Duration> {
+        let mut bucket = self.clone();
+        bucket.refill(now);
+        // Refills repay the debt first, then the missing tokens, beyond what may be borrowed.
+        let missing = (cost + bucket.debt).saturating_sub(bucket.tokens + bucket.max_debt);
+        if missing == 0 {
+            return Some(Duration::ZERO);
+        }
+        if bucket.rate == 0 || cost > bucket.capacity + bucket.max_debt {
+            return None;
+        }
+        let elapsed = now.saturating_duration_since(bucket.last_refill_time);
+        let per = bucket.refill_duration.as_nanos();
+        let nanos = if bucket.smooth {
+            (missing as u128 * per).div_ceil(bucket.rate as u128)
+        } else {
+            missing.div_ceil(bucket.rate) as u128 * per
+        }
+        .saturating_sub(elapsed.as_nanos());
+        Some(Duration::from_nanos(
+            u64::try_from(nanos).unwrap_or(u64::MAX),
+        ))
+    }
+
+    /// Returns `cost` previously acquired tokens at the given point in time, without exceeding the capacity.
+    fn refund(&mut self, cost: usize, now: Instant) {
+        self.refill(now);
+        self.deposit(cost);
+    }
+
+    /// Adds `tokens` to the bucket, repaying any debt first, without exceeding the capacity.
+    fn deposit(&mut self, tokens: usize) {
+        let repaid = tokens.min(self.debt);
+        self.debt -= repaid;
+        self.tokens = self
+            .tokens
+            .saturating_add(tokens - repaid)
+            .min(self.capacity);
+    }
+
     /// Refills tokens based on time elapsed since the last refill.
-    fn refill(&mut self) {
-        let now = Instant::now();
-        let elapsed = now.duration_since(self.last_refill_time);
+    /// Every full refill period adds `rate` tokens, but the bucket never holds more than `capacity`.
+    fn refill(&mut self, now: Instant) {
+        if self.smooth {
+            return self.refill_smoothly(now);
+        }
+        let elapsed = now.saturating_duration_since(self.last_refill_time);
 
         // Calculate the elapsed time in milliseconds
         if elapsed >= self.refill_duration {
             let elapsed_millis = elapsed.as_millis() as u64; // Convert elapsed time to milliseconds
             let refill_duration_millis = self.refill_duration.as_millis() as u64; // Convert refill duration to milliseconds
+            if refill_duration_millis == 0 {
+                self.tokens = self.capacity;
+                self.debt = 0;
+                self.last_refill_time = now;
+                return;
+            }
 
-            // Calculate the number of new tokens to add
-            let new_tokens = (elapsed_millis / refill_duration_millis) as usize;
-            self.tokens += new_tokens;
+            // Calculate the number of new tokens to add, saturating instead of overflowing after long idle periods
+            let periods =
+                usize::try_from(elapsed_millis / refill_duration_millis).unwrap_or(usize::MAX);
+            let new_tokens = periods.saturating_mul(self.rate);
+            self.deposit(new_tokens);
 
             // Reset the last refill time to avoid under-refilling tokens
             self.last_refill_time =
                 now - Duration::from_millis(elapsed_millis % refill_duration_millis);
         }
     }
+
+    /// Refills tokens continuously at `rate` tokens per period, accruing whole tokens as soon as enough time passed
+    /// for them. The time spent on a partial token is kept for the next refill.
+    fn refill_smoothly(&mut self, now: Instant) {
+        let per = self.refill_duration.as_nanos();
+        if per == 0 {
+            self.tokens = self.capacity;
+            self.debt = 0;
+        }
+        if self.tokens >= self.capacity {
+            // A full bucket accrues nothing, so the next token starts accruing now.
+            self.last_refill_time = now;
+            return;
+        }
+        let elapsed = now
+            .saturating_duration_since(self.last_refill_time)
+            .as_nanos();
+        let new_tokens = elapsed * self.rate as u128 / per;
+        if new_tokens == 0 {
+            return;
+        }
+        let missing = (self.capacity - self.tokens + self.debt) as u128;
+        if new_tokens >= missing {
+            self.tokens = self.capacity;
+            self.debt = 0;
+            self.last_refill_time = now;
+        } else {
+            self.deposit(new_tokens as usize);
+            let accrued = new_tokens * per / self.rate as u128;
+            self.last_refill_time += Duration::from_nanos(accrued as u64);
+        }
+    }
+}
+
+/// Writes a snapshot of a state's buckets to its [`SnapshotFile`].
+#[cfg(feature = "serde")]
+type SaveSnapshot<K> = dyn Fn(&[BucketSnapshot<K>]) -> std::io::Result<()> + Send + Sync;
+
+/// The number of replicas of an application sharing its limits, see [`LimitState::with_replicas`].
+#[derive(Clone)]
+enum Replicas {
+    Static(usize),
+    #[cfg(feature = "tokio")]
+    Watch(tokio::sync::watch::Receiver<usize>),
+}
+
+impl Replicas {
+    /// Returns the share of a limit of `count` requests enforced by each replica, at least one request unless the
+    /// limit is zero.
+    fn share(&self, count: usize) -> usize {
+        let replicas = match self {
+            Self::Static(replicas) => *replicas,
+            #[cfg(feature = "tokio")]
+            Self::Watch(replicas) => *replicas.borrow(),
+        };
+        if count == 0 {
+            0
+        } else {
+            (count / replicas.max(1)).max(1)
+        }
+    }
+}
+
+/// A ramp of limits from a fraction of their count up to the full count, see [`LimitState::with_warm_up`].
+#[derive(Clone, Copy)]
+struct WarmUp {
+    start: Instant,
+    duration: Duration,
+    from: f64,
+}
+
+impl WarmUp {
+    /// Returns the effective limit of `count` requests at `now`, at least one request unless the limit is zero.
+    fn limit(&self, count: usize, now: Instant) -> usize {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return count;
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        let fraction = self.from + (1.0 - self.from) * progress;
+        ((count as f64 * fraction) as usize).clamp(count.min(1), count)
+    }
 }
 
+/// Formats keys for rejections, see [`LimitState::with_key_format`].
+type KeyFormat<K> = dyn Fn(&K) -> String + Send + Sync;
+
 /// Manages the state of rate limits for various keys.
-/// This struct holds a concurrent map of keys to their corresponding `TokenBucket` instances,
-/// enabling efficient state management across asynchronous tasks.
+/// By default, this struct holds a concurrent map of keys to their corresponding `TokenBucket` instances,
+/// enabling efficient state management across asynchronous tasks; a [`Backend`] can be installed to store
+/// the buckets elsewhere.
 #[derive(Clone)]
 pub struct LimitState<K>
 where
     K: Key,
 {
-    rate_limits: Arc<DashMap<K, TokenBucket>>,
+    rate_limits: MemoryBackend<K>,
+    windows: Arc<Map<K, WindowCounter>>,
+    logs: Arc<Map<K, RequestLog>>,
+    schedules: Arc<Map<K, VirtualSchedule>>,
+    paces: Arc<Map<K, VirtualSchedule>>,
+    calendars: Arc<Map<K, CalendarCounter>>,
+    in_flight: Arc<Map<K, usize>>,
+    algorithm: algorithm::Kind,
+    replicas: Option<Replicas>,
+    warm_up: Option<WarmUp>,
+    timetable: Option<Arc<Timetable>>,
+    reset_schedule: Option<Arc<CronSchedule>>,
+    quotas: Arc<Map<K, ScheduledCounter>>,
+    aimd: Option<Arc<Aimd<K>>>,
+    backend: Option<Arc<dyn Backend<K>>>,
+    failure_policy: FailurePolicy,
+    bans: Arc<BanList<K>>,
+    spikes: Option<Arc<SpikeDetector<K>>>,
+    chaos: Option<Arc<Chaos<K>>>,
+    challenge: Option<Arc<Challenge<K>>>,
+    lockout: Option<Arc<Lockout<K>>>,
+    priority: Option<Arc<Priority<K>>>,
+    rate_limit_headers: RateLimitHeaders,
+    rejection_response: Option<Responder>,
+    html_rejections: bool,
+    grpc_rejections: bool,
+    key_format: Option<Arc<KeyFormat<K>>>,
+    shedder: Option<Arc<LoadShedder>>,
+    clock: Arc<dyn Clock>,
+    registry: Option<LimitRegistry>,
+    events: Option<Arc<LimitEvents<K>>>,
+    #[cfg(feature = "serde")]
+    snapshot_file: Option<Arc<SaveSnapshot<K>>>,
+    #[cfg(feature = "tokio")]
+    tarpit: Option<Arc<Tarpit<K>>>,
+    #[cfg(feature = "tokio")]
+    max_delay: Option<Duration>,
+    #[cfg(feature = "tokio")]
+    watchers: Arc<Map<K, tokio::sync::watch::Sender<Option<Decision>>>>,
 }
 
 impl<K> Default for LimitState<K>
@@ -181,7 +690,45 @@ where
     /// Constructs a new `LimitState` with an empty map of rate limits.
     fn default() -> Self {
         Self {
-            rate_limits: Arc::new(DashMap::new()),
+            rate_limits: MemoryBackend::default(),
+            windows: Arc::new(Map::new()),
+            logs: Arc::new(Map::new()),
+            schedules: Arc::new(Map::new()),
+            paces: Arc::new(Map::new()),
+            calendars: Arc::new(Map::new()),
+            in_flight: Arc::new(Map::new()),
+            algorithm: algorithm::Kind::default(),
+            replicas: None,
+            warm_up: None,
+            timetable: None,
+            reset_schedule: None,
+            quotas: Arc::new(Map::new()),
+            aimd: None,
+            backend: None,
+            failure_policy: FailurePolicy::default(),
+            bans: Arc::new(BanList::default()),
+            spikes: None,
+            chaos: None,
+            challenge: None,
+            lockout: None,
+            priority: None,
+            rate_limit_headers: RateLimitHeaders::default(),
+            rejection_response: None,
+            html_rejections: false,
+            grpc_rejections: false,
+            key_format: None,
+            shedder: None,
+            clock: Arc::new(SystemClock),
+            registry: None,
+            events: None,
+            #[cfg(feature = "serde")]
+            snapshot_file: None,
+            #[cfg(feature = "tokio")]
+            tarpit: None,
+            #[cfg(feature = "tokio")]
+            max_delay: None,
+            #[cfg(feature = "tokio")]
+            watchers: Arc::new(Map::new()),
         }
     }
 }
@@ -190,155 +737,1916 @@ impl<K> LimitState<K>
 where
     K: Key,
 {
-    /// Checks and updates the rate limit for the given key, returning `true` if the request can proceed.
-    pub fn check(&self, key: K, count: usize, per: u64) -> bool {
-        let mut bucket = self
-            .rate_limits
-            .entry(key)
-            .or_insert_with(|| TokenBucket::new(count, per));
-        bucket.try_acquire()
+    /// Replaces the ban list consulted before every rate limit check.
+    pub fn with_ban_list(mut self, bans: BanList<K>) -> Self {
+        self.bans = Arc::new(bans);
+        self
     }
-}
 
-#[async_trait::async_trait]
-impl<const C: usize, const P: u64, K, S> FromRequestParts<S> for Limit<C, P, K>
-where
-    LimitState<K>: FromRef<S>,
-    S: Send + Sync,
-    K: Key,
-    K::Extractor: FromRequestParts<S>,
-{
-    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+    /// Returns the ban list consulted before every rate limit check.
+    pub fn ban_list(&self) -> &BanList<K> {
+        &self.bans
+    }
 
-    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let key_extractor = match K::Extractor::from_request_parts(parts, state).await {
-            Ok(ke) => ke,
-            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
-        };
+    /// Installs a detector that is notified of every checked request, so sudden spikes in a key's
+    /// request rate can be acted upon before its limit is exhausted.
+    pub fn with_spike_detector(mut self, detector: SpikeDetector<K>) -> Self {
+        self.spikes = Some(Arc::new(detector));
+        self
+    }
 
-        let limit_state: LimitState<K> = FromRef::from_ref(state);
-        let key = K::from_extractor(&key_extractor);
-        if limit_state.check(key, C, P) {
-            Ok(Self(key_extractor))
-        } else {
-            Err(LimitRejection::RateLimitExceeded)
+    /// Enables fault injection, randomly rejecting requests as if their rate limit had been exceeded.
+    /// Intended for testing client backoff behavior; injected rejections do not consume tokens.
+    pub fn with_chaos(mut self, chaos: Chaos<K>) -> Self {
+        self.chaos = Some(Arc::new(chaos));
+        self
+    }
+
+    /// Answers requests exceeding their limit with a challenge, such as a CAPTCHA, instead of rejecting them.
+    pub fn with_challenge(mut self, challenge: Challenge<K>) -> Self {
+        self.challenge = Some(Arc::new(challenge));
+        self
+    }
+
+    /// Reports that the client behind `key` has solved a challenge, granting it the challenge's allowance of
+    /// requests beyond its limit. Does nothing if no challenge is installed.
+    pub fn challenge_solved(&self, key: K) {
+        if let Some(challenge) = &self.challenge {
+            challenge.solved(key);
         }
     }
-}
 
-/// Enumerates possible failure modes for rate limiting when extracting from request parts.
-#[derive(Debug)]
-pub enum LimitRejection<R> {
-    /// Indicates a failure during key extraction, storing the underlying rejection reason.
-    KeyExtractionFailure(R),
+    /// Blocks keys that exceeded their limit for the full cooldown of `lockout`,
+    /// instead of letting them continue once their bucket refills.
+    pub fn with_lockout(mut self, lockout: Lockout<K>) -> Self {
+        self.lockout = Some(Arc::new(lockout));
+        self
+    }
 
-    /// Indicates that the rate limit has been exceeded.
-    RateLimitExceeded,
-}
+    /// Reserves part of every limit for the high-priority requests classified by `priority`.
+    pub fn with_priority(mut self, priority: Priority<K>) -> Self {
+        self.priority = Some(Arc::new(priority));
+        self
+    }
 
-impl<R: Display> Display for LimitRejection<R> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            LimitRejection::KeyExtractionFailure(r) => write!(f, "{r}"),
-            LimitRejection::RateLimitExceeded => write!(f, "Rate limit exceeded."),
-        }
+    /// Rejects every request with `503 Service Unavailable` while `shedder` reports the system as overloaded.
+    pub fn with_load_shedding(mut self, shedder: LoadShedder) -> Self {
+        self.shedder = Some(Arc::new(shedder));
+        self
     }
-}
 
-impl<R: Error + 'static> Error for LimitRejection<R> {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        match self {
-            LimitRejection::KeyExtractionFailure(ve) => Some(ve),
-            LimitRejection::RateLimitExceeded => None,
+    /// Replaces the clock used to refill buckets, e.g. with a `MockClock` in tests.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Records every limit enforced through this state in `registry`, which may be shared with other states.
+    pub fn with_registry(mut self, registry: LimitRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Grants keys `grace` requests beyond their limit for their first period, so new clients doing an initial sync
+    /// are not throttled immediately. Only applies to the in-memory buckets.
+    pub fn with_grace(mut self, grace: usize) -> Self {
+        self.rate_limits = self.rate_limits.with_grace(grace);
+        self
+    }
+
+    /// Lets keys borrow up to `max` tokens from future refills once their bucket is empty, serving occasional
+    /// important requests beyond the limit instead of rejecting them. Borrowed tokens are repaid first by the next
+    /// refills, so the key's sustained rate stays within its limit. Only applies to the in-memory token buckets.
+    pub fn with_debt(mut self, max: usize) -> Self {
+        self.rate_limits = self.rate_limits.with_debt(max);
+        self
+    }
+
+    /// Rolls up to `max` tokens a key left unused in one period over into the next, on top of its refill, for
+    /// products advertising that unused requests roll over. Only applies to the in-memory token buckets.
+    pub fn with_carry_over(mut self, max: usize) -> Self {
+        self.rate_limits = self.rate_limits.with_carry_over(max);
+        self
+    }
+
+    /// Starts the in-memory state of newly seen keys at `fraction` (between `0.0` for empty and `1.0` for full, the
+    /// default) of their limit, so cold keys ramp up instead of bursting their whole limit right away. Applies to all
+    /// algorithms.
+    pub fn with_initial_fill(mut self, fraction: f64) -> Self {
+        self.rate_limits = self.rate_limits.with_initial_fill(fraction);
+        self
+    }
+
+    /// Selects the header fields describing quotas in responses, the legacy `X-RateLimit-*` headers by default.
+    pub fn with_rate_limit_headers(mut self, headers: RateLimitHeaders) -> Self {
+        self.rate_limit_headers = headers;
+        self
+    }
+
+    /// Builds the responses to requests exceeding their rate limit with `responder`, e.g. to match the error envelope
+    /// of the application, instead of the default `429 Too Many Requests`. [`RateLimitExceeded::headers`] returns
+    /// the headers of the default response.
+    ///
+    /// Lockouts and [`Chaos`] rejections are built with `responder` too. Requests refused for other reasons than
+    /// their quota, i.e. bans, load shedding and backend failures, keep their default responses.
+    pub fn with_rejection_response(
+        mut self,
+        responder: impl Fn(&RateLimitExceeded) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        self.rejection_response = Some(Arc::new(responder));
+        self
+    }
+
+    /// Renders rate limit rejections as HTML pages for requests preferring `text/html`, e.g. on browser-facing routes.
+    /// Otherwise, rejections are rendered as JSON for requests preferring `application/json`, and as plain text.
+    pub fn with_html_rejections(mut self) -> Self {
+        self.html_rejections = true;
+        self
+    }
+
+    /// Answers gRPC requests exceeding their rate limit, e.g. of tonic services served through axum, with the
+    /// `RESOURCE_EXHAUSTED` gRPC status and a `google.rpc.RetryInfo` in its details, instead of an HTTP `429`.
+    /// Every other rejection is mapped to a gRPC status too: lockouts and chaos to `RESOURCE_EXHAUSTED`, bans to
+    /// `PERMISSION_DENIED`, and load shedding and backend failures to `UNAVAILABLE`.
+    pub fn with_grpc_rejections(mut self) -> Self {
+        self.grpc_rejections = true;
+        self
+    }
+
+    /// Formats the keys of rejected requests with `format` into the [`RateLimitExceeded::key`] of their rejection,
+    /// e.g. `|key| key.to_string()`, for middleware and logging layers acting on rejections. Keys are not formatted
+    /// by default, since they may identify users.
+    pub fn with_key_format(
+        mut self,
+        format: impl Fn(&K) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.key_format = Some(Arc::new(format));
+        self
+    }
+
+    /// Returns the responder to rejections of `key`, preferring the key's own rejections over the installed
+    /// responder and the default response.
+    fn responder(&self, key: &K) -> Responder
+    where
+        K: 'static,
+    {
+        let key = key.clone();
+        let fallback = self.rejection_response.clone();
+        Arc::new(move |exceeded: &RateLimitExceeded| {
+            key.rejection(exceeded).unwrap_or_else(|| match &fallback {
+                Some(responder) => responder(exceeded),
+                None => exceeded.default_response(),
+            })
+        })
+    }
+
+    /// Formats `key` with the installed key format, if any.
+    pub(crate) fn format_key(&self, key: &K) -> Option<String> {
+        self.key_format.as_ref().map(|format| format(key))
+    }
+
+    /// Reports every decision made through this state to `events`.
+    pub fn with_events(mut self, events: LimitEvents<K>) -> Self {
+        self.events = Some(Arc::new(events));
+        self
+    }
+
+    /// Stores buckets in `backend` instead of process memory.
+    ///
+    /// By default, errors reported by the backend do not reject requests: a failing store lets traffic through
+    /// rather than taking the application down with it. See [`with_failure_policy`](Self::with_failure_policy).
+    pub fn with_backend(mut self, backend: impl Backend<K> + 'static) -> Self {
+        self.backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// Divides every limit enforced by the in-memory buckets evenly among `replicas` instances of the application,
+    /// approximately enforcing it globally without a shared store. Each instance allows at least one request per
+    /// period, so the limit is exceeded if it is lower than the number of replicas. Also applies to the buckets
+    /// used with [`FailurePolicy::FallbackToLocal`].
+    pub fn with_replicas(mut self, replicas: usize) -> Self {
+        self.replicas = Some(Replicas::Static(replicas));
+        self
+    }
+
+    /// Divides limits like [`with_replicas`](Self::with_replicas), following the number of replicas published on
+    /// `replicas`, e.g. by a task watching the application's deployment. Buckets adopt a new share on their next
+    /// request.
+    #[cfg(feature = "tokio")]
+    pub fn with_replicas_from(mut self, replicas: tokio::sync::watch::Receiver<usize>) -> Self {
+        self.replicas = Some(Replicas::Watch(replicas));
+        self
+    }
+
+    /// Ramps every limit enforced by the in-memory state from `from` (between `0.0` and `1.0`) of its count up to the
+    /// full count over `duration`, starting now, to protect cold caches and downstream services after a deploy. Each
+    /// limit allows at least one request per period while warming up.
+    pub fn with_warm_up(mut self, duration: Duration, from: f64) -> Self {
+        self.warm_up = Some(WarmUp {
+            start: self.clock.now(),
+            duration,
+            from: from.clamp(0.0, 1.0),
+        });
+        self
+    }
+
+    /// Varies every limit enforced by the in-memory state by time of day or day of week, see [`Timetable`].
+    pub fn with_timetable(mut self, timetable: Timetable) -> Self {
+        self.timetable = Some(Arc::new(timetable));
+        self
+    }
+
+    /// Adapts every limit enforced by the in-memory state to the feedback reported with
+    /// [`report_success`](Self::report_success) and [`report_failure`](Self::report_failure), see [`Aimd`].
+    pub fn with_aimd(mut self, aimd: Aimd<K>) -> Self {
+        self.aimd = Some(Arc::new(aimd));
+        self
+    }
+
+    /// Reports that a request of `key` succeeded downstream, raising its adapted limit. Does nothing if no [`Aimd`]
+    /// is installed.
+    pub fn report_success(&self, key: &K) {
+        if let Some(aimd) = &self.aimd {
+            aimd.success(key);
         }
     }
-}
 
-impl<R: IntoResponse> IntoResponse for LimitRejection<R> {
-    fn into_response(self) -> Response {
-        match self {
-            LimitRejection::KeyExtractionFailure(rejection) => rejection.into_response(),
-            LimitRejection::RateLimitExceeded => {
-                (StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded.").into_response()
-            }
+    /// Reports that a request of `key` failed downstream, e.g. with a `5xx` response, lowering its adapted limit.
+    /// Does nothing if no [`Aimd`] is installed.
+    pub fn report_failure(&self, key: &K) {
+        if let Some(aimd) = &self.aimd {
+            aimd.failure(key);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::routing::get;
-    use axum::Router;
-    use axum_test::TestServer;
-    use http::Uri;
-    use std::future::IntoFuture;
+    /// Resets the quotas of all keys at the times of `schedule` instead of every period: limits enforced by the
+    /// in-memory state then allow their count of requests between two resets, whatever their period. The time until
+    /// the next reset is reported as the reset of every decision, and so in response headers.
+    pub fn with_reset_schedule(mut self, schedule: CronSchedule) -> Self {
+        self.reset_schedule = Some(Arc::new(schedule));
+        self
+    }
 
-    #[tokio::test]
-    async fn limit() {
-        const TEST_ROUTE0: &str = "/limit0";
-        const TEST_ROUTE1: &str = "/limit1";
-        async fn handler0(Limit(_uri): Limit<1, 1, Uri>) -> impl IntoResponse {}
+    /// Returns the time of the next quota reset, if a reset schedule is installed.
+    pub fn next_reset(&self) -> Option<SystemTime> {
+        let schedule = self.reset_schedule.as_ref()?;
+        cron::system_time(schedule.next_after_millis(algorithm::unix_millis(self.clock.now()))?)
+    }
 
-        async fn handler1(Limit(_uri): Limit<3, 1, Uri>) -> impl IntoResponse {}
+    /// Sets how requests are handled when the backend fails to decide on them.
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
 
-        let my_app = Router::new()
-            .route(TEST_ROUTE0, get(handler0))
-            .route(TEST_ROUTE1, get(handler1))
-            .with_state(LimitState::default());
+    /// Delays requests progressively as their key approaches its limit, and keeps delaying instead of rejecting
+    /// requests beyond it.
+    #[cfg(feature = "tokio")]
+    pub fn with_tarpit(mut self, tarpit: Tarpit<K>) -> Self {
+        self.tarpit = Some(Arc::new(tarpit));
+        self
+    }
 
-        let server = TestServer::new(my_app).expect("Failed to create test server");
+    /// Shapes traffic like a leaky bucket: requests exceeding their limit wait until their key's quota is replenished
+    /// instead of being rejected, for up to `max_delay`. Requests that would have to wait longer are rejected right
+    /// away. Suits internal APIs whose callers prefer latency over errors.
+    #[cfg(feature = "tokio")]
+    pub fn with_shaping(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
 
-        let response = server.get(TEST_ROUTE0).await;
-        assert_eq!(response.status_code(), StatusCode::OK);
-        let response = server.get(TEST_ROUTE0).await;
-        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        let response = server.get(TEST_ROUTE0).await;
-        assert_eq!(response.status_code(), StatusCode::OK);
+    /// Seeds the in-memory buckets of known keys with their remaining quota for a limit of `count` requests per `per`
+    /// milliseconds, e.g. from quotas persisted before a restart. Existing buckets of these keys are replaced.
+    pub fn preload(&self, quotas: impl IntoIterator<Item = (K, usize)>, count: usize, per: u64) {
+        let now = self.clock.now();
+        for (key, remaining) in quotas {
+            self.rate_limits
+                .insert(key, TokenBucket::with_remaining(count, per, remaining, now));
+        }
+    }
 
-        let gets = vec![
-            server.get(TEST_ROUTE1).into_future(),
-            server.get(TEST_ROUTE1).into_future(),
-            server.get(TEST_ROUTE1).into_future(),
-        ];
+    /// Forgets the in-memory state of `key` and ends its lockout, so its next request starts with a fresh quota, e.g.
+    /// once support has resolved a customer's complaint. Buckets kept by an installed [`Backend`] are not affected.
+    pub fn reset(&self, key: &K) {
+        self.rate_limits.remove(key);
+        self.windows.remove(key);
+        self.logs.remove(key);
+        self.schedules.remove(key);
+        self.paces.remove(key);
+        self.calendars.remove(key);
+        self.quotas.remove(key);
+        if let Some(lockout) = &self.lockout {
+            lockout.unlock(key);
+        }
+    }
 
-        let resp = futures::future::join_all(gets).await;
-        assert!(!resp.iter().any(|r| !r.status_code().is_success()));
-        assert_eq!(
-            server.get(TEST_ROUTE1).await.status_code(),
-            StatusCode::TOO_MANY_REQUESTS
-        );
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        let response = server.get(TEST_ROUTE1).await;
-        assert_eq!(response.status_code(), StatusCode::OK);
+    /// Takes a snapshot of all in-memory buckets, e.g. to persist long-running quotas across restarts.
+    pub fn snapshot(&self) -> Vec<BucketSnapshot<K>> {
+        self.rate_limits.snapshot_at(self.clock.now())
     }
 
-    #[tokio::test]
-    async fn limit_per_100_millis() {
-        const TEST_ROUTE: &str = "/limit_per_100_millis";
+    /// Restores in-memory buckets from a snapshot taken with [`snapshot`](Self::snapshot). Existing buckets of these
+    /// keys are replaced. Time that passed between taking and restoring the snapshot does not refill the buckets.
+    pub fn restore(&self, snapshot: impl IntoIterator<Item = BucketSnapshot<K>>) {
+        let now = self.clock.now();
+        for bucket in snapshot {
+            let (key, bucket) = bucket.into_bucket(now);
+            self.rate_limits.insert(key, bucket);
+        }
+    }
 
-        async fn handler(Limit(_uri): Limit<1, 100, Uri>) -> impl IntoResponse {}
+    /// Writes a snapshot of the in-memory buckets to `file` whenever the state is [flushed](Self::flush).
+    #[cfg(feature = "serde")]
+    pub fn with_snapshot_file(mut self, file: SnapshotFile) -> Self
+    where
+        K: serde::Serialize,
+    {
+        self.snapshot_file = Some(Arc::new(move |buckets| file.save(buckets)));
+        self
+    }
 
-        let my_app = Router::new()
-            .route(TEST_ROUTE, get(handler))
-            .with_state(LimitState::default());
+    /// Persists outstanding state: flushes the backend, writes a snapshot to the state's snapshot file if one is
+    /// configured, and saves the [ban list](Self::ban_list) to its store. All are attempted even if another one
+    /// fails.
+    pub async fn flush(&self) -> Result<(), BackendError> {
+        #[cfg(feature = "serde")]
+        let saved = match &self.snapshot_file {
+            Some(save) => save(&self.snapshot()).map_err(BackendError::new),
+            None => Ok(()),
+        };
+        #[cfg(not(feature = "serde"))]
+        let saved = Ok(());
+        let banned = self.bans.save().map_err(BackendError::new);
+        let flushed = match &self.backend {
+            Some(backend) => backend.flush().await,
+            None => Ok(()),
+        };
+        saved.and(banned).and(flushed)
+    }
 
-        let server = TestServer::new(my_app).expect("Failed to create test server");
+    /// Returns a future that [flushes](Self::flush) the state once `signal` completes, so a deploy does not reset
+    /// long-running quotas. It can be passed to axum's `with_graceful_shutdown` in place of the signal itself;
+    /// errors are ignored, call [`flush`](Self::flush) directly to handle them.
+    ///
+    /// ```rust,no_run
+    /// # use axum_limit::LimitState;
+    /// # use std::future::Future;
+    /// # async fn example(
+    /// #     listener: tokio::net::TcpListener,
+    /// #     app: axum::Router,
+    /// #     shutdown: impl Future<Output = ()> + Send + 'static,
+    /// # ) {
+    /// let state = LimitState::<http::Method>::default();
+    /// axum::serve(listener, app)
+    ///     .with_graceful_shutdown(state.flush_on_shutdown(shutdown))
+    ///     .await
+    ///     .expect("server error");
+    /// # }
+    /// ```
+    pub fn flush_on_shutdown(
+        &self,
+        signal: impl std::future::Future<Output = ()> + Send + 'static,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static
+    where
+        K: 'static,
+    {
+        let state = self.clone();
+        async move {
+            signal.await;
+            let _ = state.flush().await;
+        }
+    }
 
-        // 第一次请求应该成功
-        let response = server.get(TEST_ROUTE).await;
-        assert_eq!(response.status_code(), StatusCode::OK);
+    /// Returns a future writing a snapshot of the in-memory buckets to `file` every `interval`, to be spawned on the
+    /// runtime. It completes once the state and all of its clones are dropped; failed writes are retried on the
+    /// next tick.
+    #[cfg(all(feature = "serde", feature = "tokio"))]
+    pub fn write_snapshots(
+        &self,
+        file: SnapshotFile,
+        interval: Duration,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static
+    where
+        K: serde::Serialize + 'static,
+    {
+        let buckets = self.rate_limits.downgrade();
+        let clock = self.clock.clone();
+        async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let Some(buckets) = buckets.upgrade() else {
+                    return;
+                };
+                let _ = file.save(&snapshot::snapshot(&buckets, clock.now()));
+            }
+        }
+    }
 
-        // 马上再发起一次请求应该被限制
-        let response = server.get(TEST_ROUTE).await;
-        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    /// Seeds the in-memory buckets of recently active keys at `fraction` (between `0.0` and `1.0`) of their capacity
+    /// for a limit of `count` requests per `per` milliseconds, so a restart does not hand every client a full burst
+    /// at the same time. Existing buckets of these keys are replaced.
+    pub fn prewarm(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        count: usize,
+        per: u64,
+        fraction: f64,
+    ) {
+        let remaining = (count as f64 * fraction.clamp(0.0, 1.0)) as usize;
+        self.preload(keys.into_iter().map(|key| (key, remaining)), count, per);
+    }
 
-        // 等待 100 毫秒
-        tokio::time::sleep(Duration::from_millis(100)).await;
+    /// Checks and updates the in-memory rate limit for the given key, returning `true` if the request can proceed.
+    pub fn check(&self, key: K, count: usize, per: u64) -> bool {
+        let now = self.clock.now();
+        let decision = self.acquire_local(&key, count, per, 1, now);
+        self.observe(&key, Some(decision), now);
+        decision.allowed
+    }
 
-        // 再次请求应该成功
-        let response = server.get(TEST_ROUTE).await;
-        assert_eq!(response.status_code(), StatusCode::OK);
+    /// Charges one request to each distinct key of `keys`, e.g. every resource ID of a batch request, against a limit
+    /// of `count` requests per `per` milliseconds. Keys repeated within the batch are only charged once.
+    ///
+    /// The keys are charged one after another, and if any of them exceeded its limit, the keys charged before it are
+    /// refunded. This compensation is best effort: concurrent requests may observe the charges of a batch that is
+    /// rejected later on, and keys whose refund fails at the backend stay charged.
+    ///
+    /// Keys for which the backend failed to decide are handled according to the [`FailurePolicy`]; with
+    /// [`FailurePolicy::FailClosed`], they are rejected like keys that exceeded their limit.
+    pub async fn charge_batch(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        count: usize,
+        per: u64,
+    ) -> Result<(), BatchLimitExceeded<K>> {
+        let mut seen = HashSet::new();
+        let mut charged = Vec::new();
+        for (index, key) in keys.into_iter().enumerate() {
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            match self.charge(&key, count, per, 1).await {
+                Ok(Some(decision)) if decision.allowed => charged.push(key),
+                Ok(None) => {}
+                Ok(Some(_)) | Err(_) => {
+                    for key in &charged {
+                        self.refund(key, count, per, 1).await;
+                    }
+                    return Err(BatchLimitExceeded { index, key });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribes to the state of `key`'s bucket, updated whenever a request of the key is checked,
+    /// e.g. to push a user's live quota consumption to a dashboard. Holds `None` until the next check.
+    #[cfg(feature = "tokio")]
+    pub fn watch(&self, key: K) -> tokio::sync::watch::Receiver<Option<Decision>> {
+        self.watchers
+            .entry(key)
+            .or_insert_with(|| tokio::sync::watch::channel(None).0)
+            .subscribe()
+    }
+
+    /// Returns the format of rejection bodies negotiated with the client of a request.
+    pub(crate) fn format(&self, parts: &Parts) -> Format {
+        Format::negotiate(&parts.headers, self.html_rejections, self.grpc_rejections)
+    }
+
+    /// Builds the rejection of a request of `key` whose quota of `count` requests per `per` milliseconds is
+    /// withheld until `retry_after`, e.g. by a lockout, rendered in `format`.
+    pub(crate) fn exhausted(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        retry_after: Duration,
+        format: Format,
+    ) -> RateLimitExceeded
+    where
+        K: 'static,
+    {
+        let decision = Decision {
+            allowed: false,
+            limit: count,
+            remaining: 0,
+            reset: retry_after,
+        };
+        RateLimitExceeded::new(
+            decision,
+            retry_after,
+            Duration::from_millis(per),
+            self.rate_limit_headers,
+        )
+        .for_key(self.format_key(key))
+        .responding_with(Some(self.responder(key)))
+        .negotiated(format)
+    }
+
+    /// Takes one of `max` slots for requests of `key` in flight, unless all of them are taken.
+    fn enter(&self, key: K, max: usize) -> Option<concurrency::InFlight<K>> {
+        concurrency::InFlight::enter(&self.in_flight, key, max)
+    }
+
+    /// Applies bans, fault injection and the rate limit to a request with the given key,
+    /// reporting the remaining quota to any layers wrapping the handler and returning it to the extractor.
+    async fn enforce<R>(
+        &self,
+        parts: &Parts,
+        key: K,
+        count: usize,
+        per: u64,
+    ) -> Result<Option<Decision>, LimitRejection<R>>
+    where
+        K: 'static,
+    {
+        if let Some(registry) = &self.registry {
+            registry.register(parts, count, per, std::any::type_name::<K>());
+        }
+
+        let (result, decision) = self.decide(parts, &key, count, per).await;
+        if let Some(events) = &self.events {
+            let outcome = match &result {
+                Ok(()) => LimitOutcome::Allowed,
+                Err(LimitRejection::Banned(_)) => LimitOutcome::Banned,
+                Err(LimitRejection::ChaosInjected(_)) => LimitOutcome::ChaosInjected,
+                Err(LimitRejection::Challenged(_)) => LimitOutcome::Challenged,
+                Err(LimitRejection::BackendError(..)) => LimitOutcome::BackendError,
+                Err(LimitRejection::Overloaded(_)) => LimitOutcome::Overloaded,
+                Err(_) => LimitOutcome::Rejected,
+            };
+            events.emit(parts, &key, outcome, decision);
+        }
+        result.map(|()| decision)
+    }
+
+    /// Decides whether a request may proceed, also returning the state of its bucket if it was charged.
+    async fn decide<R>(
+        &self,
+        parts: &Parts,
+        key: &K,
+        count: usize,
+        per: u64,
+    ) -> (Result<(), LimitRejection<R>>, Option<Decision>)
+    where
+        K: 'static,
+    {
+        let format = self.format(parts);
+        if let Some(shedder) = &self.shedder {
+            if let Some(retry_after) = shedder.shed(self.clock.now()) {
+                let refusal = Refusal::new(Some(retry_after), format);
+                return (Err(LimitRejection::Overloaded(refusal)), None);
+            }
+        }
+
+        if self.bans.is_banned(key) {
+            return (
+                Err(LimitRejection::Banned(Refusal::new(None, format))),
+                None,
+            );
+        }
+
+        if let Some(chaos) = &self.chaos {
+            if let Some(retry_after) = chaos.inject(parts, key) {
+                let exceeded = self.exhausted(key, count, per, retry_after, format);
+                return (Err(LimitRejection::ChaosInjected(exceeded)), None);
+            }
+        }
+
+        if let Some(lockout) = &self.lockout {
+            if let Some(retry_after) = lockout.remaining(key, self.clock.now()) {
+                let exceeded = self.exhausted(key, count, per, retry_after, format);
+                return (Err(LimitRejection::LockedOut(exceeded)), None);
+            }
+        }
+
+        let decision = match self.charge(key, count, per, 1).await {
+            Ok(decision) => decision,
+            Err(error) => {
+                let refusal = Refusal::new(None, format);
+                return (Err(LimitRejection::BackendError(error, refusal)), None);
+            }
+        };
+        #[cfg(feature = "tokio")]
+        let decision = match self.shape(key, count, per, decision).await {
+            Ok(decision) => decision,
+            Err(error) => {
+                let refusal = Refusal::new(None, format);
+                return (Err(LimitRejection::BackendError(error, refusal)), None);
+            }
+        };
+        let decision = match (decision, &self.priority) {
+            (Some(decision), Some(priority)) if priority.intrudes(parts, key, &decision) => {
+                self.refund(key, count, per, 1).await;
+                Some(Decision {
+                    allowed: false,
+                    remaining: decision.remaining + 1,
+                    ..decision
+                })
+            }
+            (decision, _) => decision,
+        };
+
+        #[cfg(feature = "tokio")]
+        if let (Some(tarpit), Some(decision)) = (&self.tarpit, &decision) {
+            let delay = tarpit.delay(key, decision);
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            self.admitted(parts, key, count, per, *decision);
+            return (Ok(()), Some(*decision));
+        }
+
+        let result = match decision {
+            Some(decision) if !decision.allowed => match &self.challenge {
+                Some(challenge) => match challenge.challenge(parts, key) {
+                    Some(response) => Err(LimitRejection::Challenged(response)),
+                    None => Ok(()),
+                },
+                None => match &self.lockout {
+                    Some(lockout) => {
+                        let retry_after = lockout.lock(key, self.clock.now());
+                        let exceeded = self.exhausted(key, count, per, retry_after, format);
+                        Err(LimitRejection::LockedOut(exceeded))
+                    }
+                    None => Err(LimitRejection::RateLimitExceeded(
+                        RateLimitExceeded::new(
+                            decision,
+                            self.retry_after(key, &decision),
+                            Duration::from_millis(per),
+                            self.rate_limit_headers,
+                        )
+                        .for_key(self.format_key(key))
+                        .responding_with(Some(self.responder(key)))
+                        .negotiated(format),
+                    )),
+                },
+            },
+            Some(decision) => {
+                self.admitted(parts, key, count, per, decision);
+                Ok(())
+            }
+            None => Ok(()),
+        };
+        (result, decision)
+    }
+
+    /// Waits for the quota of a rejected request to be replenished and charges it again, as long as the state's
+    /// maximum delay allows.
+    #[cfg(feature = "tokio")]
+    async fn shape(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        mut decision: Option<Decision>,
+    ) -> Result<Option<Decision>, BackendError> {
+        let Some(mut budget) = self.max_delay else {
+            return Ok(decision);
+        };
+        while let Some(rejected) = decision.filter(|decision| !decision.allowed) {
+            // Nothing to wait for if the request can never fit, e.g. into a limit of zero.
+            if rejected.reset.is_zero() || rejected.reset > budget {
+                break;
+            }
+            budget -= rejected.reset;
+            tokio::time::sleep(rejected.reset).await;
+            decision = self.charge(key, count, per, 1).await?;
+        }
+        Ok(decision)
+    }
+
+    /// Reports an admitted request to any layers wrapping the handler.
+    fn admitted(&self, parts: &Parts, key: &K, count: usize, per: u64, decision: Decision)
+    where
+        K: 'static,
+    {
+        if let Some(slot) = parts.extensions.get::<QuotaSlot>() {
+            slot.record(Quota {
+                decision,
+                window: Duration::from_millis(per),
+                headers: self.rate_limit_headers,
+            });
+        }
+        if let Some(slot) = parts.extensions.get::<ChargeSlot>() {
+            slot.record(upload::charger(self.clone(), key.clone(), count, per));
+        }
+    }
+
+    /// Takes `cost` tokens for `key` from the backend or the in-memory buckets.
+    /// Returns `None` if the backend failed to make a decision and the failure policy lets requests through.
+    async fn charge(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Option<Decision>, BackendError> {
+        let decision = self.acquire(key, count, per, cost).await;
+        self.observe(
+            key,
+            decision.as_ref().ok().copied().flatten(),
+            self.clock.now(),
+        );
+        decision
+    }
+
+    /// Returns the time until a request of `key` rejected with `decision` could be admitted, computed from its
+    /// in-memory token bucket if there is one, or assumed to be the reset of the decision otherwise.
+    fn retry_after(&self, key: &K, decision: &Decision) -> Duration {
+        let bucket = match self.algorithm {
+            _ if self.backend.is_some() || self.reset_schedule.is_some() => None,
+            algorithm::Kind::TokenBucket | algorithm::Kind::Burst(_) | algorithm::Kind::Smooth => {
+                self.rate_limits.retry_after_at(key, 1, self.clock.now())
+            }
+            _ => None,
+        };
+        bucket.unwrap_or(decision.reset)
+    }
+
+    /// Returns `cost` tokens taken for `key` to the backend or the in-memory buckets.
+    async fn refund(&self, key: &K, count: usize, per: u64, cost: usize) {
+        match &self.backend {
+            Some(backend) => {
+                // A failed refund only leaves the key with fewer tokens than it should have.
+                let _ = backend.refund(key, count, per, cost).await;
+            }
+            None if self.reset_schedule.is_some() => {
+                if let (Some(schedule), Some(mut counter)) =
+                    (&self.reset_schedule, self.quotas.get_mut(key))
+                {
+                    counter.refund(schedule, cost, self.clock.now());
+                }
+            }
+            None => match self.algorithm {
+                algorithm::Kind::TokenBucket
+                | algorithm::Kind::Burst(_)
+                | algorithm::Kind::Smooth => {
+                    self.rate_limits.refund_at(key, cost, self.clock.now())
+                }
+                algorithm::Kind::SlidingWindow => {
+                    if let Some(mut counter) = self.windows.get_mut(key) {
+                        counter.refund(cost, self.clock.now());
+                    }
+                }
+                algorithm::Kind::SlidingLog => {
+                    if let Some(mut log) = self.logs.get_mut(key) {
+                        log.refund(cost);
+                    }
+                }
+                algorithm::Kind::Gcra => {
+                    if let Some(mut schedule) = self.schedules.get_mut(key) {
+                        schedule.refund(count, cost, self.clock.now());
+                    }
+                }
+                algorithm::Kind::Pacing => {
+                    if let Some(mut schedule) = self.paces.get_mut(key) {
+                        schedule.refund(1, cost, self.clock.now());
+                    }
+                }
+                algorithm::Kind::Calendar(_) => {
+                    if let Some(mut counter) = self.calendars.get_mut(key) {
+                        counter.refund(cost, self.clock.now());
+                    }
+                }
+            },
+        }
+    }
+
+    /// Takes `cost` tokens like [`charge`](Self::charge), without notifying any hooks.
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Option<Decision>, BackendError> {
+        let Some(backend) = &self.backend else {
+            return Ok(Some(self.acquire_local(
+                key,
+                count,
+                per,
+                cost,
+                self.clock.now(),
+            )));
+        };
+        match backend.acquire(key, count, per, cost).await {
+            Ok(decision) => Ok(Some(decision)),
+            Err(error) => match self.failure_policy {
+                FailurePolicy::FailOpen => Ok(None),
+                FailurePolicy::FailClosed => Err(error),
+                FailurePolicy::FallbackToLocal => Ok(Some(self.acquire_local(
+                    key,
+                    count,
+                    per,
+                    cost,
+                    self.clock.now(),
+                ))),
+            },
+        }
+    }
+
+    /// Takes `cost` tokens from the in-memory state of `key`, limited to this replica's share of `count` as warmed up
+    /// and scheduled at `now` and adapted to the feedback reported for `key`, until the next scheduled reset if there is one.
+    fn acquire_local(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        let share = |count| {
+            let count = self
+                .warm_up
+                .map_or(count, |warm_up| warm_up.limit(count, now));
+            let count = self
+                .timetable
+                .as_ref()
+                .map_or(count, |timetable| timetable.limit(count, now));
+            let count = self
+                .aimd
+                .as_ref()
+                .map_or(count, |aimd| aimd.limit(key, count));
+            self.replicas
+                .as_ref()
+                .map_or(count, |replicas: &Replicas| replicas.share(count))
+        };
+        // Requests new keys are considered to have made already, unless they start full.
+        let used = |count| count - self.rate_limits.initial_tokens(count);
+        if let Some(schedule) = &self.reset_schedule {
+            return algorithm::decide_in(
+                &self.quotas,
+                key,
+                || ScheduledCounter::new(schedule, used(share(count)), now),
+                |counter| counter.decide_at(schedule, share(count), cost, now),
+            );
+        }
+        match self.algorithm {
+            algorithm::Kind::TokenBucket
+                if self.replicas.is_some()
+                    || self.warm_up.is_some()
+                    || self.timetable.is_some()
+                    || self.aimd.is_some() =>
+            {
+                self.rate_limits
+                    .acquire_resized_at(key, share(count), per, cost, now)
+            }
+            algorithm::Kind::TokenBucket => self.rate_limits.acquire_at(key, count, per, cost, now),
+            algorithm::Kind::Burst(burst) => {
+                self.rate_limits
+                    .acquire_burst_at(key, share(count), per, share(burst), cost, now)
+            }
+            algorithm::Kind::Smooth => {
+                self.rate_limits
+                    .acquire_smooth_at(key, share(count), per, cost, now)
+            }
+            algorithm::Kind::SlidingWindow => algorithm::decide_in(
+                &self.windows,
+                key,
+                || WindowCounter::new(per, now).with_used(used(share(count))),
+                |counter| counter.decide_at(share(count), cost, now),
+            ),
+            algorithm::Kind::SlidingLog => algorithm::decide_in(
+                &self.logs,
+                key,
+                || RequestLog::new(per).with_used(used(share(count)), now),
+                |log| log.decide_at(share(count), cost, now),
+            ),
+            algorithm::Kind::Gcra => algorithm::decide_in(
+                &self.schedules,
+                key,
+                || VirtualSchedule::new(per, now).with_used(share(count), used(share(count))),
+                |schedule| schedule.decide_at(share(count), cost, now),
+            ),
+            algorithm::Kind::Pacing => algorithm::decide_in(
+                &self.paces,
+                key,
+                || VirtualSchedule::new(per, now).with_used(share(count), used(1)),
+                |schedule| schedule.decide_paced_at(per, share(count), cost, now),
+            ),
+            algorithm::Kind::Calendar(offset) => algorithm::decide_in(
+                &self.calendars,
+                key,
+                || CalendarCounter::new(per, offset, now).with_used(used(share(count))),
+                |counter| counter.decide_at(share(count), cost, now),
+            ),
+        }
+    }
+
+    /// Notifies the hooks observing every checked request.
+    fn observe(&self, key: &K, decision: Option<Decision>, now: Instant) {
+        self.bans.record(key, decision.is_none_or(|d| d.allowed));
+        #[cfg(feature = "tokio")]
+        if let (Some(watcher), Some(decision)) = (self.watchers.get(key), decision) {
+            if watcher.receiver_count() > 0 {
+                watcher.send_replace(Some(decision));
+            } else {
+                drop(watcher);
+                self.watchers
+                    .remove_if(key, |_, watcher| watcher.receiver_count() == 0);
+            }
+        }
+        if let Some(spikes) = &self.spikes {
+            spikes.observe(key, now);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<const C: usize, const P: u64, K, A, S> FromRequestParts<S> for Limit<C, P, K, A>
+where
+    LimitState<K>: FromRef<S>,
+    S: Send + Sync,
+    K: Key + 'static,
+    A: Algorithm,
+    K::Extractor: FromRequestParts<S> + Send,
+{
+    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key_extractor = match K::Extractor::from_request_parts(parts, state).await {
+            Ok(ke) => ke,
+            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
+        };
+
+        let mut limit_state: LimitState<K> = FromRef::from_ref(state);
+        limit_state.algorithm = algorithm::kind::<A>();
+        let key = K::from_extractor(&key_extractor);
+        let decision = limit_state.enforce(parts, key, C, P).await?;
+        RateLimitInfo::record(parts, decision);
+        Ok(Self(key_extractor))
+    }
+}
+
+/// Enumerates possible failure modes for rate limiting when extracting from request parts.
+///
+/// Handlers that take `Result<Limit<..>, LimitRejection<_>>` (or `Option<Limit<..>>`) receive the rejection
+/// instead of the default response, so they can respond with custom business logic:
+///
+/// ```
+/// use axum::response::IntoResponse;
+/// use axum_limit::{Limit, LimitRejection};
+/// use http::{Method, StatusCode};
+/// use std::convert::Infallible;
+///
+/// async fn handler(limit: Result<Limit<10, 60_000, Method>, LimitRejection<Infallible>>) -> impl IntoResponse {
+///     match limit {
+///         Ok(_) => "Here you go.".into_response(),
+///         Err(LimitRejection::RateLimitExceeded(_)) => {
+///             (StatusCode::TOO_MANY_REQUESTS, "Upgrade your plan for more requests.").into_response()
+///         }
+///         Err(rejection) => rejection.into_response(),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub enum LimitRejection<R> {
+    /// Indicates a failure during key extraction, storing the underlying rejection reason.
+    KeyExtractionFailure(R),
+
+    /// Indicates that the rate limit has been exceeded, holding the quota of the key.
+    RateLimitExceeded(RateLimitExceeded),
+
+    /// Indicates that the key is locked out after exceeding its rate limit, holding its exhausted quota until the
+    /// end of the lockout.
+    LockedOut(RateLimitExceeded),
+
+    /// Indicates that the key is on the ban list.
+    Banned(Refusal),
+
+    /// Indicates a synthetic rate limit rejection injected by [`Chaos`] testing, holding an exhausted quota until
+    /// the delay after which the client is asked to retry.
+    ChaosInjected(RateLimitExceeded),
+
+    /// Indicates that the rate limit has been exceeded and the client must solve a [`Challenge`] to continue.
+    /// Holds the challenge response.
+    Challenged(Response),
+
+    /// Indicates that the [`Backend`] failed to decide on the request under [`FailurePolicy::FailClosed`].
+    BackendError(BackendError, Refusal),
+
+    /// Indicates that the request was shed by a [`LoadShedder`] while the system is overloaded.
+    Overloaded(Refusal),
+}
+
+impl LimitRejection<Infallible> {
+    /// Converts a rejection that cannot be a key extraction failure into one for any extractor.
+    pub(crate) fn widen<R>(self) -> LimitRejection<R> {
+        match self {
+            LimitRejection::KeyExtractionFailure(never) => match never {},
+            LimitRejection::RateLimitExceeded(exceeded) => {
+                LimitRejection::RateLimitExceeded(exceeded)
+            }
+            LimitRejection::LockedOut(exceeded) => LimitRejection::LockedOut(exceeded),
+            LimitRejection::Banned(refusal) => LimitRejection::Banned(refusal),
+            LimitRejection::ChaosInjected(exceeded) => LimitRejection::ChaosInjected(exceeded),
+            LimitRejection::Challenged(response) => LimitRejection::Challenged(response),
+            LimitRejection::BackendError(error, refusal) => {
+                LimitRejection::BackendError(error, refusal)
+            }
+            LimitRejection::Overloaded(refusal) => LimitRejection::Overloaded(refusal),
+        }
+    }
+}
+
+impl<R: Display> Display for LimitRejection<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitRejection::KeyExtractionFailure(r) => write!(f, "{r}"),
+            LimitRejection::RateLimitExceeded(exceeded)
+            | LimitRejection::LockedOut(exceeded)
+            | LimitRejection::ChaosInjected(exceeded) => write!(f, "{exceeded}"),
+            LimitRejection::Challenged(_) => write!(f, "Rate limit exceeded."),
+            LimitRejection::Banned(_) => write!(f, "Access denied."),
+            LimitRejection::BackendError(error, _) => write!(f, "{error}"),
+            LimitRejection::Overloaded(_) => write!(f, "Service overloaded."),
+        }
+    }
+}
+
+impl<R: Error + 'static> Error for LimitRejection<R> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LimitRejection::KeyExtractionFailure(ve) => Some(ve),
+            LimitRejection::BackendError(error, _) => Some(error),
+            LimitRejection::RateLimitExceeded(_)
+            | LimitRejection::LockedOut(_)
+            | LimitRejection::Banned(_)
+            | LimitRejection::ChaosInjected(_)
+            | LimitRejection::Challenged(_)
+            | LimitRejection::Overloaded(_) => None,
+        }
+    }
+}
+
+impl<R: IntoResponse> IntoResponse for LimitRejection<R> {
+    fn into_response(self) -> Response {
+        match self {
+            LimitRejection::KeyExtractionFailure(rejection) => rejection.into_response(),
+            LimitRejection::RateLimitExceeded(exceeded)
+            | LimitRejection::LockedOut(exceeded)
+            | LimitRejection::ChaosInjected(exceeded) => exceeded.into_response(),
+            LimitRejection::Banned(refusal) => {
+                refusal.render(StatusCode::FORBIDDEN, "Access denied.")
+            }
+            LimitRejection::Challenged(response) => response,
+            LimitRejection::BackendError(_, refusal) => refusal.render(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Rate limiting is temporarily unavailable.",
+            ),
+            LimitRejection::Overloaded(refusal) => {
+                refusal.render(StatusCode::SERVICE_UNAVAILABLE, "Service overloaded.")
+            }
+        }
+    }
+}
+
+/// Converts a delay to the whole seconds of a `Retry-After` header, rounding up to never ask for a too early retry.
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{header, Method, Uri};
+    use std::future::IntoFuture;
+
+    #[tokio::test]
+    async fn limit() {
+        const TEST_ROUTE0: &str = "/limit0";
+        const TEST_ROUTE1: &str = "/limit1";
+        async fn handler0(Limit(_uri): Limit<1, 1, Uri>) -> impl IntoResponse {}
+
+        async fn handler1(Limit(_uri): Limit<3, 1, Uri>) -> impl IntoResponse {}
+
+        let my_app = Router::new()
+            .route(TEST_ROUTE0, get(handler0))
+            .route(TEST_ROUTE1, get(handler1))
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get(TEST_ROUTE0).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server.get(TEST_ROUTE0).await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let response = server.get(TEST_ROUTE0).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let gets = vec![
+            server.get(TEST_ROUTE1).into_future(),
+            server.get(TEST_ROUTE1).into_future(),
+            server.get(TEST_ROUTE1).into_future(),
+        ];
+
+        let resp = futures::future::join_all(gets).await;
+        assert!(!resp.iter().any(|r| !r.status_code().is_success()));
+        assert_eq!(
+            server.get(TEST_ROUTE1).await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let response = server.get(TEST_ROUTE1).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn limit_per_100_millis() {
+        const TEST_ROUTE: &str = "/limit_per_100_millis";
+
+        async fn handler(Limit(_uri): Limit<1, 100, Uri>) -> impl IntoResponse {}
+
+        let my_app = Router::new()
+            .route(TEST_ROUTE, get(handler))
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        // 第一次请求应该成功
+        let response = server.get(TEST_ROUTE).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        // 马上再发起一次请求应该被限制
+        let response = server.get(TEST_ROUTE).await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+
+        // 等待 100 毫秒
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // 再次请求应该成功
+        let response = server.get(TEST_ROUTE).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn shaping() {
+        async fn handler(_: Limit<1, 100, Method>) {}
+
+        let shaped = |max_delay| {
+            let my_app = Router::new()
+                .route("/", get(handler))
+                .with_state(LimitState::default().with_shaping(max_delay));
+            TestServer::new(my_app).expect("Failed to create test server")
+        };
+
+        let server = shaped(Duration::from_secs(1));
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        let started = Instant::now();
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let server = shaped(Duration::from_millis(10));
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        let response = server.get("/").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn limit_warning() {
+        async fn handler(Limit(_uri): Limit<4, 60_000, Uri>) -> impl IntoResponse {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .layer(LimitWarningLayer::new(0.5))
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get("/").await;
+        assert!(response.maybe_header(&X_RATELIMIT_WARNING).is_none());
+        let response = server.get("/").await;
+        assert!(response.maybe_header(&X_RATELIMIT_WARNING).is_none());
+        let response = server.get("/").await;
+        assert_eq!(
+            response.header(&X_RATELIMIT_WARNING),
+            "1 of 4 requests remaining"
+        );
+    }
+
+    #[tokio::test]
+    async fn quota_headers() {
+        async fn handler(Limit(_uri): Limit<4, 60_000, Uri>) -> impl IntoResponse {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .layer(LimitWarningLayer::new(0.5))
+            .layer(QuotaHeadersLayer::new())
+            .with_state(LimitState::default().with_rate_limit_headers(RateLimitHeaders::Both));
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get("/").await;
+        assert_eq!(response.header(&X_RATELIMIT_LIMIT), "4");
+        assert_eq!(response.header(&X_RATELIMIT_REMAINING), "3");
+        assert_eq!(response.header(&RATELIMIT_POLICY), "\"default\";q=4;w=60");
+        server.get("/").await;
+        let response = server.get("/").await;
+        assert_eq!(response.header(&X_RATELIMIT_REMAINING), "1");
+        assert!(response.maybe_header(&X_RATELIMIT_WARNING).is_some());
+    }
+
+    #[tokio::test]
+    async fn soft_threshold() {
+        async fn handler(Limit(_uri): Limit<4, 60_000, Uri>) -> impl IntoResponse {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .layer(LimitWarningLayer::used(0.5))
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get("/").await;
+        assert!(response.maybe_header(&X_RATELIMIT_WARNING).is_none());
+        let response = server.get("/").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.header(&X_RATELIMIT_WARNING),
+            "2 of 4 requests remaining"
+        );
+    }
+
+    #[tokio::test]
+    async fn read_write_limit() {
+        async fn handler(_: ReadWriteLimit<2, 60_000, 1, 60_000, Uri>) -> impl IntoResponse {}
+
+        let my_app = Router::new()
+            .route("/", get(handler).post(handler))
+            .with_state(LimitState::<(Uri, Access)>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.post("/").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.post("/").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn registry() {
+        async fn handler(Limit(_uri): Limit<5, 1000, Uri>) -> impl IntoResponse {}
+
+        let registry = LimitRegistry::default();
+        let my_app = Router::new()
+            .route("/users/:id", get(handler))
+            .with_state(LimitState::default().with_registry(registry.clone()));
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+        server.get("/users/1").await;
+        server.get("/users/2").await;
+
+        let limits = registry.limits();
+        assert_eq!(limits.len(), 1);
+        assert_eq!(limits[0].count, 5);
+        assert_eq!(limits[0].per, 1000);
+        assert_eq!(limits[0].key, "http::uri::Uri");
+        // Route templates are only known with the `axum` feature.
+        let route = cfg!(feature = "axum").then_some("/users/:id");
+        assert_eq!(limits[0].route.as_deref(), route);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn watch() {
+        let state = LimitState::<Method>::default();
+        let mut quota = state.watch(Method::GET);
+        assert_eq!(*quota.borrow(), None);
+
+        assert!(state.check(Method::GET, 2, 60_000));
+        assert!(quota.has_changed().expect("sender is alive"));
+        assert_eq!(quota.borrow_and_update().map(|d| d.remaining), Some(1));
+
+        assert!(state.check(Method::POST, 2, 60_000));
+        assert!(!quota.has_changed().expect("sender is alive"));
+    }
+
+    #[test]
+    fn preload() {
+        let state = LimitState::<Method>::default();
+        state.preload([(Method::GET, 0), (Method::POST, 1)], 2, 60_000);
+
+        assert!(!state.check(Method::GET, 2, 60_000));
+        assert!(state.check(Method::POST, 2, 60_000));
+        assert!(!state.check(Method::POST, 2, 60_000));
+        assert!(state.check(Method::PUT, 2, 60_000));
+        assert!(state.check(Method::PUT, 2, 60_000));
+    }
+
+    #[test]
+    fn replicas() {
+        let state = LimitState::<Method>::default().with_replicas(3);
+        assert!(state.check(Method::GET, 6, 60_000));
+        assert!(state.check(Method::GET, 6, 60_000));
+        assert!(!state.check(Method::GET, 6, 60_000));
+
+        // Every replica allows at least one request.
+        assert!(state.check(Method::POST, 2, 60_000));
+        assert!(!state.check(Method::POST, 2, 60_000));
+    }
+
+    #[tokio::test]
+    async fn charge_batch() {
+        let state = LimitState::<Method>::default();
+        assert!(state.check(Method::PUT, 2, 60_000));
+
+        let batch = [Method::GET, Method::PUT, Method::POST];
+        assert_eq!(state.charge_batch(batch.clone(), 2, 60_000).await, Ok(()));
+        assert_eq!(
+            state.charge_batch(batch, 2, 60_000).await,
+            Err(BatchLimitExceeded {
+                index: 1,
+                key: Method::PUT
+            })
+        );
+
+        // The rejected batch was not charged to its other keys.
+        assert!(state.check(Method::GET, 2, 60_000));
+        assert!(!state.check(Method::GET, 2, 60_000));
+    }
+
+    #[tokio::test]
+    async fn charge_batch_failures() {
+        #[derive(Default)]
+        struct Flaky(std::sync::Mutex<Vec<(&'static str, Method)>>);
+
+        #[async_trait::async_trait]
+        impl Backend<Method> for Arc<Flaky> {
+            async fn acquire(
+                &self,
+                key: &Method,
+                count: usize,
+                _per: u64,
+                _cost: usize,
+            ) -> Result<Decision, BackendError> {
+                if *key == Method::DELETE {
+                    return Err(BackendError::new("connection refused"));
+                }
+                self.0
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(("acquire", key.clone()));
+                Ok(Decision {
+                    allowed: true,
+                    limit: count,
+                    remaining: count - 1,
+                    reset: Duration::ZERO,
+                })
+            }
+
+            async fn refund(
+                &self,
+                key: &Method,
+                _count: usize,
+                _per: u64,
+                _cost: usize,
+            ) -> Result<(), BackendError> {
+                self.0
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(("refund", key.clone()));
+                Ok(())
+            }
+        }
+
+        let backend = Arc::new(Flaky::default());
+        let state = LimitState::default()
+            .with_backend(backend.clone())
+            .with_failure_policy(FailurePolicy::FailClosed);
+
+        let batch = [Method::GET, Method::GET, Method::PUT, Method::DELETE];
+        assert_eq!(
+            state.charge_batch(batch, 2, 60_000).await,
+            Err(BatchLimitExceeded {
+                index: 3,
+                key: Method::DELETE
+            })
+        );
+        // The repeated key was charged once, and the keys charged before the failure were refunded.
+        assert_eq!(
+            *backend.0.lock().unwrap_or_else(|e| e.into_inner()),
+            [
+                ("acquire", Method::GET),
+                ("acquire", Method::PUT),
+                ("refund", Method::GET),
+                ("refund", Method::PUT),
+            ]
+        );
+    }
+
+    #[test]
+    fn grace() {
+        let state = LimitState::<Method>::default().with_grace(1);
+
+        assert!(state.check(Method::GET, 1, 50));
+        assert!(state.check(Method::GET, 1, 50));
+        assert!(!state.check(Method::GET, 1, 50));
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(state.check(Method::GET, 1, 50));
+        assert!(!state.check(Method::GET, 1, 50));
+    }
+
+    #[test]
+    fn warm_up() {
+        let state = LimitState::<Method>::default().with_warm_up(Duration::from_millis(200), 0.2);
+        assert!(state.check(Method::GET, 10, 100));
+        assert!(state.check(Method::GET, 10, 100));
+        assert!(!state.check(Method::GET, 10, 100));
+
+        std::thread::sleep(Duration::from_millis(250));
+        for _ in 0..10 {
+            assert!(state.check(Method::GET, 10, 100));
+        }
+        assert!(!state.check(Method::GET, 10, 100));
+    }
+
+    #[test]
+    fn debt() {
+        let state = LimitState::<Method>::default().with_debt(2);
+        assert!(state.check(Method::GET, 1, 50));
+        assert!(state.check(Method::GET, 1, 50));
+        assert!(state.check(Method::GET, 1, 50));
+        assert!(!state.check(Method::GET, 1, 50));
+
+        // The refill repays part of the debt, which can be borrowed again, but no more.
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(state.check(Method::GET, 1, 50));
+        assert!(!state.check(Method::GET, 1, 50));
+    }
+
+    #[test]
+    fn smooth_debt() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket {
+            smooth: true,
+            max_debt: 2,
+            ..TokenBucket::new(2usize, 1_000u64, now)
+        };
+        assert!(bucket.try_acquire_many_at(3, now));
+        assert_eq!(bucket.debt, 1);
+
+        // Refilling the whole bucket repays the debt too, so the full debt can be borrowed again.
+        let later = now + Duration::from_secs(10);
+        assert!(bucket.try_acquire_many_at(2, later));
+        assert_eq!(bucket.debt, 0);
+        assert!(bucket.try_acquire_many_at(2, later));
+        assert!(!bucket.try_acquire_at(later));
+        assert_eq!(bucket.reset_at(later), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn retry_after() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket {
+            smooth: true,
+            ..TokenBucket::new(10usize, 10_000u64, now)
+        };
+        assert!(bucket.decide_at(10, now).allowed);
+        let decision = bucket.decide_at(1, now);
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_secs(10));
+        // A smooth bucket admits the next request as soon as its first token is back.
+        assert_eq!(bucket.retry_after_at(1, now), Some(Duration::from_secs(1)));
+        assert_eq!(
+            bucket.retry_after_at(2, now + Duration::from_millis(500)),
+            Some(Duration::from_millis(1_500))
+        );
+        assert_eq!(bucket.retry_after_at(11, now), None);
+
+        let mut bucket = TokenBucket::new(2usize, 1_000u64, now);
+        assert!(bucket.decide_at(2, now).allowed);
+        assert_eq!(
+            bucket.retry_after_at(3, now + Duration::from_millis(200)),
+            None
+        );
+        assert_eq!(
+            bucket.retry_after_at(1, now + Duration::from_millis(200)),
+            Some(Duration::from_millis(800))
+        );
+    }
+
+    #[test]
+    fn carry_over() {
+        let state = LimitState::<Method>::default().with_carry_over(2);
+        assert!(state.check(Method::GET, 3, 50));
+
+        // Two unused tokens roll over into the next period.
+        std::thread::sleep(Duration::from_millis(60));
+        for _ in 0..5 {
+            assert!(state.check(Method::GET, 3, 50));
+        }
+        assert!(!state.check(Method::GET, 3, 50));
+    }
+
+    #[test]
+    fn initial_fill() {
+        let state = LimitState::<Method>::default().with_initial_fill(0.5);
+        assert!(state.check(Method::GET, 4, 60_000));
+        assert!(state.check(Method::GET, 4, 60_000));
+        assert!(!state.check(Method::GET, 4, 60_000));
+
+        let mut state = LimitState::<Method>::default().with_initial_fill(0.0);
+        state.algorithm = algorithm::Kind::SlidingLog;
+        assert!(!state.check(Method::GET, 4, 60_000));
+    }
+
+    #[test]
+    fn prewarm() {
+        let state = LimitState::<Method>::default();
+        state.prewarm([Method::GET], 4, 60_000, 0.5);
+
+        assert!(state.check(Method::GET, 4, 60_000));
+        assert!(state.check(Method::GET, 4, 60_000));
+        assert!(!state.check(Method::GET, 4, 60_000));
+    }
+
+    #[tokio::test]
+    async fn chaos() {
+        async fn handler(Limit(_uri): Limit<100, 1000, Uri>) -> impl IntoResponse {}
+
+        let state = LimitState::default().with_chaos(
+            Chaos::new(1.0)
+                .retry_after(Duration::from_millis(1500))
+                .when(|parts, _| parts.uri.path() == "/chaos"),
+        );
+        let my_app = Router::new()
+            .route("/chaos", get(handler))
+            .route("/calm", get(handler))
+            .with_state(state);
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get("/chaos").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header(header::RETRY_AFTER), "2");
+
+        let response = server.get("/calm").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn events() {
+        async fn handler(Limit(_method): Limit<1, 60_000, Method>) -> impl IntoResponse {}
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events = {
+            let seen = seen.clone();
+            LimitEvents::new(move |event: &LimitEvent<'_, Method>| {
+                seen.lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push((event.outcome, event.request_id.map(str::to_string)));
+            })
+        };
+        let my_app = Router::new()
+            .route("/events", get(handler))
+            .with_state(LimitState::default().with_events(events));
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        server
+            .get("/events")
+            .add_header(
+                header::HeaderName::from_static("x-request-id"),
+                header::HeaderValue::from_static("first"),
+            )
+            .await;
+        server.get("/events").await;
+
+        let seen = seen.lock().unwrap_or_else(|e| e.into_inner());
+        assert_eq!(
+            *seen,
+            [
+                (LimitOutcome::Allowed, Some("first".to_string())),
+                (LimitOutcome::Rejected, None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn handler_side_rejection() {
+        async fn handler(limit: Option<Limit<1, 60_000, Method>>) -> impl IntoResponse {
+            match limit {
+                Some(_) => "allowed",
+                None => "upgrade",
+            }
+        }
+
+        let my_app = Router::new()
+            .route("/optional", get(handler))
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/optional").await.text(), "allowed");
+        let response = server.get("/optional").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "upgrade");
+    }
+
+    #[tokio::test]
+    async fn scopes() {
+        #[derive(Clone, Default)]
+        struct AppState {
+            search: LimitState<(Method, Scope<1>)>,
+            upload: LimitState<(Method, Scope<2>)>,
+        }
+
+        impl FromRef<AppState> for LimitState<(Method, Scope<1>)> {
+            fn from_ref(state: &AppState) -> Self {
+                state.search.clone()
+            }
+        }
+
+        impl FromRef<AppState> for LimitState<(Method, Scope<2>)> {
+            fn from_ref(state: &AppState) -> Self {
+                state.upload.clone()
+            }
+        }
+
+        async fn search(_: Limit<1, 60_000, (Method, Scope<1>)>) {}
+        async fn upload(_: Limit<1, 60_000, (Method, Scope<2>)>) {}
+
+        let my_app = Router::new()
+            .route("/search", get(search))
+            .route("/upload", get(upload))
+            .with_state(AppState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/search").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/upload").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/search").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn route_path() {
+        async fn handler(_: Limit<1, 60_000, RoutePath>) {}
+
+        let my_app = Router::new()
+            .route("/users/:id", get(handler))
+            .route("/posts/:id", get(handler))
+            .with_state(LimitState::<RoutePath>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/users/1").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/posts/1").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/users/2").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn host() {
+        async fn handler(_: Limit<1, 60_000, Host>) {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<Host>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let get = |host: &'static str| server.get("/").add_header(header::HOST, host);
+        assert_eq!(get("a.example.com").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            get("b.example.com:8080").await.status_code(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            get("A.Example.com:443").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn unit_key() {
+        async fn handler(_: Limit<2, 60_000, ()>) {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<()>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        let response = server
+            .get("/")
+            .add_header(header::HOST, "other.example.com")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn lockout() {
+        async fn handler(Limit(_method): Limit<1, 50, Method>) -> impl IntoResponse {}
+
+        let state = LimitState::default().with_lockout(Lockout::new(Duration::from_secs(60)));
+        let my_app = Router::new()
+            .route("/login", get(handler))
+            .with_state(state);
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get("/login").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.get("/login").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header(header::RETRY_AFTER), "60");
+
+        // The bucket has refilled by now, but the key is still locked out.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = server
+            .get("/login")
+            .add_header(header::ACCEPT, "application/json")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header(&X_RATELIMIT_REMAINING), "0");
+        assert!(response.text().contains("\"status\":429"));
+    }
+
+    #[tokio::test]
+    async fn grpc_rejections() {
+        let grpc_status = |state: LimitState<Method>| async move {
+            let request = http::Request::builder()
+                .header(header::CONTENT_TYPE, "application/grpc")
+                .body(())
+                .expect("valid request");
+            let (parts, _) = request.into_parts();
+            let rejection = state
+                .with_grpc_rejections()
+                .enforce::<Infallible>(&parts, Method::GET, 1, 60_000)
+                .await
+                .expect_err("the request is rejected");
+            let response = rejection.into_response();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.headers()["grpc-status"].clone()
+        };
+
+        let locked = LimitState::default().with_lockout(Lockout::new(Duration::from_secs(60)));
+        locked
+            .lockout
+            .as_ref()
+            .expect("lockout")
+            .lock(&Method::GET, Instant::now());
+        assert_eq!(grpc_status(locked).await, "8");
+        assert_eq!(
+            grpc_status(LimitState::default().with_chaos(Chaos::new(1.0))).await,
+            "8"
+        );
+        let banned = LimitState::default();
+        banned.ban_list().ban(Method::GET, None);
+        assert_eq!(grpc_status(banned).await, "7");
+        let overloaded = LimitState::default().with_load_shedding(
+            LoadShedder::new(|| Load {
+                cpu: 1.0,
+                memory: 1.0,
+            })
+            .probe_interval(Duration::ZERO),
+        );
+        assert_eq!(grpc_status(overloaded).await, "14");
+    }
+
+    #[tokio::test]
+    async fn challenge() {
+        async fn handler(Limit(_uri): Limit<1, 60_000, Method>) -> impl IntoResponse {}
+
+        let state = LimitState::default().with_challenge(
+            Challenge::new(|_, _| (StatusCode::UNAUTHORIZED, "Solve the CAPTCHA.").into_response())
+                .allowance(1),
+        );
+        let my_app = Router::new()
+            .route("/challenge", get(handler))
+            .with_state(state.clone());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get("/challenge").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.get("/challenge").await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+        state.challenge_solved(Method::GET);
+        let response = server.get("/challenge").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.get("/challenge").await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn failure_policy() {
+        struct Unreachable;
+
+        #[async_trait::async_trait]
+        impl Backend<Method> for Unreachable {
+            async fn acquire(
+                &self,
+                _key: &Method,
+                _count: usize,
+                _per: u64,
+                _cost: usize,
+            ) -> Result<Decision, BackendError> {
+                Err(BackendError::new("connection refused"))
+            }
+        }
+
+        async fn handler(_: Limit<1, 60_000, Method>) {}
+
+        let statuses = |policy| async move {
+            let state = LimitState::default()
+                .with_backend(Unreachable)
+                .with_failure_policy(policy);
+            let my_app = Router::new()
+                .route("/policy", get(handler))
+                .with_state(state);
+            let server = TestServer::new(my_app).expect("Failed to create test server");
+            [
+                server.get("/policy").await.status_code(),
+                server.get("/policy").await.status_code(),
+            ]
+        };
+
+        assert_eq!(
+            statuses(FailurePolicy::FailOpen).await,
+            [StatusCode::OK, StatusCode::OK]
+        );
+        assert_eq!(
+            statuses(FailurePolicy::FailClosed).await,
+            [StatusCode::SERVICE_UNAVAILABLE; 2]
+        );
+        assert_eq!(
+            statuses(FailurePolicy::FallbackToLocal).await,
+            [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS]
+        );
+    }
+
+    #[tokio::test]
+    async fn flush_on_shutdown() {
+        #[derive(Default)]
+        struct Buffered(std::sync::atomic::AtomicBool);
+
+        #[async_trait::async_trait]
+        impl Backend<Method> for Buffered {
+            async fn acquire(
+                &self,
+                _key: &Method,
+                count: usize,
+                _per: u64,
+                _cost: usize,
+            ) -> Result<Decision, BackendError> {
+                Ok(Decision {
+                    allowed: true,
+                    limit: count,
+                    remaining: count,
+                    reset: Duration::ZERO,
+                })
+            }
+
+            async fn flush(&self) -> Result<(), BackendError> {
+                self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        let backend = Arc::new(Buffered::default());
+        let state = LimitState::default().with_backend(backend.clone());
+        let (shutdown, signal) = futures::channel::oneshot::channel::<()>();
+        let flushed = tokio::spawn(state.flush_on_shutdown(async {
+            let _ = signal.await;
+        }));
+
+        tokio::task::yield_now().await;
+        assert!(!backend.0.load(std::sync::atomic::Ordering::SeqCst));
+        let _ = shutdown.send(());
+        flushed.await.expect("Flush task panicked");
+        assert!(backend.0.load(std::sync::atomic::Ordering::SeqCst));
     }
 }
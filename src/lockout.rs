@@ -0,0 +1,47 @@
+use crate::map::Map;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// A hard lockout of keys that exceeded their limit.
+///
+/// Without a lockout, a rejected key may continue as soon as its bucket refills. When installed on a
+/// [`LimitState`](crate::LimitState), a rejected key is instead blocked for the full cooldown, which is what login
+/// and OTP endpoints need to make guessing impractical.
+pub struct Lockout<K> {
+    cooldown: Duration,
+    locked: Map<K, Instant>,
+}
+
+impl<K> Lockout<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs a new `Lockout` blocking rejected keys for `cooldown`.
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            locked: Map::new(),
+        }
+    }
+
+    /// Returns how long `key` remains locked out, if at all.
+    pub(crate) fn remaining(&self, key: &K, now: Instant) -> Option<Duration> {
+        let until = *self.locked.get(key)?;
+        if until > now {
+            return Some(until - now);
+        }
+        self.locked.remove_if(key, |_, until| *until <= now);
+        None
+    }
+
+    /// Ends the lockout of `key`, if any.
+    pub(crate) fn unlock(&self, key: &K) {
+        self.locked.remove(key);
+    }
+
+    /// Locks `key` out for the cooldown, returning its duration.
+    pub(crate) fn lock(&self, key: &K, now: Instant) -> Duration {
+        self.locked.insert(key.clone(), now + self.cooldown);
+        self.cooldown
+    }
+}
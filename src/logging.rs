@@ -0,0 +1,147 @@
+use crate::{LimitEvent, LimitEvents, LimitOutcome, TokenBucket};
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// A structured logger for rate limit decisions, emitting [`tracing`] events with sampling.
+///
+/// Logging every decision would melt under attack traffic, so only a fraction of allowed and rejected requests is
+/// logged (1% and 100% by default), and the logger itself is limited to a number of events per second. Events that
+/// were dropped by the latter are counted in the `suppressed` field of the next logged event.
+///
+/// Install it with [`LimitState::with_events`](crate::LimitState::with_events)`(log.events())`.
+pub struct DecisionLog {
+    allowed: f64,
+    rejected: f64,
+    budget: Mutex<TokenBucket>,
+    suppressed: AtomicU64,
+    random: RandomState,
+    counter: AtomicU64,
+}
+
+impl Default for DecisionLog {
+    /// Constructs a new `DecisionLog` logging 1% of allowed and all rejected requests, at most 100 per second.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecisionLog {
+    /// Constructs a new `DecisionLog` logging 1% of allowed and all rejected requests, at most 100 per second.
+    pub fn new() -> Self {
+        Self {
+            allowed: 0.01,
+            rejected: 1.0,
+            budget: Mutex::new(TokenBucket::new(100usize, 1000u64, Instant::now())),
+            suppressed: AtomicU64::new(0),
+            random: RandomState::new(),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the fraction of allowed requests to log, between `0.0` and `1.0`.
+    pub fn sample_allowed(mut self, fraction: f64) -> Self {
+        self.allowed = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the fraction of rejected requests to log, between `0.0` and `1.0`.
+    pub fn sample_rejected(mut self, fraction: f64) -> Self {
+        self.rejected = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the maximum number of events logged per second.
+    pub fn max_per_second(mut self, events: usize) -> Self {
+        self.budget = Mutex::new(TokenBucket::new(events, 1000u64, Instant::now()));
+        self
+    }
+
+    /// Turns the logger into a hook to install on a [`LimitState`](crate::LimitState).
+    pub fn events<K>(self) -> LimitEvents<K>
+    where
+        K: Debug,
+    {
+        LimitEvents::new(move |event| self.log(event))
+    }
+
+    /// Decides whether to log an event with `outcome`, returning the number of events suppressed before it if so.
+    fn sample(&self, outcome: LimitOutcome, now: Instant) -> Option<u64> {
+        let fraction = match outcome {
+            LimitOutcome::Allowed => self.allowed,
+            _ => self.rejected,
+        };
+        let sample = self
+            .random
+            .hash_one(self.counter.fetch_add(1, Ordering::Relaxed));
+        let roll = (sample >> 11) as f64 / (1u64 << 53) as f64;
+        if roll >= fraction {
+            return None;
+        }
+
+        let admitted = self
+            .budget
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_acquire_at(now);
+        if !admitted {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(self.suppressed.swap(0, Ordering::Relaxed))
+    }
+
+    fn log<K: Debug>(&self, event: &LimitEvent<'_, K>) {
+        let Some(suppressed) = self.sample(event.outcome, Instant::now()) else {
+            return;
+        };
+        let limit = event.decision.map(|d| d.limit);
+        let remaining = event.decision.map(|d| d.remaining);
+        let reset_ms = event.decision.map(|d| d.reset.as_millis() as u64);
+        macro_rules! log {
+            ($level:expr) => {
+                tracing::event!(
+                    target: "axum_limit",
+                    $level,
+                    key = ?event.key,
+                    outcome = ?event.outcome,
+                    method = %event.method,
+                    path = event.path,
+                    request_id = event.request_id,
+                    limit,
+                    remaining,
+                    reset_ms,
+                    suppressed,
+                    "rate limit decision"
+                )
+            };
+        }
+        match event.outcome {
+            LimitOutcome::Allowed => log!(tracing::Level::INFO),
+            _ => log!(tracing::Level::WARN),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_and_limits_itself() {
+        let log = DecisionLog::new().sample_allowed(0.0).max_per_second(2);
+        let now = Instant::now();
+
+        assert_eq!(log.sample(LimitOutcome::Allowed, now), None);
+        assert_eq!(log.sample(LimitOutcome::Rejected, now), Some(0));
+        assert_eq!(log.sample(LimitOutcome::Banned, now), Some(0));
+        assert_eq!(log.sample(LimitOutcome::Rejected, now), None);
+        assert_eq!(log.sample(LimitOutcome::Rejected, now), None);
+
+        let later = now + std::time::Duration::from_secs(1);
+        assert_eq!(log.sample(LimitOutcome::Rejected, later), Some(2));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::Key;
+use axum_login::{AuthSession, AuthUser, AuthnBackend};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// The user of an `axum-login` session as a key, so `Limit<100, 60_000, LoginUser<Backend>>` limits each
+/// authenticated user. All anonymous requests share a single key.
+///
+/// Users are identified by the string form of their ID, and the session is extracted from the request like
+/// `AuthSession<Backend>`, which requires the `AuthManagerLayer`.
+pub struct LoginUser<B> {
+    id: Option<String>,
+    _backend: PhantomData<fn() -> B>,
+}
+
+impl<B> LoginUser<B> {
+    /// Returns the ID of the authenticated user, or `None` for anonymous requests.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+}
+
+impl<B> Debug for LoginUser<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("LoginUser").field(&self.id).finish()
+    }
+}
+
+impl<B> Clone for LoginUser<B> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            _backend: PhantomData,
+        }
+    }
+}
+
+impl<B> PartialEq for LoginUser<B> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<B> Eq for LoginUser<B> {}
+
+impl<B> Hash for LoginUser<B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<B> Key for LoginUser<B>
+where
+    B: AuthnBackend + Send + Sync + 'static,
+{
+    type Extractor = AuthSession<B>;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        Self {
+            id: extractor.user.as_ref().map(|user| user.id().to_string()),
+            _backend: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::extract::Path;
+    use axum::routing::get;
+    use axum::Router;
+    use axum_login::AuthManagerLayerBuilder;
+    use axum_test::TestServerConfig;
+    use http::StatusCode;
+    use std::convert::Infallible;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    #[derive(Debug, Clone)]
+    struct User(u64);
+
+    impl AuthUser for User {
+        type Id = u64;
+
+        fn id(&self) -> u64 {
+            self.0
+        }
+
+        fn session_auth_hash(&self) -> &[u8] {
+            b"secret"
+        }
+    }
+
+    /// A backend knowing every user, authenticated by their ID alone.
+    #[derive(Debug, Clone)]
+    struct Users;
+
+    #[async_trait::async_trait]
+    impl AuthnBackend for Users {
+        type User = User;
+        type Credentials = u64;
+        type Error = Infallible;
+
+        async fn authenticate(&self, id: u64) -> Result<Option<User>, Infallible> {
+            Ok(Some(User(id)))
+        }
+
+        async fn get_user(&self, id: &u64) -> Result<Option<User>, Infallible> {
+            Ok(Some(User(*id)))
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_each_user() {
+        async fn sign_in(mut session: AuthSession<Users>, Path(id): Path<u64>) {
+            let user = session
+                .authenticate(id)
+                .await
+                .expect("users are known")
+                .expect("users are authenticated");
+            session.login(&user).await.expect("the user is logged in");
+        }
+
+        async fn handler(_: Limit<1, 60_000, LoginUser<Users>>) {}
+
+        let sessions = SessionManagerLayer::new(MemoryStore::default()).with_secure(false);
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<LoginUser<Users>>::default())
+            .route("/sign-in/:id", get(sign_in))
+            .layer(AuthManagerLayerBuilder::new(Users, sessions).build());
+
+        let config = TestServerConfig::builder().save_cookies();
+        let server = || {
+            config
+                .clone()
+                .build_server(my_app.clone())
+                .expect("Failed to create test server")
+        };
+        let (alice, bob, anonymous, stranger) = (server(), server(), server(), server());
+
+        alice.get("/sign-in/1").await;
+        bob.get("/sign-in/2").await;
+        alice.get("/").await.assert_status_ok();
+        bob.get("/").await.assert_status_ok();
+        alice
+            .get("/")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+        // Anonymous requests share a single bucket.
+        anonymous.get("/").await.assert_status_ok();
+        stranger
+            .get("/")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+    }
+}
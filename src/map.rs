@@ -0,0 +1,201 @@
+//! The concurrent maps holding per-key state.
+//!
+//! With the default `dashmap` feature these are `dashmap`'s sharded maps. Without it, a minimal replacement built
+//! on a single `Mutex<HashMap>` provides the same subset of their API, trading concurrency for fewer dependencies.
+
+#[cfg(feature = "dashmap")]
+pub(crate) use dashmap::{DashMap as Map, DashSet as Set};
+
+#[cfg(not(feature = "dashmap"))]
+pub(crate) use std_map::{Map, Set};
+
+#[cfg(not(feature = "dashmap"))]
+mod std_map {
+    use std::collections::{HashMap, HashSet};
+    use std::hash::Hash;
+    use std::ops::{Deref, DerefMut};
+    use std::sync::{Mutex, MutexGuard};
+
+    const PRESENT: &str = "entries cannot be removed while referenced";
+
+    /// A map guarded by a single lock.
+    #[derive(Debug)]
+    pub(crate) struct Map<K, V> {
+        inner: Mutex<HashMap<K, V>>,
+    }
+
+    impl<K, V> Default for Map<K, V> {
+        fn default() -> Self {
+            Self {
+                inner: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    /// A reference to an entry of a [`Map`], holding the map's lock.
+    pub(crate) struct Ref<'a, K, V> {
+        guard: MutexGuard<'a, HashMap<K, V>>,
+        key: K,
+    }
+
+    impl<K: Eq + Hash, V> Deref for Ref<'_, K, V> {
+        type Target = V;
+
+        fn deref(&self) -> &V {
+            self.guard.get(&self.key).expect(PRESENT)
+        }
+    }
+
+    impl<K: Eq + Hash, V> DerefMut for Ref<'_, K, V> {
+        fn deref_mut(&mut self) -> &mut V {
+            self.guard.get_mut(&self.key).expect(PRESENT)
+        }
+    }
+
+    /// A snapshot of an entry of a [`Map`], as yielded by [`Map::iter`].
+    pub(crate) struct Snapshot<K, V> {
+        key: K,
+        value: V,
+    }
+
+    impl<K, V> Snapshot<K, V> {
+        pub(crate) fn key(&self) -> &K {
+            &self.key
+        }
+
+        pub(crate) fn value(&self) -> &V {
+            &self.value
+        }
+    }
+
+    /// A possibly vacant entry of a [`Map`], holding the map's lock.
+    pub(crate) struct Entry<'a, K, V> {
+        guard: MutexGuard<'a, HashMap<K, V>>,
+        key: K,
+    }
+
+    impl<'a, K: Eq + Hash + Clone, V> Entry<'a, K, V> {
+        pub(crate) fn or_insert(self, value: V) -> Ref<'a, K, V> {
+            self.or_insert_with(|| value)
+        }
+
+        #[cfg_attr(not(feature = "gossip"), allow(dead_code))]
+        pub(crate) fn or_default(self) -> Ref<'a, K, V>
+        where
+            V: Default,
+        {
+            self.or_insert_with(V::default)
+        }
+
+        pub(crate) fn or_insert_with(mut self, value: impl FnOnce() -> V) -> Ref<'a, K, V> {
+            self.guard.entry(self.key.clone()).or_insert_with(value);
+            Ref {
+                guard: self.guard,
+                key: self.key,
+            }
+        }
+    }
+
+    impl<K: Eq + Hash + Clone, V> Map<K, V> {
+        pub(crate) fn new() -> Self {
+            Self::default()
+        }
+
+        fn lock(&self) -> MutexGuard<'_, HashMap<K, V>> {
+            self.inner.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub(crate) fn get(&self, key: &K) -> Option<Ref<'_, K, V>> {
+            self.get_mut(key)
+        }
+
+        pub(crate) fn get_mut(&self, key: &K) -> Option<Ref<'_, K, V>> {
+            let guard = self.lock();
+            guard.contains_key(key).then(|| Ref {
+                guard,
+                key: key.clone(),
+            })
+        }
+
+        pub(crate) fn entry(&self, key: K) -> Entry<'_, K, V> {
+            Entry {
+                guard: self.lock(),
+                key,
+            }
+        }
+
+        pub(crate) fn insert(&self, key: K, value: V) -> Option<V> {
+            self.lock().insert(key, value)
+        }
+
+        pub(crate) fn remove(&self, key: &K) -> Option<(K, V)> {
+            self.lock().remove_entry(key)
+        }
+
+        pub(crate) fn remove_if(&self, key: &K, f: impl FnOnce(&K, &V) -> bool) -> Option<(K, V)> {
+            let mut guard = self.lock();
+            let (key, value) = guard.get_key_value(key)?;
+            if f(key, value) {
+                let key = key.clone();
+                guard.remove_entry(&key)
+            } else {
+                None
+            }
+        }
+
+        pub(crate) fn retain(&self, f: impl FnMut(&K, &mut V) -> bool) {
+            self.lock().retain(f);
+        }
+
+        #[cfg(test)]
+        pub(crate) fn len(&self) -> usize {
+            self.lock().len()
+        }
+
+        /// Returns a snapshot of all entries.
+        pub(crate) fn iter(&self) -> impl Iterator<Item = Snapshot<K, V>>
+        where
+            V: Clone,
+        {
+            let entries: Vec<_> = self
+                .lock()
+                .iter()
+                .map(|(key, value)| Snapshot {
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect();
+            entries.into_iter()
+        }
+    }
+
+    /// A set guarded by a single lock.
+    #[derive(Debug)]
+    pub(crate) struct Set<T> {
+        inner: Mutex<HashSet<T>>,
+    }
+
+    impl<T> Default for Set<T> {
+        fn default() -> Self {
+            Self {
+                inner: Mutex::new(HashSet::new()),
+            }
+        }
+    }
+
+    impl<T: Eq + Hash> Set<T> {
+        pub(crate) fn contains(&self, value: &T) -> bool {
+            self.inner
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains(value)
+        }
+
+        pub(crate) fn insert(&self, value: T) -> bool {
+            self.inner
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(value)
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use crate::backend::StoredBucket;
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use memcache::{Client, CommandError, MemcacheError};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The largest expiration memcached treats as relative, in seconds; larger values are read as Unix timestamps.
+const MAX_RELATIVE_EXPIRATION: u64 = 30 * 24 * 60 * 60;
+
+/// A stored value and its CAS token.
+type Item = (Vec<u8>, Option<u64>);
+
+/// The memcached commands buckets are updated with, implemented by the `memcache` client.
+trait Store: Send + Sync {
+    /// Returns the value stored at `key` and its CAS token, if any.
+    fn gets(&self, key: &str) -> Result<Option<Item>, MemcacheError>;
+
+    /// Stores `value` at `key` unless the key already exists.
+    fn add(&self, key: &str, value: &[u8], expiration: u32) -> Result<(), MemcacheError>;
+
+    /// Stores `value` at `key` unless it was updated since its CAS token was `cas`.
+    fn cas(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError>;
+}
+
+impl Store for Client {
+    fn gets(&self, key: &str) -> Result<Option<Item>, MemcacheError> {
+        let mut stored = Client::gets::<(Vec<u8>, u32, Option<u64>)>(self, &[key])?;
+        Ok(stored.remove(key).map(|(bytes, _, cas)| (bytes, cas)))
+    }
+
+    fn add(&self, key: &str, value: &[u8], expiration: u32) -> Result<(), MemcacheError> {
+        Client::add(self, key, value, expiration)
+    }
+
+    fn cas(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: u32,
+        cas: u64,
+    ) -> Result<bool, MemcacheError> {
+        Client::cas(self, key, value, expiration, cas)
+    }
+}
+
+/// A [`Backend`] keeping token buckets in memcached, so all instances of an application share the same limits.
+///
+/// Buckets are updated with compare-and-swap: a concurrent update by another instance makes the update start over
+/// from the new state, up to a configurable number of [`attempts`](Self::attempts). Like the
+/// [`RedisBackend`](crate::RedisBackend), keys are stored by their [`KeyHasher`] identifier, e.g.
+/// `axum-limit:v1:5f3a…:10:1000`. The `memcache` client is blocking, so its calls run on tokio's blocking threads.
+#[derive(Clone)]
+pub struct MemcachedBackend {
+    client: Arc<dyn Store>,
+    hasher: KeyHasher,
+    prefix: String,
+    attempts: usize,
+}
+
+impl MemcachedBackend {
+    /// Constructs a new `MemcachedBackend` storing buckets through `client`, identifying keys with `hasher`.
+    pub fn new(client: Client, hasher: KeyHasher) -> Self {
+        Self::with_store(Arc::new(client), hasher)
+    }
+
+    /// Constructs a new `MemcachedBackend` storing buckets in `client`.
+    fn with_store(client: Arc<dyn Store>, hasher: KeyHasher) -> Self {
+        Self {
+            client,
+            hasher,
+            prefix: "axum-limit".to_string(),
+            attempts: 8,
+        }
+    }
+
+    /// Replaces the prefix of all bucket keys, `axum-limit` by default.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets how many times an update is attempted before giving up on contention, 8 by default.
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Returns the memcached key of the bucket of `count` tokens per `per` milliseconds for `key`.
+    fn bucket_key<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!(
+            "{}:{}:{count}:{per}",
+            self.prefix,
+            self.hasher.identify(key)
+        )
+    }
+
+    /// Applies `update` to the stored bucket with compare-and-swap, returning its result once the bucket was
+    /// stored, or `None` if the bucket does not exist and `create` is `false`.
+    fn update<T>(
+        &self,
+        key: &str,
+        count: usize,
+        per: u64,
+        create: bool,
+        update: impl Fn(&mut StoredBucket, u64) -> T,
+    ) -> Result<Option<T>, BackendError> {
+        // Buckets are full again once a period has passed since their last update.
+        let expiration = per.div_ceil(1000).max(1);
+        let expiration = if expiration > MAX_RELATIVE_EXPIRATION {
+            0
+        } else {
+            expiration as u32
+        };
+
+        for _ in 0..self.attempts {
+            let stored = self.client.gets(key).map_err(BackendError::new)?;
+            let Some((bytes, cas)) = stored else {
+                if !create {
+                    return Ok(None);
+                }
+                // Seed a full bucket, then take tokens from it with compare-and-swap like any other.
+                let full = StoredBucket::full(count, StoredBucket::now());
+                match self.client.add(key, &full.to_bytes(), expiration) {
+                    Ok(()) | Err(MemcacheError::CommandError(CommandError::KeyExists)) => continue,
+                    Err(error) => return Err(BackendError::new(error)),
+                }
+            };
+
+            let now = StoredBucket::now();
+            let mut bucket =
+                StoredBucket::from_bytes(&bytes).unwrap_or_else(|| StoredBucket::full(count, now));
+            let result = update(&mut bucket, now);
+            let cas = cas.ok_or_else(|| BackendError::new("memcached returned no CAS token"))?;
+            match self.client.cas(key, &bucket.to_bytes(), expiration, cas) {
+                Ok(true) => return Ok(Some(result)),
+                Ok(false)
+                | Err(MemcacheError::CommandError(
+                    CommandError::KeyExists | CommandError::KeyNotFound,
+                )) => continue,
+                Err(error) => return Err(BackendError::new(error)),
+            }
+        }
+        Err(BackendError::new(format!(
+            "bucket {key} is contended, gave up after {} attempts",
+            self.attempts
+        )))
+    }
+
+    /// Runs a blocking update of the bucket on tokio's blocking threads.
+    async fn spawn_update<T>(
+        &self,
+        key: String,
+        count: usize,
+        per: u64,
+        create: bool,
+        update: impl Fn(&mut StoredBucket, u64) -> T + Send + 'static,
+    ) -> Result<Option<T>, BackendError>
+    where
+        T: Send + 'static,
+    {
+        let backend = self.clone();
+        tokio::task::spawn_blocking(move || backend.update(&key, count, per, create, update))
+            .await
+            .map_err(BackendError::new)?
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for MemcachedBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.spawn_update(key, count, per, true, move |bucket, now| {
+            bucket.decide(count, per, cost, now)
+        })
+        .await?
+        .ok_or_else(|| BackendError::new("memcached bucket disappeared"))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.spawn_update(key, count, per, false, move |bucket, now| {
+            bucket.refund(count, per, cost, now)
+        })
+        .await
+        .map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// An in-memory memcached, letting other instances interfere with the next updates.
+    #[derive(Default)]
+    struct Fake {
+        values: Mutex<HashMap<String, (Vec<u8>, u64)>>,
+        /// The number of compare-and-swaps that lose against a concurrent update.
+        contended: Mutex<usize>,
+        /// A bucket another instance stores just before the next add.
+        seeded: Mutex<Option<StoredBucket>>,
+        /// The commands run, in order.
+        commands: Mutex<Vec<&'static str>>,
+    }
+
+    impl Fake {
+        fn log(&self, command: &'static str) {
+            self.commands
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(command);
+        }
+
+        fn commands(&self) -> Vec<&'static str> {
+            self.commands
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone()
+        }
+
+        fn stored(&self, key: &str) -> Option<StoredBucket> {
+            let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+            StoredBucket::from_bytes(&values.get(key)?.0)
+        }
+    }
+
+    impl Store for Fake {
+        fn gets(&self, key: &str) -> Result<Option<Item>, MemcacheError> {
+            self.log("gets");
+            let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(values
+                .get(key)
+                .map(|(bytes, cas)| (bytes.clone(), Some(*cas))))
+        }
+
+        fn add(&self, key: &str, value: &[u8], _expiration: u32) -> Result<(), MemcacheError> {
+            self.log("add");
+            let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(bucket) = self.seeded.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                values.insert(key.to_string(), (bucket.to_bytes().to_vec(), 1));
+            }
+            if values.contains_key(key) {
+                return Err(MemcacheError::CommandError(CommandError::KeyExists));
+            }
+            values.insert(key.to_string(), (value.to_vec(), 1));
+            Ok(())
+        }
+
+        fn cas(
+            &self,
+            key: &str,
+            value: &[u8],
+            _expiration: u32,
+            cas: u64,
+        ) -> Result<bool, MemcacheError> {
+            self.log("cas");
+            let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+            let Some((stored, version)) = values.get_mut(key) else {
+                return Err(MemcacheError::CommandError(CommandError::KeyNotFound));
+            };
+            let mut contended = self.contended.lock().unwrap_or_else(|e| e.into_inner());
+            if *contended > 0 {
+                *contended -= 1;
+                *version += 1;
+            }
+            if *version != cas {
+                return Ok(false);
+            }
+            *stored = value.to_vec();
+            *version += 1;
+            Ok(true)
+        }
+    }
+
+    fn backend(fake: &Arc<Fake>) -> MemcachedBackend {
+        MemcachedBackend::with_store(fake.clone(), KeyHasher::new(7))
+    }
+
+    #[tokio::test]
+    async fn retries_contended_updates() {
+        let fake = Arc::new(Fake::default());
+        *fake.contended.lock().unwrap_or_else(|e| e.into_inner()) = 2;
+        let backend = backend(&fake);
+
+        let decision = Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .expect("the update succeeds");
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 9);
+        assert_eq!(
+            fake.commands(),
+            ["gets", "add", "gets", "cas", "gets", "cas", "gets", "cas"]
+        );
+
+        // An update losing every compare-and-swap gives up.
+        *fake.contended.lock().unwrap_or_else(|e| e.into_inner()) = usize::MAX;
+        let backend = backend.attempts(3);
+        assert!(Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .is_err());
+        let key = backend.bucket_key("alice", 10, 60_000);
+        assert_eq!(fake.stored(&key).map(|bucket| bucket.tokens), Some(9));
+    }
+
+    #[tokio::test]
+    async fn seeds_missing_buckets_once() {
+        let fake = Arc::new(Fake::default());
+        let backend = backend(&fake);
+
+        // Another instance creates the bucket and takes a token between our read and our add.
+        let mut theirs = StoredBucket::full(10, StoredBucket::now());
+        theirs.decide(10, 60_000, 1, StoredBucket::now());
+        *fake.seeded.lock().unwrap_or_else(|e| e.into_inner()) = Some(theirs);
+
+        let decision = Backend::acquire(&backend, &"alice", 10, 60_000, 1)
+            .await
+            .expect("the update succeeds");
+        assert_eq!(decision.remaining, 8);
+        assert_eq!(fake.commands(), ["gets", "add", "gets", "cas"]);
+
+        // Refunds never create buckets.
+        Backend::refund(&backend, &"bob", 10, 60_000, 1)
+            .await
+            .expect("the refund succeeds");
+        let key = backend.bucket_key("bob", 10, 60_000);
+        assert!(fake.stored(&key).is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{Backend, BackendError, Decision};
+use futures_util::future::join;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// A [`Backend`] for switching storage without resetting every key's counters at once.
+///
+/// Until the cut-over, decisions are made by the old backend while every request is also applied to the new one,
+/// so the new backend's buckets converge to the old ones. Once the migration window has passed (which should be at
+/// least the longest limit period), or [`cut_over`](Self::cut_over) is called, only the new backend is used.
+pub struct MigratingBackend<O, N> {
+    old: O,
+    new: N,
+    cut_over_at: Instant,
+    cut_over: AtomicBool,
+}
+
+/// The phase of a [`MigratingBackend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationPhase {
+    /// Decisions come from the old backend, requests are applied to both backends.
+    DoubleWrite,
+    /// Only the new backend is used.
+    CutOver,
+}
+
+impl<O, N> MigratingBackend<O, N> {
+    /// Constructs a new `MigratingBackend` that double-writes for `window` before cutting over from `old` to `new`.
+    pub fn new(old: O, new: N, window: Duration) -> Self {
+        Self {
+            old,
+            new,
+            cut_over_at: Instant::now() + window,
+            cut_over: AtomicBool::new(false),
+        }
+    }
+
+    /// Cuts over to the new backend immediately.
+    pub fn cut_over(&self) {
+        self.cut_over.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the current phase of the migration.
+    pub fn phase(&self) -> MigrationPhase {
+        if self.cut_over.load(Ordering::Relaxed) {
+            return MigrationPhase::CutOver;
+        }
+        if Instant::now() >= self.cut_over_at {
+            self.cut_over();
+            return MigrationPhase::CutOver;
+        }
+        MigrationPhase::DoubleWrite
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, O, N> Backend<K> for MigratingBackend<O, N>
+where
+    K: Sync,
+    O: Backend<K>,
+    N: Backend<K>,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        match self.phase() {
+            MigrationPhase::DoubleWrite => {
+                // The new backend is only being warmed up, so its failures must not affect decisions.
+                let (old, _) = join(
+                    self.old.acquire(key, count, per, cost),
+                    self.new.acquire(key, count, per, cost),
+                )
+                .await;
+                old
+            }
+            MigrationPhase::CutOver => self.new.acquire(key, count, per, cost).await,
+        }
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        match self.phase() {
+            MigrationPhase::DoubleWrite => {
+                let (old, _) = join(
+                    self.old.refund(key, count, per, cost),
+                    self.new.refund(key, count, per, cost),
+                )
+                .await;
+                old
+            }
+            MigrationPhase::CutOver => self.new.refund(key, count, per, cost).await,
+        }
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        let (old, new) = join(self.old.flush(), self.new.flush()).await;
+        old.and(new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryBackend;
+    use http::Method;
+
+    #[tokio::test]
+    async fn new_backend_inherits_counters() {
+        let old = MemoryBackend::<Method>::default();
+        let new = MemoryBackend::<Method>::default();
+        let migration = MigratingBackend::new(old, new, Duration::from_secs(60));
+
+        let decision = migration.acquire(&Method::GET, 2, 60_000, 1).await;
+        assert!(decision.expect("memory backend").allowed);
+        assert_eq!(migration.phase(), MigrationPhase::DoubleWrite);
+
+        migration.cut_over();
+        let decision = migration.acquire(&Method::GET, 2, 60_000, 1).await;
+        assert_eq!(decision.expect("memory backend").remaining, 0);
+    }
+}
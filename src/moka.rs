@@ -0,0 +1,120 @@
+use crate::{Backend, BackendError, Clock, Decision, Key, SystemClock, TokenBucket};
+use ::moka::sync::Cache;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A [`Backend`] keeping token buckets in process memory like the [`MemoryBackend`](crate::MemoryBackend), but
+/// evicting the buckets of keys that have been idle for a configurable time to live, so limiting by high-cardinality
+/// keys such as users or IP addresses does not grow memory without bound.
+///
+/// The time to live should be at least the longest limit period: an evicted bucket is recreated full, which is
+/// only what refilling would have done by then.
+/// Clones share the same buckets.
+#[derive(Clone)]
+pub struct MokaBackend<K> {
+    buckets: Cache<K, Arc<Mutex<TokenBucket>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K> MokaBackend<K>
+where
+    K: Key + 'static,
+{
+    /// Constructs a new `MokaBackend` evicting buckets that were not used for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            buckets: Cache::builder().time_to_idle(ttl).build(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Constructs a new `MokaBackend` evicting buckets that were not used for `ttl`, and the least recently used
+    /// buckets beyond `max_keys`.
+    pub fn with_max_keys(ttl: Duration, max_keys: u64) -> Self {
+        Self {
+            buckets: Cache::builder()
+                .time_to_idle(ttl)
+                .max_capacity(max_keys)
+                .build(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replaces the clock used to refill buckets.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Takes `cost` tokens for `key` at the given point in time.
+    fn acquire_at(&self, key: &K, count: usize, per: u64, cost: usize, now: Instant) -> Decision {
+        let bucket = match self.buckets.get(key) {
+            Some(bucket) => bucket,
+            None => self.buckets.get_with(key.clone(), || {
+                Arc::new(Mutex::new(TokenBucket::new(count, per, now)))
+            }),
+        };
+        let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.decide_at(cost, now)
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for MokaBackend<K>
+where
+    K: Key + 'static,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        Ok(self.acquire_at(key, count, per, cost, self.clock.now()))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        _count: usize,
+        _per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        if let Some(bucket) = self.buckets.get(key) {
+            let now = self.clock.now();
+            bucket
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .refund(cost, now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+    use http::Method;
+
+    #[test]
+    fn evicts_idle_buckets() {
+        let backend = MokaBackend::new(Duration::from_millis(50));
+        let allowed = || {
+            backend
+                .acquire(&Method::GET, 1, 60_000, 1)
+                .now_or_never()
+                .and_then(Result::ok)
+                .is_some_and(|decision| decision.allowed)
+        };
+
+        assert!(allowed());
+        assert!(!allowed());
+        assert!(backend.buckets.contains_key(&Method::GET));
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!backend.buckets.contains_key(&Method::GET));
+        assert!(allowed());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{Group, Key, LimitRejection, LimitState, RateLimitInfo};
+use axum_core::extract::{FromRef, FromRequestParts};
+use http::request::Parts;
+use std::convert::Infallible;
+use std::ops::{Deref, DerefMut};
+
+/// A key that belongs to a parent key sharing an umbrella quota, e.g. a user belonging to an organization.
+pub trait Member: Key {
+    /// The type of the parent key.
+    type Parent: Key;
+
+    /// Returns the parent of this key.
+    fn parent(&self) -> Self::Parent;
+}
+
+/// Rate limit drawing on both the key's own limit of `COUNT` requests per `PER` milliseconds and its parent's
+/// limit of `PARENT_COUNT` requests per `PARENT_PER` milliseconds, so the members of one parent collectively cannot
+/// exceed the parent's contract.
+///
+/// Requires a `LimitState<K>` and a `LimitState<K::Parent>` in the router state. A request is only charged if both
+/// limits allow it: when the parent's limit rejects it, the token taken from the member's bucket is returned.
+///
+/// The two limits are not charged atomically. Until the token is returned, concurrent requests of the member may
+/// be rejected because of it, and a refund failing at the member's backend leaves the token charged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NestedLimit<
+    const COUNT: usize,
+    const PER: u64,
+    const PARENT_COUNT: usize,
+    const PARENT_PER: u64,
+    K,
+>(pub K::Extractor)
+where
+    K: Member;
+
+impl<const C: usize, const P: u64, const PC: usize, const PP: u64, K> Deref
+    for NestedLimit<C, P, PC, PP, K>
+where
+    K: Member,
+{
+    type Target = K::Extractor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const C: usize, const P: u64, const PC: usize, const PP: u64, K> DerefMut
+    for NestedLimit<C, P, PC, PP, K>
+where
+    K: Member,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<const C: usize, const P: u64, const PC: usize, const PP: u64, K> NestedLimit<C, P, PC, PP, K>
+where
+    K: Member,
+{
+    /// Consumes the limit and returns the inner extractor.
+    pub fn into_inner(self) -> K::Extractor {
+        self.0
+    }
+}
+
+#[async_trait::async_trait]
+impl<const C: usize, const P: u64, const PC: usize, const PP: u64, K, S> FromRequestParts<S>
+    for NestedLimit<C, P, PC, PP, K>
+where
+    LimitState<K>: FromRef<S>,
+    LimitState<K::Parent>: FromRef<S>,
+    S: Send + Sync,
+    K: Member + 'static,
+    K::Parent: 'static,
+    K::Extractor: FromRequestParts<S> + Send,
+{
+    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key_extractor = match K::Extractor::from_request_parts(parts, state).await {
+            Ok(ke) => ke,
+            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
+        };
+
+        let member_state: LimitState<K> = FromRef::from_ref(state);
+        let parent_state: LimitState<K::Parent> = FromRef::from_ref(state);
+        let key = K::from_extractor(&key_extractor);
+        let parent = key.parent();
+
+        enforce_both(
+            parts,
+            &member_state,
+            key,
+            (C, P),
+            &parent_state,
+            parent,
+            (PC, PP),
+        )
+        .await?;
+        Ok(Self(key_extractor))
+    }
+}
+
+/// The [`Group`] of all requests, keying the shared bucket of [`GlobalLimit`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Global;
+
+impl Group for Global {}
+
+/// Rate limit drawing on both the key's own limit of `COUNT` requests per `PER` milliseconds and a global limit of
+/// `GLOBAL_COUNT` requests per `GLOBAL_PER` milliseconds shared by all keys, capping total throughput while still
+/// enforcing fairness per key.
+///
+/// Requires a `LimitState<K>` and a `LimitState<Global>` in the router state. A request is only charged if both
+/// limits allow it: when the global limit rejects it, the token taken from the key's bucket is returned.
+///
+/// As with [`NestedLimit`], the two limits are not charged atomically, so a rejected request briefly holds a token
+/// of its key.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalLimit<
+    const COUNT: usize,
+    const PER: u64,
+    const GLOBAL_COUNT: usize,
+    const GLOBAL_PER: u64,
+    K,
+>(pub K::Extractor)
+where
+    K: Key;
+
+impl<const C: usize, const P: u64, const GC: usize, const GP: u64, K> Deref
+    for GlobalLimit<C, P, GC, GP, K>
+where
+    K: Key,
+{
+    type Target = K::Extractor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const C: usize, const P: u64, const GC: usize, const GP: u64, K> DerefMut
+    for GlobalLimit<C, P, GC, GP, K>
+where
+    K: Key,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<const C: usize, const P: u64, const GC: usize, const GP: u64, K> GlobalLimit<C, P, GC, GP, K>
+where
+    K: Key,
+{
+    /// Consumes the limit and returns the inner extractor.
+    pub fn into_inner(self) -> K::Extractor {
+        self.0
+    }
+}
+
+#[async_trait::async_trait]
+impl<const C: usize, const P: u64, const GC: usize, const GP: u64, K, S> FromRequestParts<S>
+    for GlobalLimit<C, P, GC, GP, K>
+where
+    LimitState<K>: FromRef<S>,
+    LimitState<Global>: FromRef<S>,
+    S: Send + Sync,
+    K: Key + 'static,
+    K::Extractor: FromRequestParts<S> + Send,
+{
+    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key_extractor = match K::Extractor::from_request_parts(parts, state).await {
+            Ok(ke) => ke,
+            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
+        };
+
+        let key_state: LimitState<K> = FromRef::from_ref(state);
+        let global_state: LimitState<Global> = FromRef::from_ref(state);
+        let key = K::from_extractor(&key_extractor);
+
+        enforce_both(
+            parts,
+            &key_state,
+            key,
+            (C, P),
+            &global_state,
+            Global,
+            (GC, GP),
+        )
+        .await?;
+        Ok(Self(key_extractor))
+    }
+}
+
+/// Enforces the limit of `key` and then that of `parent`, returning the token taken for `key` if the parent's limit
+/// rejects the request, and recording the most constrained quota of both. The refund is a best-effort compensation
+/// rather than a rollback, since the buckets may live in different backends.
+async fn enforce_both<K, T, R>(
+    parts: &mut Parts,
+    state: &LimitState<K>,
+    key: K,
+    (count, per): (usize, u64),
+    parent_state: &LimitState<T>,
+    parent: T,
+    (parent_count, parent_per): (usize, u64),
+) -> Result<(), LimitRejection<R>>
+where
+    K: Key + 'static,
+    T: Key + 'static,
+{
+    let decision = state.enforce(parts, key.clone(), count, per).await?;
+    let parent_result = parent_state
+        .enforce::<Infallible>(parts, parent, parent_count, parent_per)
+        .await;
+    match parent_result {
+        Ok(parent_decision) => {
+            RateLimitInfo::record(parts, decision);
+            RateLimitInfo::record(parts, parent_decision);
+            Ok(())
+        }
+        Err(rejection) => {
+            state.refund(&key, count, per, 1).await;
+            Err(rejection.widen())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{Method, StatusCode, Uri};
+
+    /// A user identified by a path like `/:org/:user`.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct User(String);
+
+    impl Key for User {
+        type Extractor = Uri;
+
+        fn from_extractor(extractor: &Self::Extractor) -> Self {
+            User(extractor.path().to_string())
+        }
+    }
+
+    impl Member for User {
+        type Parent = Uri;
+
+        fn parent(&self) -> Uri {
+            let org = self.0.split('/').nth(1).unwrap_or_default();
+            Uri::try_from(format!("/{org}")).unwrap_or_default()
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct AppState {
+        users: LimitState<User>,
+        orgs: LimitState<Uri>,
+    }
+
+    impl FromRef<AppState> for LimitState<User> {
+        fn from_ref(state: &AppState) -> Self {
+            state.users.clone()
+        }
+    }
+
+    impl FromRef<AppState> for LimitState<Uri> {
+        fn from_ref(state: &AppState) -> Self {
+            state.orgs.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn members_share_parent_quota() {
+        async fn handler(_: NestedLimit<2, 60_000, 3, 60_000, User>) {}
+
+        let state = AppState::default();
+        let my_app = Router::new()
+            .route("/:org/:user", get(handler))
+            .with_state(state.clone());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(
+            server.get("/acme/alice").await.status_code(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            server.get("/acme/alice").await.status_code(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            server.get("/acme/alice").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(server.get("/acme/bob").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/acme/bob").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(server.get("/other/bob").await.status_code(), StatusCode::OK);
+
+        // Bob's rejected request was refunded.
+        assert!(state.users.check(User("/acme/bob".to_string()), 2, 60_000));
+    }
+
+    #[derive(Clone, Default)]
+    struct GlobalState {
+        methods: LimitState<Method>,
+        global: LimitState<Global>,
+    }
+
+    impl FromRef<GlobalState> for LimitState<Method> {
+        fn from_ref(state: &GlobalState) -> Self {
+            state.methods.clone()
+        }
+    }
+
+    impl FromRef<GlobalState> for LimitState<Global> {
+        fn from_ref(state: &GlobalState) -> Self {
+            state.global.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn keys_share_global_quota() {
+        async fn handler(_: GlobalLimit<2, 60_000, 3, 60_000, Method>) {}
+
+        let state = GlobalState::default();
+        let my_app = Router::new()
+            .route("/", get(handler).post(handler))
+            .with_state(state.clone());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        assert_eq!(server.get("/").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(server.post("/").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.post("/").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        // The request rejected by the global limit was refunded.
+        assert!(state.methods.check(Method::POST, 2, 60_000));
+    }
+}
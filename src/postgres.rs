@@ -0,0 +1,211 @@
+use crate::backend::StoredBucket;
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use sqlx::PgPool;
+use std::hash::Hash;
+
+/// A [`Backend`] persisting token buckets in a PostgreSQL table, for limits whose counters must survive restarts,
+/// such as daily quotas.
+///
+/// Each decision locks the bucket's row within a transaction, timed by the database's clock. As every request
+/// costs a round trip to the database, this backend is best combined with in-memory storage for short periods
+/// using a [`PeriodSplitBackend`](crate::PeriodSplitBackend). Like the other remote backends, keys are stored by
+/// their [`KeyHasher`] identifier.
+#[derive(Clone)]
+pub struct PostgresBackend {
+    pool: PgPool,
+    hasher: KeyHasher,
+    table: String,
+}
+
+impl PostgresBackend {
+    /// Constructs a new `PostgresBackend` storing buckets through `pool`, identifying keys with `hasher`.
+    pub fn new(pool: PgPool, hasher: KeyHasher) -> Self {
+        Self {
+            pool,
+            hasher,
+            table: "axum_limit_buckets".to_string(),
+        }
+    }
+
+    /// Replaces the name of the table holding the buckets, `axum_limit_buckets` by default.
+    /// The name is used in queries as it is and must be a trusted identifier.
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Creates the table holding the buckets, unless it already exists.
+    pub async fn migrate(&self) -> Result<(), BackendError> {
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, tokens BIGINT NOT NULL, last_refill BIGINT NOT NULL)",
+            self.table
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(BackendError::new)?;
+        Ok(())
+    }
+
+    /// Returns the row key of the bucket of `count` tokens per `per` milliseconds for `key`.
+    fn bucket_key<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!("{}:{count}:{per}", self.hasher.identify(key))
+    }
+
+    /// Applies `update` to the stored bucket within a transaction, creating a full bucket first if `create` is
+    /// `true`. Returns `None` if the bucket does not exist.
+    async fn update<T>(
+        &self,
+        key: String,
+        count: usize,
+        create: bool,
+        update: impl FnOnce(&mut StoredBucket, u64) -> T + Send,
+    ) -> Result<Option<T>, BackendError> {
+        let mut transaction = self.pool.begin().await.map_err(BackendError::new)?;
+        if create {
+            sqlx::query(&format!(
+                "INSERT INTO {} (key, tokens, last_refill) \
+                 VALUES ($1, $2, (extract(epoch FROM clock_timestamp()) * 1000)::BIGINT) \
+                 ON CONFLICT (key) DO NOTHING",
+                self.table
+            ))
+            .bind(&key)
+            .bind(i64::try_from(count).unwrap_or(i64::MAX))
+            .execute(&mut *transaction)
+            .await
+            .map_err(BackendError::new)?;
+        }
+
+        let row: Option<(i64, i64, i64)> = sqlx::query_as(&format!(
+            "SELECT tokens, last_refill, (extract(epoch FROM clock_timestamp()) * 1000)::BIGINT \
+             FROM {} WHERE key = $1 FOR UPDATE",
+            self.table
+        ))
+        .bind(&key)
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(BackendError::new)?;
+        let Some((tokens, last_refill, now)) = row else {
+            return Ok(None);
+        };
+
+        let mut bucket = StoredBucket {
+            tokens: tokens.max(0) as u64,
+            last_refill: last_refill.max(0) as u64,
+        };
+        let result = update(&mut bucket, now.max(0) as u64);
+        sqlx::query(&format!(
+            "UPDATE {} SET tokens = $2, last_refill = $3 WHERE key = $1",
+            self.table
+        ))
+        .bind(&key)
+        .bind(i64::try_from(bucket.tokens).unwrap_or(i64::MAX))
+        .bind(i64::try_from(bucket.last_refill).unwrap_or(i64::MAX))
+        .execute(&mut *transaction)
+        .await
+        .map_err(BackendError::new)?;
+        transaction.commit().await.map_err(BackendError::new)?;
+        Ok(Some(result))
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for PostgresBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(key, count, true, |bucket, now| {
+            bucket.decide(count, per, cost, now)
+        })
+        .await?
+        .ok_or_else(|| BackendError::new("PostgreSQL bucket disappeared"))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(key, count, false, |bucket, now| {
+            bucket.refund(count, per, cost, now)
+        })
+        .await
+        .map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bucket_keys_identify_limits() {
+        let pool = PgPool::connect_lazy("postgres://localhost/axum_limit").expect("valid URL");
+        let backend = PostgresBackend::new(pool, KeyHasher::new(7));
+        let second = backend.bucket_key("alice", 10, 1_000);
+        assert_eq!(
+            second,
+            format!("{}:10:1000", KeyHasher::new(7).identify("alice"))
+        );
+        assert_ne!(second, backend.bucket_key("alice", 100, 60_000));
+    }
+
+    /// Runs against the database at `DATABASE_URL`, e.g. with
+    /// `DATABASE_URL=postgres://postgres@localhost cargo test --features postgres -- --ignored`.
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database at DATABASE_URL"]
+    async fn decides_within_transactions() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+        let pool = PgPool::connect(&url)
+            .await
+            .expect("the database is reachable");
+        let table = format!("axum_limit_test_{}", std::process::id());
+        let backend = PostgresBackend::new(pool.clone(), KeyHasher::new(7)).table(&table);
+        backend.migrate().await.expect("the table is created");
+
+        // Concurrent requests lock the row in turn, so exactly the limit is admitted.
+        let decisions = futures::future::join_all(
+            (0..10).map(|_| Backend::acquire(&backend, &"alice", 5, 60_000, 1)),
+        )
+        .await;
+        let allowed = decisions
+            .into_iter()
+            .map(|decision| decision.expect("the database decides"))
+            .filter(|decision| decision.allowed)
+            .count();
+        assert_eq!(allowed, 5);
+
+        Backend::refund(&backend, &"alice", 5, 60_000, 1)
+            .await
+            .expect("the refund succeeds");
+        let decision = Backend::acquire(&backend, &"alice", 5, 60_000, 1)
+            .await
+            .expect("the database decides");
+        assert!(decision.allowed);
+
+        // Refunds never create buckets.
+        Backend::refund(&backend, &"bob", 5, 60_000, 1)
+            .await
+            .expect("the refund succeeds");
+        let rows: (i64,) = sqlx::query_as(&format!("SELECT count(*) FROM {table}"))
+            .fetch_one(&pool)
+            .await
+            .expect("the table is readable");
+        assert_eq!(rows.0, 1);
+
+        sqlx::query(&format!("DROP TABLE {table}"))
+            .execute(&pool)
+            .await
+            .expect("the table is dropped");
+    }
+}
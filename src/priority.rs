@@ -0,0 +1,66 @@
+use crate::Decision;
+use http::request::Parts;
+
+type PriorityClassifier<K> = Box<dyn Fn(&Parts, &K) -> bool + Send + Sync>;
+
+/// Capacity reserved for high-priority traffic, so premium requests still succeed when a limit is under pressure.
+///
+/// When installed on a [`LimitState`](crate::LimitState), the classifier decides for every request whether it is of
+/// high priority. Other requests are rejected once taking their token would leave less than the reserved fraction
+/// of the limit, which only high-priority requests may use up.
+pub struct Priority<K> {
+    classifier: PriorityClassifier<K>,
+    reserved: f64,
+}
+
+impl<K> Priority<K> {
+    /// Constructs a new `Priority` treating requests for which `classifier` returns `true` as high-priority.
+    /// A fifth of every limit is reserved for them by default.
+    pub fn new(classifier: impl Fn(&Parts, &K) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            classifier: Box::new(classifier),
+            reserved: 0.2,
+        }
+    }
+
+    /// Sets the fraction of every limit reserved for high-priority requests, between `0.0` and `1.0`.
+    pub fn reserve(mut self, fraction: f64) -> Self {
+        self.reserved = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Decides whether an admitted request dipped into the reserved capacity without being of high priority.
+    pub(crate) fn intrudes(&self, parts: &Parts, key: &K, decision: &Decision) -> bool {
+        let reserved = (decision.limit as f64 * self.reserved).ceil() as usize;
+        decision.allowed && decision.remaining < reserved && !(self.classifier)(parts, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LimitState;
+    use http::{Method, Request};
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn reserves_capacity_for_high_priority() {
+        let state = LimitState::<Method>::default().with_priority(
+            Priority::new(|parts, _| parts.headers.contains_key("x-premium")).reserve(0.5),
+        );
+        let (standard, _) = Request::new(()).into_parts();
+        let (premium, _) = Request::builder()
+            .header("x-premium", "1")
+            .body(())
+            .expect("valid request")
+            .into_parts();
+        let enforce = |parts| state.enforce::<Infallible>(parts, Method::GET, 4, 60_000);
+
+        assert!(enforce(&standard).await.is_ok());
+        assert!(enforce(&standard).await.is_ok());
+        assert!(enforce(&standard).await.is_err());
+        assert!(enforce(&premium).await.is_ok());
+        assert!(enforce(&premium).await.is_ok());
+        assert!(enforce(&premium).await.is_err());
+    }
+}
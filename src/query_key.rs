@@ -0,0 +1,217 @@
+use crate::rejection::{render, Format};
+use crate::Key;
+use axum_core::extract::FromRequestParts;
+use axum_core::response::Response;
+use http::request::Parts;
+use http::{HeaderMap, StatusCode};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// Names the query parameter a [`QueryKey`] is extracted from, usually implemented with
+/// [`query_key!`](crate::query_key).
+pub trait KeyParam: Send + Sync + 'static {
+    /// The name of the parameter.
+    const NAME: &'static str;
+
+    /// Builds the response to a request without the parameter, `400 Bad Request` by default.
+    fn missing() -> Response {
+        render(
+            Format::default(),
+            StatusCode::BAD_REQUEST,
+            HeaderMap::new(),
+            &format!("Missing the `{}` query parameter.", Self::NAME),
+            None,
+        )
+    }
+}
+
+/// Decodes a component of an `application/x-www-form-urlencoded` query, returning `None` if it is not UTF-8.
+fn decode(component: &str) -> Option<String> {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// The value of the query parameter named by `P` as a key, e.g. `Limit<100, 60_000, QueryKey<ApiKeyParam>>` to
+/// limit each API key of a legacy API passing it as `?api_key=`. Requests without the parameter, or with an empty
+/// value, are rejected with [`KeyParam::missing`]; of repeated parameters, the first one counts.
+pub struct QueryKey<P> {
+    value: String,
+    _param: PhantomData<fn() -> P>,
+}
+
+impl<P> QueryKey<P> {
+    /// Returns the decoded value of the parameter.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl<P> Debug for QueryKey<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("QueryKey").field(&self.value).finish()
+    }
+}
+
+impl<P> Clone for QueryKey<P> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            _param: PhantomData,
+        }
+    }
+}
+
+impl<P> PartialEq for QueryKey<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<P> Eq for QueryKey<P> {}
+
+impl<P> Hash for QueryKey<P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+#[async_trait::async_trait]
+impl<P, S> FromRequestParts<S> for QueryKey<P>
+where
+    P: KeyParam,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+            .find(|(name, _)| decode(name).as_deref() == Some(P::NAME))
+            .and_then(|(_, value)| decode(value))
+            .filter(|value| !value.is_empty())
+            .map(|value| Self {
+                value,
+                _param: PhantomData,
+            })
+            .ok_or_else(P::missing)
+    }
+}
+
+impl<P> Key for QueryKey<P>
+where
+    P: KeyParam,
+{
+    type Extractor = Self;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        extractor.clone()
+    }
+}
+
+/// Declares a marker type naming a query parameter for [`QueryKey`], optionally with the response to requests
+/// without it.
+///
+/// ```
+/// use axum::{http::StatusCode, routing::get, Router};
+/// use axum_limit::{query_key, Limit, LimitState, QueryKey};
+///
+/// query_key! {
+///     /// The `api_key` query parameter.
+///     pub ApiKeyParam = "api_key" => (StatusCode::UNAUTHORIZED, "An API key is required.");
+/// }
+///
+/// async fn handler(_: Limit<100, 60_000, QueryKey<ApiKeyParam>>) {}
+///
+/// let _app: Router<()> = Router::new()
+///     .route("/", get(handler))
+///     .with_state(LimitState::<QueryKey<ApiKeyParam>>::default());
+/// ```
+#[macro_export]
+macro_rules! query_key {
+    ($(#[$meta:meta])* $vis:vis $name:ident = $param:literal $(;)?) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default)]
+        $vis struct $name;
+
+        impl $crate::KeyParam for $name {
+            const NAME: &'static str = $param;
+        }
+    };
+    ($(#[$meta:meta])* $vis:vis $name:ident = $param:literal => $missing:expr $(;)?) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default)]
+        $vis struct $name;
+
+        impl $crate::KeyParam for $name {
+            const NAME: &'static str = $param;
+
+            fn missing() -> $crate::__private::Response {
+                $crate::__private::IntoResponse::into_response($missing)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+
+    crate::query_key!(ApiKey = "api_key");
+
+    #[tokio::test]
+    async fn limits_each_query_param() {
+        async fn handler(Limit(key): Limit<1, 60_000, QueryKey<ApiKey>>) -> String {
+            key.value().to_owned()
+        }
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<QueryKey<ApiKey>>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/?page=2&api_key=a%2Bb+c").await.text(), "a+b c");
+        server.get("/?api_key=d").await.assert_status_ok();
+        server
+            .get("/?api_key=a%2Bb%20c")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+        for uri in ["/", "/?api_key=", "/?api_keys=a"] {
+            server
+                .get(uri)
+                .expect_failure()
+                .await
+                .assert_status(StatusCode::BAD_REQUEST);
+        }
+    }
+}
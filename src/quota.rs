@@ -0,0 +1,187 @@
+use crate::algorithm::unix_millis;
+use crate::cron::system_time;
+use crate::{Backend, BackendError, Clock, CronSchedule, Decision, SystemClock};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Persistent counters for the [`QuotaBackend`], e.g. rows of a database table keyed by key and period.
+///
+/// Periods are identified by the time their quota resets. Implementations should update counters atomically, e.g.
+/// with `UPDATE ... RETURNING` in SQL, so that several instances of the application can share them.
+#[async_trait::async_trait]
+pub trait QuotaStore<K>: Send + Sync {
+    /// Returns the number of requests counted for `key` in the period resetting at `reset`, zero if none.
+    async fn get(&self, key: &K, reset: SystemTime) -> Result<usize, BackendError>;
+
+    /// Adds `cost` requests to the count of `key` in the period resetting at `reset`, returning the new count.
+    async fn increment(
+        &self,
+        key: &K,
+        reset: SystemTime,
+        cost: usize,
+    ) -> Result<usize, BackendError>;
+
+    /// Removes `cost` requests from the count of `key` in the period resetting at `reset`.
+    async fn decrement(&self, key: &K, reset: SystemTime, cost: usize) -> Result<(), BackendError>;
+}
+
+/// A [`Backend`] enforcing long-running quotas, such as a million requests per month and API key, on counters kept
+/// in a [`QuotaStore`], since such windows cannot realistically live only in process memory.
+///
+/// Quotas reset at the times of a [`CronSchedule`], at midnight UTC on the first day of every month for
+/// [`monthly`](Self::monthly). Limits allow their count of requests between two resets, whatever their period.
+/// Requests beyond a quota are counted and then uncounted, so a rejected request never uses up quota.
+/// Clones share the same store.
+#[derive(Clone)]
+pub struct QuotaBackend<K> {
+    store: Arc<dyn QuotaStore<K>>,
+    schedule: Arc<CronSchedule>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K> QuotaBackend<K> {
+    /// Constructs a new `QuotaBackend` counting requests in `store`, resetting quotas at the times of `schedule`.
+    pub fn new(store: impl QuotaStore<K> + 'static, schedule: CronSchedule) -> Self {
+        Self {
+            store: Arc::new(store),
+            schedule: Arc::new(schedule),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Constructs a new `QuotaBackend` counting requests in `store`, resetting quotas at the start of every month.
+    pub fn monthly(store: impl QuotaStore<K> + 'static) -> Self {
+        Self::new(store, CronSchedule::monthly())
+    }
+
+    /// Replaces the clock used to tell the current period.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns the time the current period's quotas reset, and how long until then.
+    fn reset(&self) -> Result<(SystemTime, Duration), BackendError> {
+        let now = unix_millis(self.clock.now());
+        self.schedule
+            .next_after_millis(now)
+            .and_then(|next| {
+                let until = Duration::from_millis(u64::try_from(next - now).ok()?);
+                Some((system_time(next)?, until))
+            })
+            .ok_or_else(|| BackendError::new("the quota schedule has no upcoming reset"))
+    }
+
+    /// Returns the number of requests left to `key` in the current period for a quota of `count`, e.g. for billing
+    /// pages.
+    pub async fn remaining(&self, key: &K, count: usize) -> Result<usize, BackendError> {
+        let (reset, _) = self.reset()?;
+        Ok(count.saturating_sub(self.store.get(key, reset).await?))
+    }
+
+    /// Returns the time the current period's quotas reset.
+    pub fn next_reset(&self) -> Result<SystemTime, BackendError> {
+        Ok(self.reset()?.0)
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for QuotaBackend<K>
+where
+    K: Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        _per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let (reset, until) = self.reset()?;
+        let used = self.store.increment(key, reset, cost).await?;
+        let allowed = used <= count;
+        if !allowed {
+            self.store.decrement(key, reset, cost).await?;
+        }
+        let used = if allowed {
+            used
+        } else {
+            used.saturating_sub(cost)
+        };
+        Ok(Decision {
+            allowed,
+            limit: count,
+            remaining: count.saturating_sub(used),
+            reset: until,
+        })
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        _count: usize,
+        _per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let (reset, _) = self.reset()?;
+        self.store.decrement(key, reset, cost).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Counters(Mutex<HashMap<(&'static str, SystemTime), usize>>);
+
+    #[async_trait::async_trait]
+    impl QuotaStore<&'static str> for Counters {
+        async fn get(&self, key: &&'static str, reset: SystemTime) -> Result<usize, BackendError> {
+            let counters = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(counters.get(&(*key, reset)).copied().unwrap_or(0))
+        }
+
+        async fn increment(
+            &self,
+            key: &&'static str,
+            reset: SystemTime,
+            cost: usize,
+        ) -> Result<usize, BackendError> {
+            let mut counters = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            let used = counters.entry((*key, reset)).or_insert(0);
+            *used += cost;
+            Ok(*used)
+        }
+
+        async fn decrement(
+            &self,
+            key: &&'static str,
+            reset: SystemTime,
+            cost: usize,
+        ) -> Result<(), BackendError> {
+            let mut counters = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(used) = counters.get_mut(&(*key, reset)) {
+                *used = used.saturating_sub(cost);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_quota_in_store() {
+        let backend = QuotaBackend::monthly(Counters::default());
+        let acquire = |cost| backend.acquire(&"api-key", 3, 0, cost);
+
+        assert!(acquire(2).await.expect("store is available").allowed);
+        let decision = acquire(2).await.expect("store is available");
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 1);
+        assert!(decision.reset <= Duration::from_secs(31 * 24 * 60 * 60));
+        assert_eq!(backend.remaining(&"api-key", 3).await.ok(), Some(1));
+        assert!(acquire(1).await.expect("store is available").allowed);
+        assert_eq!(backend.remaining(&"api-key", 3).await.ok(), Some(0));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{Key, LimitRejection, LimitState, RateLimitInfo};
+use axum_core::extract::{FromRef, FromRequestParts};
+use http::request::Parts;
+use http::Method;
+use std::ops::{Deref, DerefMut};
+
+/// Classifies requests by whether their method is safe (read-only) or mutating.
+///
+/// As a key, `Access` gives reads and writes separate buckets, e.g. `Limit<100, 60_000, (UserId, Access)>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// A request with a safe method: `GET`, `HEAD`, `OPTIONS` or `TRACE`.
+    Read,
+    /// A request with any other method, e.g. `POST`, `PUT`, `PATCH` or `DELETE`.
+    Write,
+}
+
+impl From<&Method> for Access {
+    fn from(method: &Method) -> Self {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE => Access::Read,
+            _ => Access::Write,
+        }
+    }
+}
+
+impl Key for Access {
+    type Extractor = Method;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        Access::from(extractor)
+    }
+}
+
+/// Rate limit with separate budgets for reads and writes of the same key:
+/// requests with safe methods are limited to `READ_COUNT` per `READ_PER` milliseconds,
+/// all other requests to `WRITE_COUNT` per `WRITE_PER` milliseconds.
+///
+/// Requires a `LimitState<(K, Access)>` in the router state, so plans like "1000 reads plus 100 writes per minute"
+/// can be served by a single route:
+///
+/// ```
+/// use axum::{routing::get, Router};
+/// use axum_limit::{Access, LimitState, ReadWriteLimit};
+/// use http::Uri;
+///
+/// async fn handler(_: ReadWriteLimit<1000, 60_000, 100, 60_000, Uri>) {}
+///
+/// let _app: Router<()> = Router::new()
+///     .route("/items", get(handler).post(handler))
+///     .with_state(LimitState::<(Uri, Access)>::default());
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadWriteLimit<
+    const READ_COUNT: usize,
+    const READ_PER: u64,
+    const WRITE_COUNT: usize,
+    const WRITE_PER: u64,
+    K,
+>(pub K::Extractor)
+where
+    K: Key;
+
+impl<const RC: usize, const RP: u64, const WC: usize, const WP: u64, K> Deref
+    for ReadWriteLimit<RC, RP, WC, WP, K>
+where
+    K: Key,
+{
+    type Target = K::Extractor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const RC: usize, const RP: u64, const WC: usize, const WP: u64, K> DerefMut
+    for ReadWriteLimit<RC, RP, WC, WP, K>
+where
+    K: Key,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<const RC: usize, const RP: u64, const WC: usize, const WP: u64, K>
+    ReadWriteLimit<RC, RP, WC, WP, K>
+where
+    K: Key,
+{
+    /// Consumes the limit and returns the inner extractor.
+    pub fn into_inner(self) -> K::Extractor {
+        self.0
+    }
+}
+
+#[async_trait::async_trait]
+impl<const RC: usize, const RP: u64, const WC: usize, const WP: u64, K, S> FromRequestParts<S>
+    for ReadWriteLimit<RC, RP, WC, WP, K>
+where
+    LimitState<(K, Access)>: FromRef<S>,
+    S: Send + Sync,
+    K: Key + 'static,
+    K::Extractor: FromRequestParts<S> + Send,
+{
+    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key_extractor = match K::Extractor::from_request_parts(parts, state).await {
+            Ok(ke) => ke,
+            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
+        };
+
+        let limit_state: LimitState<(K, Access)> = FromRef::from_ref(state);
+        let access = Access::from(&parts.method);
+        let (count, per) = match access {
+            Access::Read => (RC, RP),
+            Access::Write => (WC, WP),
+        };
+        let key = (K::from_extractor(&key_extractor), access);
+        let decision = limit_state.enforce(parts, key, count, per).await?;
+        RateLimitInfo::record(parts, decision);
+        Ok(Self(key_extractor))
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use ::redis::aio::ConnectionLike;
+use ::redis::Script;
+use std::hash::Hash;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Refills and takes tokens from the bucket at `KEYS[1]` like [`TokenBucket`](crate::TokenBucket), timed by the
+/// server's clock. Buckets expire once they would be full again.
+const ACQUIRE: &str = r"
+local capacity = tonumber(ARGV[1])
+local per = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'last')
+local tokens = tonumber(state[1]) or capacity
+local last = tonumber(state[2]) or now
+local elapsed = now - last
+if elapsed >= per then
+    if per == 0 then
+        tokens = capacity
+        last = now
+    else
+        tokens = math.min(capacity, tokens + math.floor(elapsed / per) * capacity)
+        last = now - elapsed % per
+    end
+end
+local allowed = 0
+if tokens >= cost then
+    tokens = tokens - cost
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tokens, 'last', last)
+redis.call('PEXPIRE', KEYS[1], math.max(per, 1))
+return { allowed, tokens, math.max(per - (now - last), 0) }
+";
+
+/// Admits requests by the generic cell rate algorithm like `redis-cell`, storing only the theoretical arrival time
+/// of the next request at `KEYS[1]`, timed by the server's clock. Requests are spaced `per / count` milliseconds
+/// apart, with bursts of up to `count` requests. Returns whether the request is allowed, the remaining quota and,
+/// for denied requests, the time until it would be allowed, or else the time until the full quota is available.
+const GCRA: &str = r"
+local count = tonumber(ARGV[1])
+local per = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3])
+if count == 0 then
+    return { 0, 0, per }
+end
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + tonumber(time[2]) / 1000
+local interval = per / count
+local tat = math.max(tonumber(redis.call('GET', KEYS[1])) or now, now)
+local new_tat = tat + cost * interval
+local allow_at = new_tat - per
+if allow_at > now then
+    return { 0, math.floor((per - (tat - now)) / interval), math.ceil(allow_at - now) }
+end
+redis.call('SET', KEYS[1], string.format('%.3f', new_tat), 'PX', math.max(math.ceil(new_tat - now), 1))
+return { 1, math.floor((per - (new_tat - now)) / interval), math.ceil(new_tat - now) }
+";
+
+/// Returns tokens to the bucket at `KEYS[1]`, if it exists, without exceeding its capacity.
+const REFUND: &str = r"
+local tokens = tonumber(redis.call('HGET', KEYS[1], 'tokens'))
+if tokens then
+    redis.call('HSET', KEYS[1], 'tokens', math.min(tonumber(ARGV[1]), tokens + tonumber(ARGV[2])))
+end
+return 0
+";
+
+fn acquire_script() -> &'static Script {
+    static SCRIPT: OnceLock<Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| Script::new(ACQUIRE))
+}
+
+fn gcra_script() -> &'static Script {
+    static SCRIPT: OnceLock<Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| Script::new(GCRA))
+}
+
+fn refund_script() -> &'static Script {
+    static SCRIPT: OnceLock<Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| Script::new(REFUND))
+}
+
+/// A [`Backend`] keeping token buckets in Redis, so all instances of an application share the same limits.
+///
+/// Works with any async connection of the `redis` crate, e.g. a `MultiplexedConnection` or, for Redis Cluster
+/// deployments, a `ClusterConnection` (see [`cluster`](Self::cluster) behind the `redis-cluster` feature), which
+/// follows `MOVED` and `ASK` redirections so limits keep working while slots are resharded. Each bucket is updated
+/// by a single script call, atomically.
+///
+/// Keys are stored by their [`KeyHasher`] identifier wrapped in a hash tag, e.g. `axum-limit:{v1:5f3a…}:10:1000`,
+/// so all buckets of a key live in the same cluster slot.
+///
+/// With [`gcra`](Self::gcra), requests are admitted by the generic cell rate algorithm instead, in the style of
+/// `redis-cell`.
+#[derive(Clone)]
+pub struct RedisBackend<C> {
+    pub(crate) connection: C,
+    hasher: KeyHasher,
+    prefix: String,
+    gcra: bool,
+}
+
+impl<C> RedisBackend<C> {
+    /// Constructs a new `RedisBackend` storing buckets through `connection`, identifying keys with `hasher`.
+    pub fn new(connection: C, hasher: KeyHasher) -> Self {
+        Self {
+            connection,
+            hasher,
+            prefix: "axum-limit".to_string(),
+            gcra: false,
+        }
+    }
+
+    /// Replaces the prefix of all bucket keys, `axum-limit` by default.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Admits requests by the generic cell rate algorithm, spacing them evenly over the period while allowing bursts
+    /// of up to the whole limit, instead of refilling buckets once per period. Each decision is a single script
+    /// call storing a single timestamp per key. Refunds are not supported and do nothing.
+    pub fn gcra(mut self) -> Self {
+        self.gcra = true;
+        self
+    }
+
+    /// Returns the Redis key of the bucket of `count` tokens per `per` milliseconds for `key`.
+    pub(crate) fn bucket_key<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!(
+            "{}:{{{}}}:{count}:{per}",
+            self.prefix,
+            self.hasher.identify(key)
+        )
+    }
+}
+
+#[cfg(feature = "redis-cluster")]
+impl RedisBackend<::redis::cluster_async::ClusterConnection> {
+    /// Connects to the Redis Cluster reachable through the given initial `nodes`.
+    pub async fn cluster<T>(
+        nodes: impl IntoIterator<Item = T>,
+        hasher: KeyHasher,
+    ) -> Result<Self, BackendError>
+    where
+        T: ::redis::IntoConnectionInfo,
+    {
+        let client = ::redis::cluster::ClusterClient::new(nodes).map_err(BackendError::new)?;
+        let connection = client
+            .get_async_connection()
+            .await
+            .map_err(BackendError::new)?;
+        Ok(Self::new(connection, hasher))
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, C> Backend<K> for RedisBackend<C>
+where
+    K: Hash + Sync,
+    C: ConnectionLike + Clone + Send + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let mut connection = self.connection.clone();
+        let script = if self.gcra {
+            gcra_script()
+        } else {
+            acquire_script()
+        };
+        let (allowed, remaining, reset): (u8, usize, u64) = script
+            .key(self.bucket_key(key, count, per))
+            .arg(count)
+            .arg(per)
+            .arg(cost)
+            .invoke_async(&mut connection)
+            .await
+            .map_err(BackendError::new)?;
+        Ok(Decision {
+            allowed: allowed == 1,
+            limit: count,
+            remaining,
+            reset: Duration::from_millis(reset),
+        })
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        if self.gcra {
+            return Ok(());
+        }
+        let mut connection = self.connection.clone();
+        refund_script()
+            .key(self.bucket_key(key, count, per))
+            .arg(count)
+            .arg(cost)
+            .invoke_async::<()>(&mut connection)
+            .await
+            .map_err(BackendError::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::redis::{Arg, Cmd, Pipeline, RedisFuture, Value};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn bucket_keys_share_hash_tag() {
+        let backend = RedisBackend::new((), KeyHasher::new(7)).prefix("app");
+        let second = backend.bucket_key("alice", 10, 1_000);
+        let minute = backend.bucket_key("alice", 100, 60_000);
+
+        let tag = |key: &str| key.split(['{', '}']).nth(1).map(str::to_string);
+        assert_eq!(tag(&second), Some(KeyHasher::new(7).identify("alice")));
+        assert_eq!(tag(&second), tag(&minute));
+        assert_ne!(second, minute);
+        assert!(second.starts_with("app:{v1:"));
+    }
+
+    /// A connection recording the scripts it was asked to run, answering with a denial.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl ConnectionLike for Recorder {
+        fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+            if let Some(Arg::Simple(sha)) = cmd.args_iter().nth(1) {
+                self.0
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(sha.to_vec());
+            }
+            let reply = [0, 3, 250].map(Value::Int).to_vec();
+            Box::pin(async { Ok(Value::Array(reply)) })
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            _cmd: &'a Pipeline,
+            _offset: usize,
+            _count: usize,
+        ) -> RedisFuture<'a, Vec<Value>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn gcra_decides_in_one_script_call() {
+        let recorder = Recorder::default();
+        let backend = RedisBackend::new(recorder.clone(), KeyHasher::new(7)).gcra();
+
+        let decision = backend
+            .acquire(&"alice", 10, 1_000, 1)
+            .await
+            .expect("Failed to acquire");
+        assert_eq!(
+            decision,
+            Decision {
+                allowed: false,
+                limit: 10,
+                remaining: 3,
+                reset: Duration::from_millis(250),
+            }
+        );
+        let calls = recorder.0.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        assert_eq!(calls, vec![gcra_script().get_hash().as_bytes().to_vec()]);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::map::{Map, Set};
+use http::request::Parts;
+use http::Method;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+/// A limit as declared by an extractor on a route, recorded by a [`LimitRegistry`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeclaredLimit {
+    /// The route template the limit applies to, e.g. `/users/:id`.
+    /// Only available with the `axum` feature; otherwise `None`.
+    pub route: Option<String>,
+    /// The HTTP method of the route.
+    pub method: Method,
+    /// The number of requests allowed within the period.
+    pub count: usize,
+    /// The period (in milliseconds) for which the limit applies.
+    pub per: u64,
+    /// The type name of the key the limit is applied by.
+    pub key: &'static str,
+}
+
+/// A registry of all limits that have been enforced at least once, for generating documentation
+/// pages or admin views from the limits actually declared in code.
+///
+/// A registry can be shared by the [`LimitState`](crate::LimitState)s of several key types,
+/// so a single registry covers the whole application.
+#[derive(Clone, Default)]
+pub struct LimitRegistry {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    seen: Set<u64>,
+    limits: Map<u64, DeclaredLimit>,
+    hasher: RandomState,
+}
+
+impl LimitRegistry {
+    /// Returns all limits recorded so far, ordered by route and method.
+    pub fn limits(&self) -> Vec<DeclaredLimit> {
+        let mut limits: Vec<DeclaredLimit> = self
+            .inner
+            .limits
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        limits.sort_by(|a, b| {
+            (&a.route, a.method.as_str(), a.count, a.per).cmp(&(
+                &b.route,
+                b.method.as_str(),
+                b.count,
+                b.per,
+            ))
+        });
+        limits
+    }
+
+    /// Records the limit enforced for a request, unless it is already known.
+    pub(crate) fn register(&self, parts: &Parts, count: usize, per: u64, key: &'static str) {
+        let route = matched_path(parts);
+        // Hashing avoids allocating for the common case of an already registered limit.
+        let id = self
+            .inner
+            .hasher
+            .hash_one((route, parts.method.as_str(), count, per, key));
+        if self.inner.seen.contains(&id) || !self.inner.seen.insert(id) {
+            return;
+        }
+
+        self.inner.limits.insert(
+            id,
+            DeclaredLimit {
+                route: route.map(str::to_string),
+                method: parts.method.clone(),
+                count,
+                per,
+                key,
+            },
+        );
+    }
+}
+
+#[cfg(feature = "axum")]
+fn matched_path(parts: &Parts) -> Option<&str> {
+    parts
+        .extensions
+        .get::<axum::extract::MatchedPath>()
+        .map(|path| path.as_str())
+}
+
+#[cfg(not(feature = "axum"))]
+fn matched_path(_: &Parts) -> Option<&str> {
+    None
+}
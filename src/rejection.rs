@@ -0,0 +1,583 @@
+use crate::{retry_after_secs, Decision};
+use axum_core::response::{IntoResponse, Response};
+use http::header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER};
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builds the response to requests exceeding their rate limit, see
+/// [`LimitState::with_rejection_response`](crate::LimitState::with_rejection_response).
+pub(crate) type Responder = Arc<dyn Fn(&RateLimitExceeded) -> Response + Send + Sync>;
+
+/// Header carrying the number of requests allowed per period.
+pub static X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+
+/// Header carrying the number of requests remaining in the current period.
+pub static X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+
+/// Header carrying the seconds until the quota is replenished.
+pub static X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Header of draft-ietf-httpapi-ratelimit-headers carrying the remaining quota and the seconds until it is
+/// replenished, e.g. `"default";r=0;t=30`.
+pub static RATELIMIT: HeaderName = HeaderName::from_static("ratelimit");
+
+/// Header of draft-ietf-httpapi-ratelimit-headers carrying the quota and its window in seconds, e.g.
+/// `"default";q=100;w=60`.
+pub static RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// The header fields describing quotas in responses, see
+/// [`LimitState::with_rate_limit_headers`](crate::LimitState::with_rate_limit_headers).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RateLimitHeaders {
+    /// The legacy `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset` headers.
+    #[default]
+    Legacy,
+    /// The `RateLimit` and `RateLimit-Policy` headers standardized by draft-ietf-httpapi-ratelimit-headers.
+    Ietf,
+    /// Both the legacy and the standardized headers, for clients migrating from the former.
+    Both,
+}
+
+impl RateLimitHeaders {
+    /// Describes the quota of `decision`, whose limit applies per `window`, in `headers`.
+    pub(crate) fn insert(self, headers: &mut HeaderMap, decision: &Decision, window: Duration) {
+        let reset = retry_after_secs(decision.reset);
+        if matches!(self, Self::Legacy | Self::Both) {
+            headers.insert(X_RATELIMIT_LIMIT.clone(), decision.limit.into());
+            headers.insert(X_RATELIMIT_REMAINING.clone(), decision.remaining.into());
+            headers.insert(X_RATELIMIT_RESET.clone(), reset.into());
+        }
+        if matches!(self, Self::Ietf | Self::Both) {
+            let policy = match retry_after_secs(window) {
+                0 => format!("\"default\";q={}", decision.limit),
+                window => format!("\"default\";q={};w={window}", decision.limit),
+            };
+            let quota = format!("\"default\";r={};t={reset}", decision.remaining);
+            for (name, value) in [(&RATELIMIT_POLICY, policy), (&RATELIMIT, quota)] {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    headers.insert(name.clone(), value);
+                }
+            }
+        }
+    }
+}
+
+/// The quota of a key that exceeded its rate limit, carried by
+/// [`LimitRejection::RateLimitExceeded`](crate::LimitRejection::RateLimitExceeded).
+///
+/// Responds with `429 Too Many Requests`, a `Retry-After` header if the time to retry is known, and headers
+/// describing the quota, the `X-RateLimit-*` headers unless the [`LimitState`](crate::LimitState) selected other
+/// [`RateLimitHeaders`], so clients can back off until the quota is replenished. The response can be replaced with
+/// [`LimitState::with_rejection_response`](crate::LimitState::with_rejection_response).
+#[derive(Clone)]
+pub struct RateLimitExceeded {
+    /// The number of requests allowed within the period.
+    pub limit: usize,
+    /// The number of requests still allowed within the current period.
+    pub remaining: usize,
+    /// The time until the quota is replenished, zero if unknown.
+    pub reset: Duration,
+    /// The time until the request could be admitted, e.g. until the next token is added to the bucket, zero if
+    /// unknown.
+    pub retry_after: Duration,
+    /// The period the limit applies to, zero if it has none.
+    pub window: Duration,
+    /// The key that exceeded its limit, as formatted by the function installed with
+    /// [`LimitState::with_key_format`](crate::LimitState::with_key_format), if any.
+    pub key: Option<String>,
+    headers: RateLimitHeaders,
+    format: Format,
+    responder: Option<Responder>,
+}
+
+impl Debug for RateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitExceeded")
+            .field("limit", &self.limit)
+            .field("remaining", &self.remaining)
+            .field("reset", &self.reset)
+            .field("retry_after", &self.retry_after)
+            .field("window", &self.window)
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimitExceeded {
+    /// Constructs a new `RateLimitExceeded` for the quota of `decision`, whose limit applies per `window`, admitting
+    /// the request after `retry_after` and described by `headers` in responses.
+    pub(crate) fn new(
+        decision: Decision,
+        retry_after: Duration,
+        window: Duration,
+        headers: RateLimitHeaders,
+    ) -> Self {
+        Self {
+            limit: decision.limit,
+            remaining: decision.remaining,
+            reset: decision.reset,
+            retry_after,
+            window,
+            key: None,
+            headers,
+            format: Format::default(),
+            responder: None,
+        }
+    }
+
+    /// Attributes the rejection to the formatted `key`, if any.
+    pub(crate) fn for_key(mut self, key: Option<String>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Renders the default response to the request in `format`.
+    pub(crate) fn negotiated(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Builds the response to the request with `responder` instead of the default one, if there is one.
+    pub(crate) fn responding_with(mut self, responder: Option<Responder>) -> Self {
+        self.responder = responder;
+        self
+    }
+
+    /// Constructs a new `RateLimitExceeded` for a limit of `limit` requests that is used up, e.g. by requests
+    /// still in flight, without telling when it will be replenished.
+    pub(crate) fn exhausted(limit: usize) -> Self {
+        Self {
+            limit,
+            remaining: 0,
+            reset: Duration::ZERO,
+            retry_after: Duration::ZERO,
+            window: Duration::ZERO,
+            key: None,
+            headers: RateLimitHeaders::default(),
+            format: Format::default(),
+            responder: None,
+        }
+    }
+
+    /// Builds the default response, ignoring any installed responder.
+    pub(crate) fn default_response(&self) -> Response {
+        render(
+            self.format,
+            StatusCode::TOO_MANY_REQUESTS,
+            self.headers(),
+            &self.to_string(),
+            self.known_retry_after(),
+        )
+    }
+
+    /// Returns the time until the request could be admitted, if known.
+    fn known_retry_after(&self) -> Option<Duration> {
+        Some(self.retry_after).filter(|retry_after| !retry_after.is_zero())
+    }
+
+    /// Returns the headers of the default response: `Retry-After` if the time to retry is known, and the headers
+    /// describing the quota, for custom responses to keep them.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let decision = Decision {
+            allowed: false,
+            limit: self.limit,
+            remaining: self.remaining,
+            reset: self.reset,
+        };
+        self.headers.insert(&mut headers, &decision, self.window);
+        if let Some(retry_after) = self.known_retry_after() {
+            headers.insert(RETRY_AFTER, retry_after_secs(retry_after).into());
+        }
+        headers
+    }
+}
+
+/// A request refused for another reason than its quota, e.g. a ban, carried by the
+/// [`LimitRejection`](crate::LimitRejection) variants other than those exceeding a rate limit.
+///
+/// Responds in the format negotiated with the client, like [`RateLimitExceeded`], with a `Retry-After` header if the
+/// time to retry is known.
+#[derive(Debug, Clone)]
+pub struct Refusal {
+    /// The time until the client is asked to retry, if known.
+    pub retry_after: Option<Duration>,
+    format: Format,
+}
+
+impl Refusal {
+    /// Constructs a new `Refusal` asking the client to retry after `retry_after`, rendered in `format`.
+    pub(crate) fn new(retry_after: Option<Duration>, format: Format) -> Self {
+        Self {
+            retry_after,
+            format,
+        }
+    }
+
+    /// Renders the refusal with `status` and the human-readable `detail`.
+    pub(crate) fn render(&self, status: StatusCode, detail: &str) -> Response {
+        render(
+            self.format,
+            status,
+            HeaderMap::new(),
+            detail,
+            self.retry_after,
+        )
+    }
+}
+
+/// The representation of a rejection's body, negotiated from the `Accept` header of the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// Plain text.
+    Text,
+    /// A JSON object with the members of an RFC 7807 problem.
+    Json,
+    /// An RFC 7807 `application/problem+json` body.
+    Problem,
+    /// An HTML page, for browsers.
+    Html,
+    /// A gRPC status, for gRPC requests.
+    Grpc,
+}
+
+impl Default for Format {
+    /// Plain text, or problem details with the `problem-details` feature.
+    fn default() -> Self {
+        if cfg!(feature = "problem-details") {
+            Self::Problem
+        } else {
+            Self::Text
+        }
+    }
+}
+
+impl Format {
+    /// Picks the format most preferred by the `Accept` header in `headers`, considering HTML only if `html` is set,
+    /// and falling back to the default format. gRPC requests are answered with a gRPC status if `grpc` is set.
+    pub(crate) fn negotiate(headers: &HeaderMap, html: bool, grpc: bool) -> Self {
+        if grpc && crate::grpc::is_grpc(headers) {
+            return Self::Grpc;
+        }
+        let mut preferred = (Self::default(), 0.0);
+        let ranges = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for range in ranges {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f64>().ok())
+                .unwrap_or(1.0);
+            let format = match media_type.as_str() {
+                "application/problem+json" => Self::Problem,
+                "application/json" if Self::default() == Self::Problem => Self::Problem,
+                "application/json" => Self::Json,
+                "text/html" if html => Self::Html,
+                "text/plain" | "text/*" => Self::Text,
+                "*/*" => Self::default(),
+                _ => continue,
+            };
+            if quality > preferred.1 {
+                preferred = (format, quality);
+            }
+        }
+        preferred.0
+    }
+}
+
+/// Quotes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Renders a rejection with `status`, `headers` and the human-readable `detail` in `format`, asking the client to
+/// retry after `retry_after` if known.
+pub(crate) fn render(
+    format: Format,
+    status: StatusCode,
+    mut headers: HeaderMap,
+    detail: &str,
+    retry_after: Option<Duration>,
+) -> Response {
+    if format == Format::Grpc {
+        return crate::grpc::render(status, headers, detail, retry_after);
+    }
+    let retry_after = retry_after.map(retry_after_secs);
+    if let Some(secs) = retry_after {
+        headers.insert(RETRY_AFTER, secs.into());
+    }
+    let title = status.canonical_reason().unwrap_or_default();
+    let (content_type, body) = match format {
+        Format::Text | Format::Grpc => {
+            return (status, headers, detail.to_string()).into_response()
+        }
+        Format::Json | Format::Problem => {
+            let mut problem = format!(
+                "{{\"type\":\"about:blank\",\"title\":{},\"status\":{},\"detail\":{}",
+                json_string(title),
+                status.as_u16(),
+                json_string(detail)
+            );
+            if let Some(secs) = retry_after {
+                problem.push_str(&format!(",\"retry_after\":{secs}"));
+            }
+            problem.push('}');
+            let content_type = match format {
+                Format::Problem => "application/problem+json",
+                _ => "application/json",
+            };
+            (content_type, problem)
+        }
+        Format::Html => {
+            let retry = retry_after
+                .map(|secs| format!("<p>Please retry in {secs} seconds.</p>"))
+                .unwrap_or_default();
+            let page = format!(
+                "<!DOCTYPE html><html><head><title>{} {title}</title></head>\
+                 <body><h1>{title}</h1><p>{detail}</p>{retry}</body></html>",
+                status.as_u16()
+            );
+            ("text/html; charset=utf-8", page)
+        }
+    };
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    (status, headers, body).into_response()
+}
+
+impl Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limit exceeded.")
+    }
+}
+
+impl IntoResponse for RateLimitExceeded {
+    fn into_response(self) -> Response {
+        match &self.responder {
+            Some(responder) => responder(&self),
+            None => self.default_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chaos, Key, Limit, LimitRejection, LimitState};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::Uri;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn emits_quota_headers() {
+        async fn handler(_: Limit<2, 60_000, Uri>) {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        server.get("/").await;
+        server.get("/").await;
+        let response = server.get("/").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header(&X_RATELIMIT_LIMIT), "2");
+        assert_eq!(response.header(&X_RATELIMIT_REMAINING), "0");
+        let reset: u64 = response
+            .header(&X_RATELIMIT_RESET)
+            .to_str()
+            .expect("ASCII header")
+            .parse()
+            .expect("whole seconds");
+        assert!((1..=60).contains(&reset));
+        assert_eq!(response.header(RETRY_AFTER), reset.to_string());
+        assert!(response.maybe_header(&RATELIMIT).is_none());
+    }
+
+    #[tokio::test]
+    async fn emits_ietf_headers() {
+        async fn handler(_: Limit<2, 60_000, Uri>) {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::default().with_rate_limit_headers(RateLimitHeaders::Ietf));
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        server.get("/").await;
+        server.get("/").await;
+        let response = server.get("/").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header(&RATELIMIT_POLICY), "\"default\";q=2;w=60");
+        let quota = response.header(&RATELIMIT);
+        let quota = quota.to_str().expect("ASCII header");
+        assert!(quota.starts_with("\"default\";r=0;t="), "{quota}");
+        assert!(response.maybe_header(&X_RATELIMIT_LIMIT).is_none());
+    }
+
+    #[tokio::test]
+    async fn builds_custom_responses() {
+        async fn handler(_: Limit<1, 60_000, Uri>) {}
+
+        fn responder(exceeded: &RateLimitExceeded) -> Response {
+            let body = format!("{{\"error\":\"quota\",\"limit\":{}}}", exceeded.limit);
+            (StatusCode::SERVICE_UNAVAILABLE, exceeded.headers(), body).into_response()
+        }
+
+        let state = LimitState::default().with_rejection_response(responder);
+        let my_app = Router::new().route("/", get(handler)).with_state(state);
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        server.get("/").await;
+        let response = server.get("/").await;
+        assert_eq!(response.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.header(&X_RATELIMIT_LIMIT), "1");
+        assert_eq!(response.text(), "{\"error\":\"quota\",\"limit\":1}");
+
+        // Chaos rejections are built by the responder too.
+        let state = LimitState::default()
+            .with_chaos(Chaos::new(1.0))
+            .with_rejection_response(responder);
+        let my_app = Router::new().route("/", get(handler)).with_state(state);
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.get("/").await;
+        assert_eq!(response.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.text(), "{\"error\":\"quota\",\"limit\":1}");
+    }
+
+    #[test]
+    fn renders_problem_details() {
+        let response = render(
+            Format::Problem,
+            StatusCode::TOO_MANY_REQUESTS,
+            HeaderMap::new(),
+            "Rate limit \"exceeded\".",
+            Some(Duration::from_millis(1_500)),
+        );
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+        assert_eq!(response.headers()[RETRY_AFTER], "2");
+        let body = futures::executor::block_on(axum::body::to_bytes(response.into_body(), 1024))
+            .expect("body is complete");
+        assert_eq!(
+            body,
+            "{\"type\":\"about:blank\",\"title\":\"Too Many Requests\",\"status\":429,\
+             \"detail\":\"Rate limit \\\"exceeded\\\".\",\"retry_after\":2}"
+        );
+    }
+
+    #[test]
+    fn negotiates_formats() {
+        let accept = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACCEPT, HeaderValue::from_static(value));
+            headers
+        };
+        let json = if cfg!(feature = "problem-details") {
+            Format::Problem
+        } else {
+            Format::Json
+        };
+        assert_eq!(
+            Format::negotiate(&HeaderMap::new(), true, false),
+            Format::default()
+        );
+        assert_eq!(
+            Format::negotiate(&accept("application/json"), false, false),
+            json
+        );
+        assert_eq!(
+            Format::negotiate(
+                &accept("text/html,application/xhtml+xml,*/*;q=0.8"),
+                true,
+                false
+            ),
+            Format::Html
+        );
+        assert_eq!(
+            Format::negotiate(&accept("text/html,*/*;q=0.8"), false, false),
+            Format::default()
+        );
+        assert_eq!(
+            Format::negotiate(&accept("application/json;q=0.5, text/plain"), true, false),
+            Format::Text
+        );
+    }
+
+    #[tokio::test]
+    async fn carries_structured_data() {
+        let state = LimitState::<http::Method>::default().with_key_format(|key| key.to_string());
+        let (parts, _) = http::Request::new(()).into_parts();
+        let enforce = || state.enforce::<Infallible>(&parts, http::Method::GET, 1, 60_000);
+
+        assert!(enforce().await.is_ok());
+        let Err(LimitRejection::RateLimitExceeded(exceeded)) = enforce().await else {
+            panic!("the limit is exceeded");
+        };
+        assert_eq!(exceeded.limit, 1);
+        assert_eq!(exceeded.remaining, 0);
+        assert_eq!(exceeded.window, Duration::from_secs(60));
+        assert!(exceeded.reset <= Duration::from_secs(60));
+        assert_eq!(exceeded.key.as_deref(), Some("GET"));
+    }
+
+    #[tokio::test]
+    async fn keys_customize_rejections() {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        struct Tenant(String);
+
+        impl Key for Tenant {
+            type Extractor = Uri;
+
+            fn from_extractor(extractor: &Self::Extractor) -> Self {
+                Tenant(extractor.path().trim_start_matches('/').to_string())
+            }
+
+            fn rejection(&self, exceeded: &RateLimitExceeded) -> Option<Response> {
+                let body = format!("{}: slow down.", self.0);
+                (self.0 == "acme").then(|| {
+                    (StatusCode::TOO_MANY_REQUESTS, exceeded.headers(), body).into_response()
+                })
+            }
+        }
+
+        async fn handler(_: Limit<1, 60_000, Tenant>) {}
+
+        let my_app = Router::new()
+            .route("/:tenant", get(handler))
+            .with_state(LimitState::<Tenant>::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        server.get("/acme").await;
+        let response = server.get("/acme").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.text(), "acme: slow down.");
+        assert_eq!(response.header(&X_RATELIMIT_LIMIT), "1");
+
+        server.get("/other").await;
+        let response = server.get("/other").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,204 @@
+//! Replaying recorded traffic against a proposed rate limit.
+//!
+//! Before enabling a limit on a production route, access logs can be replayed against it to find out how many
+//! requests would have been rejected:
+//!
+//! ```
+//! use axum_limit::replay::{parse_csv, replay};
+//!
+//! let log = "10.0.0.1,1700000000.0\n10.0.0.1,1700000000.5\n10.0.0.2,1700000000.6\n";
+//! let records = parse_csv(log.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+//!
+//! let report = replay(records, 1, 1000);
+//! assert_eq!(report.total, 3);
+//! assert_eq!(report.rejected, 1);
+//! ```
+
+use crate::TokenBucket;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::time::{Duration, Instant};
+
+/// A single recorded request: the limit key and when the request arrived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The key the request would be limited by, e.g. the client address.
+    pub key: String,
+    /// The arrival time of the request, as a duration since the Unix epoch.
+    pub timestamp: Duration,
+}
+
+/// The outcome of replaying a set of records against a rate limit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The number of replayed requests.
+    pub total: usize,
+    /// The number of requests that would have been allowed.
+    pub allowed: usize,
+    /// The number of requests that would have been rejected.
+    pub rejected: usize,
+    /// The number of rejected requests per key, for keys with at least one rejection.
+    pub rejected_by_key: HashMap<String, usize>,
+}
+
+impl ReplayReport {
+    /// Returns the fraction of requests that would have been rejected, between `0.0` and `1.0`.
+    pub fn rejection_rate(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.rejected as f64 / self.total as f64
+        }
+    }
+}
+
+/// Replays `records` against a limit of `count` requests per `per` milliseconds for each key.
+/// Records do not need to be sorted; they are replayed in timestamp order.
+pub fn replay(records: impl IntoIterator<Item = Record>, count: usize, per: u64) -> ReplayReport {
+    let mut records: Vec<Record> = records.into_iter().collect();
+    records.sort_by_key(|record| record.timestamp);
+
+    let mut report = ReplayReport::default();
+    let Some(first) = records.first().map(|record| record.timestamp) else {
+        return report;
+    };
+
+    // Recorded timestamps are mapped onto the monotonic clock the buckets operate on.
+    let start = Instant::now();
+    let mut buckets: HashMap<String, TokenBucket> = HashMap::new();
+    for record in records {
+        let now = start + (record.timestamp - first);
+        let bucket = buckets
+            .entry(record.key.clone())
+            .or_insert_with(|| TokenBucket::new(count, per, now));
+
+        report.total += 1;
+        if bucket.try_acquire_at(now) {
+            report.allowed += 1;
+        } else {
+            report.rejected += 1;
+            *report.rejected_by_key.entry(record.key).or_default() += 1;
+        }
+    }
+
+    report
+}
+
+/// Parses records from lines of the form `key,timestamp`, where the timestamp is in (possibly fractional)
+/// seconds since the Unix epoch. Empty lines and lines starting with `#` are skipped.
+pub fn parse_csv(reader: impl BufRead) -> impl Iterator<Item = io::Result<Record>> {
+    parse_lines(reader, |line| {
+        let (key, timestamp) = line.rsplit_once(',')?;
+        let timestamp = Duration::try_from_secs_f64(timestamp.trim().parse().ok()?).ok()?;
+        Some(Record {
+            key: key.trim().to_string(),
+            timestamp,
+        })
+    })
+}
+
+/// Parses records from access logs in the Common Log Format (and formats extending it, such as the Combined
+/// Log Format), using the remote host as the key:
+///
+/// ```text
+/// 127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326
+/// ```
+pub fn parse_common_log(reader: impl BufRead) -> impl Iterator<Item = io::Result<Record>> {
+    parse_lines(reader, |line| {
+        let (host, rest) = line.split_once(' ')?;
+        let (_, rest) = rest.split_once('[')?;
+        let (time, _) = rest.split_once(']')?;
+        Some(Record {
+            key: host.to_string(),
+            timestamp: parse_clf_time(time)?,
+        })
+    })
+}
+
+fn parse_lines<R, F>(reader: R, parse: F) -> impl Iterator<Item = io::Result<Record>>
+where
+    R: BufRead,
+    F: Fn(&str) -> Option<Record>,
+{
+    reader.lines().enumerate().filter_map(move |(index, line)| {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        Some(parse(line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed record on line {}: {line}", index + 1),
+            )
+        }))
+    })
+}
+
+/// Parses a Common Log Format timestamp such as `10/Oct/2000:13:55:36 -0700`.
+fn parse_clf_time(time: &str) -> Option<Duration> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let (datetime, zone) = time.split_once(' ')?;
+    let mut parts = datetime.splitn(3, '/');
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let mut parts = parts.next()?.split(':');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let hour: i64 = parts.next()?.parse().ok()?;
+    let minute: i64 = parts.next()?.parse().ok()?;
+    let second: i64 = parts.next()?.parse().ok()?;
+
+    let sign = match zone.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let offset: i64 = zone.get(1..)?.parse().ok()?;
+    let offset = sign * ((offset / 100) * 3600 + (offset % 100) * 60);
+
+    let secs =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    Some(Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+/// Returns the number of days since the Unix epoch for a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_common_log() {
+        let log = r#"
+127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /a HTTP/1.0" 200 2326
+127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /b HTTP/1.0" 200 2326
+127.0.0.2 - - [10/Oct/2000:13:55:37 -0700] "GET /a HTTP/1.0" 200 2326
+127.0.0.1 - frank [10/Oct/2000:13:55:37 -0700] "GET /c HTTP/1.0" 200 2326
+"#;
+        let records = parse_common_log(log.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .expect("valid log");
+        assert_eq!(records[0].timestamp, Duration::from_secs(971_211_336));
+
+        let report = replay(records, 1, 1000);
+        assert_eq!(report.total, 4);
+        assert_eq!(report.rejected, 1);
+        assert_eq!(report.rejected_by_key.get("127.0.0.1"), Some(&1));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::Key;
+use tower_sessions::session::Id;
+use tower_sessions::Session;
+
+/// The ID of the `tower-sessions` session of a request as a key, so `Limit<100, 60_000, SessionKey>` limits each
+/// session.
+///
+/// The session is extracted from the request like `Session`, which requires the `SessionManagerLayer`. Sessions
+/// are only assigned an ID once they are saved, so all requests without a stored session share a single key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionKey(pub Option<Id>);
+
+impl SessionKey {
+    /// Returns the ID of the session, or `None` for requests without a stored session.
+    pub fn id(&self) -> Option<Id> {
+        self.0
+    }
+}
+
+impl Key for SessionKey {
+    type Extractor = Session;
+
+    fn from_extractor(extractor: &Self::Extractor) -> Self {
+        Self(extractor.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Limit, LimitState};
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServerConfig;
+    use http::StatusCode;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    #[tokio::test]
+    async fn limits_each_session() {
+        async fn sign_in(session: Session) {
+            session
+                .insert("user", "alice")
+                .await
+                .expect("session is stored");
+        }
+
+        async fn handler(_: Limit<1, 60_000, SessionKey>) {}
+
+        let my_app = Router::new()
+            .route("/", get(handler))
+            .with_state(LimitState::<SessionKey>::default())
+            .route("/sign-in", get(sign_in))
+            .layer(SessionManagerLayer::new(MemoryStore::default()).with_secure(false));
+
+        let config = TestServerConfig::builder().save_cookies();
+        let alice = config
+            .clone()
+            .build_server(my_app.clone())
+            .expect("Failed to create test server");
+        let bob = config
+            .build_server(my_app)
+            .expect("Failed to create test server");
+
+        alice.get("/sign-in").await;
+        bob.get("/sign-in").await;
+        alice.get("/").await;
+        bob.get("/").await.assert_status_ok();
+        alice
+            .get("/")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{Backend, BackendError, Clock, Decision, Key, SystemClock, TokenBucket};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The slice of a key's bucket held by one shard.
+struct Slice {
+    count: usize,
+    bucket: TokenBucket,
+}
+
+type Shard<K> = Mutex<HashMap<K, Slice>>;
+
+/// A [`Backend`] splitting every bucket into slices held by separate shards, so threads running on different cores
+/// take tokens without contending for the same locks.
+///
+/// Each thread is assigned one shard and only draws on its slice of a key's tokens: a key whose traffic is skewed
+/// towards a few threads is rejected before it used up its whole limit, until [`reconcile`](Self::reconcile)
+/// (or a reconciler started with [`spawn_reconciler`](Self::spawn_reconciler)) rebalances the unused tokens across
+/// the shards. With an [`accuracy`](Self::accuracy) of zero, the slices add up to exactly the limit; a larger value
+/// gives every slice extra tokens to reject fewer requests between reconciliations, letting up to that fraction of
+/// the limit through on top of it.
+///
+/// Keys whose slices are all full again on reconciliation are evicted, since a new key would start the same way.
+/// Clones share the same shards.
+#[derive(Clone)]
+pub struct ShardedBackend<K> {
+    shards: Arc<[Shard<K>]>,
+    accuracy: f64,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K> ShardedBackend<K>
+where
+    K: Key,
+{
+    /// Constructs a new `ShardedBackend` with `shards` shards, usually the number of worker threads.
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Mutex::default()).collect(),
+            accuracy: 0.0,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the fraction of the limit, between `0.0` and `1.0`, that may be let through on top of it, spread over
+    /// the shards.
+    pub fn accuracy(mut self, accuracy: f64) -> Self {
+        self.accuracy = accuracy.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Replaces the clock used to refill buckets.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns the number of tokens of a bucket of `count` tokens held by the shard at `index`.
+    fn capacity(&self, count: usize, index: usize) -> usize {
+        let shards = self.shards.len();
+        let extra = (count as f64 * self.accuracy / shards as f64) as usize;
+        (count / shards + usize::from(index < count % shards)).saturating_add(extra)
+    }
+
+    /// Returns the index of the shard assigned to the current thread.
+    fn shard_index(&self) -> usize {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        thread_local! {
+            static INDEX: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+        }
+        INDEX.with(|index| index % self.shards.len())
+    }
+
+    fn lock(shard: &Shard<K>) -> MutexGuard<'_, HashMap<K, Slice>> {
+        shard.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Takes `cost` tokens for `key` from the current thread's slice at the given point in time.
+    fn acquire_at(&self, key: &K, count: usize, per: u64, cost: usize, now: Instant) -> Decision {
+        let index = self.shard_index();
+        let mut shard = Self::lock(&self.shards[index]);
+        let slice = match shard.get_mut(key) {
+            Some(slice) => slice,
+            None => shard.entry(key.clone()).or_insert_with(|| Slice {
+                count,
+                bucket: TokenBucket::new(self.capacity(count, index), per, now),
+            }),
+        };
+        Decision {
+            limit: count,
+            ..slice.bucket.decide_at(cost, now)
+        }
+    }
+
+    /// Rebalances the unused tokens of every key evenly across the shards, so keys whose traffic is skewed towards
+    /// some threads can use their whole limit. Tokens are only moved, never added. Idle keys are evicted.
+    pub fn reconcile(&self) {
+        let now = self.clock.now();
+        let mut shards: Vec<_> = self.shards.iter().map(Self::lock).collect();
+        let keys: HashSet<K> = shards
+            .iter()
+            .flat_map(|shard| shard.keys().cloned())
+            .collect();
+
+        for key in keys {
+            let Some((count, per)) = shards
+                .iter()
+                .find_map(|shard| shard.get(&key))
+                .map(|slice| (slice.count, slice.bucket.refill_duration))
+            else {
+                continue;
+            };
+            let per = per.as_millis() as u64;
+
+            // Shards that have not seen the key yet would start with a full slice.
+            let mut slices: Vec<&mut Slice> = Vec::with_capacity(shards.len());
+            for (index, shard) in shards.iter_mut().enumerate() {
+                let capacity = self.capacity(count, index);
+                let slice = shard.entry(key.clone()).or_insert_with(|| Slice {
+                    count,
+                    bucket: TokenBucket::new(capacity, per, now),
+                });
+                slice.bucket.refill(now);
+                slices.push(slice);
+            }
+
+            if slices
+                .iter()
+                .all(|slice| slice.bucket.tokens == slice.bucket.capacity)
+            {
+                drop(slices);
+                for shard in &mut shards {
+                    shard.remove(&key);
+                }
+                continue;
+            }
+
+            let mut tokens: usize = slices.iter().map(|slice| slice.bucket.tokens).sum();
+            let mut shares = slices.len();
+            // Fill the smallest slices first, so tokens a full slice cannot take go to the others.
+            slices.sort_by_key(|slice| slice.bucket.capacity);
+            for slice in slices {
+                let share = tokens.div_ceil(shares).min(slice.bucket.capacity);
+                slice.bucket.tokens = share;
+                tokens -= share;
+                shares -= 1;
+            }
+        }
+    }
+
+    /// Starts a thread calling [`reconcile`](Self::reconcile) every `interval`, until the backend and all of its
+    /// clones are dropped.
+    pub fn spawn_reconciler(&self, interval: Duration) -> JoinHandle<()>
+    where
+        K: 'static,
+    {
+        let shards: Weak<[Shard<K>]> = Arc::downgrade(&self.shards);
+        let accuracy = self.accuracy;
+        let clock = self.clock.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let Some(shards) = shards.upgrade() else {
+                return;
+            };
+            ShardedBackend {
+                shards,
+                accuracy,
+                clock: clock.clone(),
+            }
+            .reconcile();
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for ShardedBackend<K>
+where
+    K: Key,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        Ok(self.acquire_at(key, count, per, cost, self.clock.now()))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        _count: usize,
+        _per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let now = self.clock.now();
+        if let Some(slice) = Self::lock(&self.shards[self.shard_index()]).get_mut(key) {
+            slice.bucket.refund(cost, now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+    use http::Method;
+
+    fn allowed(backend: &ShardedBackend<Method>, key: Method) -> bool {
+        backend
+            .acquire(&key, 4, 60_000, 1)
+            .now_or_never()
+            .and_then(Result::ok)
+            .is_some_and(|decision| decision.allowed)
+    }
+
+    #[test]
+    fn reconcile_moves_unused_tokens() {
+        let backend = ShardedBackend::new(2);
+
+        // This thread only holds half of the limit.
+        assert!(allowed(&backend, Method::GET));
+        assert!(allowed(&backend, Method::GET));
+        assert!(!allowed(&backend, Method::GET));
+
+        // The other shard's unused tokens are split between both shards.
+        backend.reconcile();
+        assert!(allowed(&backend, Method::GET));
+        assert!(!allowed(&backend, Method::GET));
+
+        // Accuracy trades strictness for extra tokens in every slice.
+        let relaxed = ShardedBackend::new(2).accuracy(0.5);
+        assert_eq!((0..4).filter(|_| allowed(&relaxed, Method::GET)).count(), 3);
+
+        // Accuracy is capped at the whole limit again, and slices of huge limits saturate.
+        let capped = ShardedBackend::<Method>::new(2).accuracy(f64::MAX);
+        assert_eq!(capped.capacity(usize::MAX, 0), usize::MAX);
+        assert_eq!(capped.capacity(4, 0), 4);
+    }
+
+    #[derive(Clone)]
+    struct Manual(Arc<Mutex<Instant>>);
+
+    impl Clock for Manual {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    #[test]
+    fn reconcile_evicts_idle_keys() {
+        let clock = Manual(Arc::new(Mutex::new(Instant::now())));
+        let backend = ShardedBackend::new(2).with_clock(clock.clone());
+        assert!(allowed(&backend, Method::GET));
+        assert!(allowed(&backend, Method::POST));
+
+        backend.reconcile();
+        let keys = |backend: &ShardedBackend<Method>| {
+            backend
+                .shards
+                .iter()
+                .map(|shard| ShardedBackend::lock(shard).len())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&backend), [2, 2]);
+
+        // Once a period has passed, both buckets are full again.
+        *clock.0.lock().unwrap_or_else(|e| e.into_inner()) += Duration::from_secs(60);
+        assert!(allowed(&backend, Method::GET));
+        backend.reconcile();
+        assert_eq!(keys(&backend), [1, 1]);
+    }
+}
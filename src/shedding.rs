@@ -0,0 +1,128 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type LoadProbe = Box<dyn Fn() -> Load + Send + Sync>;
+
+/// The pressure on the system, as measured by the probe of a [`LoadShedder`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Load {
+    /// The fraction of CPU capacity in use, between `0.0` and `1.0`.
+    pub cpu: f64,
+    /// The fraction of memory in use, between `0.0` and `1.0`.
+    pub memory: f64,
+}
+
+/// A global guard rejecting requests while the system is overloaded, so rate limits double as overload protection.
+///
+/// When installed on a [`LimitState`](crate::LimitState), every request is rejected with `503 Service Unavailable`
+/// and a `Retry-After` header while the CPU or memory pressure reported by the probe crosses its threshold, before
+/// any key is charged. The probe is supplied by the application, e.g. backed by the `sysinfo` crate or cgroup
+/// statistics, and is called at most once per probe interval.
+pub struct LoadShedder {
+    probe: LoadProbe,
+    cpu: f64,
+    memory: f64,
+    interval: Duration,
+    retry_after: Duration,
+    last: Mutex<Option<(Instant, bool)>>,
+}
+
+impl LoadShedder {
+    /// Constructs a new `LoadShedder` measuring the system with `probe`. Requests are shed while either the CPU or
+    /// memory pressure exceeds 90% by default, probing once per second and asking clients to retry after a second.
+    pub fn new(probe: impl Fn() -> Load + Send + Sync + 'static) -> Self {
+        Self {
+            probe: Box::new(probe),
+            cpu: 0.9,
+            memory: 0.9,
+            interval: Duration::from_secs(1),
+            retry_after: Duration::from_secs(1),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Sets the fraction of CPU capacity in use beyond which requests are shed.
+    pub fn cpu_threshold(mut self, fraction: f64) -> Self {
+        self.cpu = fraction;
+        self
+    }
+
+    /// Sets the fraction of memory in use beyond which requests are shed.
+    pub fn memory_threshold(mut self, fraction: f64) -> Self {
+        self.memory = fraction;
+        self
+    }
+
+    /// Sets how long a measurement of the probe is reused.
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the delay after which shed clients are asked to retry.
+    pub fn retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after = delay;
+        self
+    }
+
+    /// Returns the delay to ask clients to retry after if requests are being shed at `now`.
+    pub(crate) fn shed(&self, now: Instant) -> Option<Duration> {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let overloaded = match *last {
+            Some((probed, overloaded)) if now.saturating_duration_since(probed) < self.interval => {
+                overloaded
+            }
+            _ => {
+                let load = (self.probe)();
+                let overloaded = load.cpu > self.cpu || load.memory > self.memory;
+                *last = Some((now, overloaded));
+                overloaded
+            }
+        };
+        overloaded.then_some(self.retry_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LimitRejection, LimitState};
+    use axum_core::response::IntoResponse;
+    use http::{header, Method, Request, StatusCode};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn sheds_while_overloaded() {
+        let overloaded = Arc::new(AtomicBool::new(true));
+        let probe = overloaded.clone();
+        let state = LimitState::<Method>::default().with_load_shedding(
+            LoadShedder::new(move || Load {
+                cpu: if probe.load(Ordering::Relaxed) {
+                    0.95
+                } else {
+                    0.2
+                },
+                memory: 0.5,
+            })
+            .probe_interval(Duration::ZERO),
+        );
+        let (parts, _) = Request::new(()).into_parts();
+
+        let rejection = state
+            .enforce::<Infallible>(&parts, Method::GET, 1, 1000)
+            .await
+            .expect_err("requests are shed");
+        assert!(matches!(rejection, LimitRejection::Overloaded(_)));
+        let response = rejection.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        overloaded.store(false, Ordering::Relaxed);
+        assert!(state
+            .enforce::<Infallible>(&parts, Method::GET, 1, 1000)
+            .await
+            .is_ok());
+    }
+}
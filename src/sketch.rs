@@ -0,0 +1,142 @@
+use crate::{Backend, BackendError, Clock, Decision, SystemClock};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A counter of the sketch, valid for one window.
+#[derive(Debug, Clone, Copy, Default)]
+struct Cell {
+    window: u64,
+    count: usize,
+}
+
+/// A [`Backend`] counting requests in a count-min sketch of fixed size instead of keeping state per key, so
+/// limiting by keys of unbounded cardinality, such as IP addresses of internet-facing services, cannot be turned
+/// against memory.
+///
+/// Requests are counted in fixed windows of `per` milliseconds. Every key is hashed into one counter of each row of
+/// the sketch, and its count is estimated as the smallest of them. Keys colliding in all rows share a count, so a
+/// key may occasionally be rejected before it used up its limit, but never admitted beyond it. The probability of
+/// over-counting shrinks with the width and depth of the sketch. Refunds are not supported.
+/// Clones share the same sketch.
+#[derive(Clone)]
+pub struct SketchBackend {
+    rows: Arc<Mutex<Vec<Vec<Cell>>>>,
+    hashers: Arc<[RandomState]>,
+    epoch: Instant,
+    clock: Arc<dyn Clock>,
+}
+
+impl SketchBackend {
+    /// Constructs a new `SketchBackend` with `depth` rows of `width` counters each.
+    pub fn new(width: usize, depth: usize) -> Self {
+        let clock = SystemClock;
+        Self {
+            rows: Arc::new(Mutex::new(vec![
+                vec![Cell::default(); width.max(1)];
+                depth.max(1)
+            ])),
+            hashers: (0..depth.max(1)).map(|_| RandomState::new()).collect(),
+            epoch: clock.now(),
+            clock: Arc::new(clock),
+        }
+    }
+
+    /// Replaces the clock used to track windows.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.epoch = clock.now();
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Counts `cost` requests of `key` against a limit of `count` per window of `per` milliseconds at `now`.
+    fn acquire_at<K: Hash>(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+        now: Instant,
+    ) -> Decision {
+        let per = per.max(1);
+        let elapsed = now.saturating_duration_since(self.epoch).as_millis() as u64;
+        let window = elapsed / per + 1;
+        let reset = Duration::from_millis(per - elapsed % per);
+
+        let mut rows = self.rows.lock().unwrap_or_else(|e| e.into_inner());
+        let indices: Vec<usize> = self
+            .hashers
+            .iter()
+            .zip(rows.iter())
+            .map(|(hasher, row)| (hasher.hash_one((key, per)) % row.len() as u64) as usize)
+            .collect();
+        let estimate = rows
+            .iter()
+            .zip(&indices)
+            .map(|(row, &index)| match row[index] {
+                cell if cell.window == window => cell.count,
+                _ => 0,
+            })
+            .min()
+            .unwrap_or(0);
+
+        let allowed = estimate.saturating_add(cost) <= count;
+        let used = if allowed {
+            // Conservative update: only raise counters below the new estimate, limiting over-counting.
+            let used = estimate + cost;
+            for (row, &index) in rows.iter_mut().zip(&indices) {
+                let cell = &mut row[index];
+                if cell.window != window {
+                    *cell = Cell { window, count: 0 };
+                }
+                cell.count = cell.count.max(used);
+            }
+            used
+        } else {
+            estimate
+        };
+        Decision {
+            allowed,
+            limit: count,
+            remaining: count.saturating_sub(used),
+            reset,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for SketchBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        Ok(self.acquire_at(key, count, per, cost, self.clock.now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_per_window() {
+        let backend = SketchBackend::new(1024, 4);
+        let start = backend.epoch;
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert!(backend.acquire_at(&"a", 2, 1_000, 1, at(0)).allowed);
+        assert!(backend.acquire_at(&"a", 2, 1_000, 1, at(400)).allowed);
+        let decision = backend.acquire_at(&"a", 2, 1_000, 1, at(600));
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_millis(400));
+        assert!(backend.acquire_at(&"b", 2, 1_000, 2, at(600)).allowed);
+        assert!(backend.acquire_at(&"a", 2, 1_000, 2, at(1_000)).allowed);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::backend::StoredBucket;
+use crate::map::Map;
+use crate::{Backend, BackendError, Decision, KeyHasher};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A [`Backend`] persisting token buckets in an embedded `sled` database, so limits of single-node deployments
+/// survive restarts without external infrastructure.
+///
+/// Buckets are loaded into memory on first access to their key and written through to the database on every
+/// change; `sled` flushes them to disk in the background, or on [`flush`](Self::flush). Buckets are timed by
+/// wall-clock time, so time passing while the application is down refills them. Like the remote backends, keys are
+/// stored by their [`KeyHasher`] identifier.
+/// Clones share the same buckets.
+#[derive(Clone)]
+pub struct SledBackend {
+    tree: ::sled::Tree,
+    hasher: KeyHasher,
+    buckets: Arc<Map<String, StoredBucket>>,
+}
+
+impl SledBackend {
+    /// Constructs a new `SledBackend` storing buckets in `tree`, identifying keys with `hasher`.
+    pub fn new(tree: ::sled::Tree, hasher: KeyHasher) -> Self {
+        Self {
+            tree,
+            hasher,
+            buckets: Arc::new(Map::new()),
+        }
+    }
+
+    /// Writes all changed buckets to disk.
+    pub async fn flush(&self) -> Result<(), BackendError> {
+        self.tree.flush_async().await.map_err(BackendError::new)?;
+        Ok(())
+    }
+
+    /// Returns the database key of the bucket of `count` tokens per `per` milliseconds for `key`.
+    fn bucket_key<K: Hash + ?Sized>(&self, key: &K, count: usize, per: u64) -> String {
+        format!("{}:{count}:{per}", self.hasher.identify(key))
+    }
+
+    /// Applies `update` to the bucket stored at `key`, loading it from the database first if it is not in memory
+    /// yet, and writes the result through. Buckets that do not exist are only created if `create` is `true`.
+    fn update<T>(
+        &self,
+        key: String,
+        count: usize,
+        create: bool,
+        update: impl FnOnce(&mut StoredBucket, u64) -> T,
+    ) -> Result<Option<T>, BackendError> {
+        let now = StoredBucket::now();
+        let mut bucket = match self.buckets.get_mut(&key) {
+            Some(bucket) => bucket,
+            None => {
+                let stored = self
+                    .tree
+                    .get(&key)
+                    .map_err(BackendError::new)?
+                    .and_then(|bytes| StoredBucket::from_bytes(&bytes));
+                let Some(stored) = stored.or(create.then(|| StoredBucket::full(count, now))) else {
+                    return Ok(None);
+                };
+                self.buckets.entry(key.clone()).or_insert(stored)
+            }
+        };
+        let result = update(&mut bucket, now);
+        self.tree
+            .insert(&key, &bucket.to_bytes()[..])
+            .map_err(BackendError::new)?;
+        Ok(Some(result))
+    }
+}
+
+#[async_trait::async_trait]
+impl<K> Backend<K> for SledBackend
+where
+    K: Hash + Sync,
+{
+    async fn acquire(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<Decision, BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(key, count, true, |bucket, now| {
+            bucket.decide(count, per, cost, now)
+        })?
+        .ok_or_else(|| BackendError::new("sled bucket disappeared"))
+    }
+
+    async fn refund(
+        &self,
+        key: &K,
+        count: usize,
+        per: u64,
+        cost: usize,
+    ) -> Result<(), BackendError> {
+        let key = self.bucket_key(key, count, per);
+        self.update(key, count, false, |bucket, now| {
+            bucket.refund(count, per, cost, now)
+        })
+        .map(drop)
+    }
+
+    async fn flush(&self) -> Result<(), BackendError> {
+        SledBackend::flush(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn buckets_survive_restarts() {
+        let path = std::env::temp_dir().join(format!("axum-limit-sled-{}", std::process::id()));
+        let open = || {
+            // The flusher thread of a dropped database may hold its lock for a moment.
+            let db = (0..100)
+                .find_map(|_| {
+                    ::sled::open(&path).ok().or_else(|| {
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        None
+                    })
+                })
+                .expect("Failed to open database");
+            let tree = db.open_tree("limits").expect("Failed to open tree");
+            SledBackend::new(tree, KeyHasher::new(1))
+        };
+        let acquire = |backend: SledBackend| async move {
+            let decision = backend.acquire(&"alice", 2, 60_000, 1).await;
+            decision.expect("Failed to acquire").allowed
+        };
+
+        let backend = open();
+        assert!(acquire(backend.clone()).await);
+        backend.flush().await.expect("Failed to flush");
+        drop(backend);
+
+        let backend = open();
+        assert!(acquire(backend.clone()).await);
+        assert!(!acquire(backend).await);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::map::Map;
+use crate::TokenBucket;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// The state of a single in-memory bucket, as taken by [`LimitState::snapshot`](crate::LimitState::snapshot) and
+/// put back by [`LimitState::restore`](crate::LimitState::restore).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BucketSnapshot<K> {
+    /// The key owning the bucket.
+    pub key: K,
+    /// The number of tokens the bucket holds when full.
+    pub capacity: usize,
+    /// The number of tokens the bucket is refilled with every period, below its capacity if unused tokens carry over.
+    pub rate: usize,
+    /// Whether the bucket is refilled continuously rather than once per period.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub smooth: bool,
+    /// The tokens borrowed from future refills, repaid before the bucket fills up again.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub debt: usize,
+    /// The most tokens the bucket may borrow from future refills.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_debt: usize,
+    /// The refill period of the bucket, in milliseconds.
+    pub per: u64,
+    /// The tokens left in the bucket.
+    pub tokens: usize,
+    /// The milliseconds elapsed since the bucket was last refilled.
+    pub elapsed: u64,
+}
+
+impl<K> BucketSnapshot<K> {
+    /// Takes a snapshot of `bucket` at the given point in time.
+    fn of(key: K, bucket: &TokenBucket, now: Instant) -> Self {
+        Self {
+            key,
+            capacity: bucket.capacity,
+            rate: bucket.rate,
+            smooth: bucket.smooth,
+            debt: bucket.debt,
+            max_debt: bucket.max_debt,
+            per: bucket.refill_duration.as_millis() as u64,
+            tokens: bucket.tokens,
+            elapsed: now
+                .saturating_duration_since(bucket.last_refill_time)
+                .as_millis() as u64,
+        }
+    }
+
+    /// Recreates the bucket at the given point in time. Buckets whose last refill cannot be represented, because it
+    /// predates the monotonic clock's origin, start a new period instead, and debts beyond their bound are capped.
+    pub(crate) fn into_bucket(self, now: Instant) -> (K, TokenBucket) {
+        let bucket = TokenBucket {
+            tokens: self.tokens,
+            capacity: self.capacity,
+            rate: self.rate,
+            smooth: self.smooth,
+            debt: self.debt.min(self.max_debt),
+            max_debt: self.max_debt,
+            last_refill_time: now
+                .checked_sub(Duration::from_millis(self.elapsed))
+                .unwrap_or(now),
+            refill_duration: Duration::from_millis(self.per),
+        };
+        (self.key, bucket)
+    }
+}
+
+/// Takes a snapshot of every bucket in `buckets` at the given point in time.
+pub(crate) fn snapshot<K>(buckets: &Map<K, TokenBucket>, now: Instant) -> Vec<BucketSnapshot<K>>
+where
+    K: Eq + Hash + Clone,
+{
+    buckets
+        .iter()
+        .map(|entry| BucketSnapshot::of(entry.key().clone(), entry.value(), now))
+        .collect()
+}
+
+/// A file holding a snapshot of a [`LimitState`](crate::LimitState)'s buckets as JSON, so long-running quotas
+/// survive process restarts.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+pub struct SnapshotFile {
+    path: std::path::PathBuf,
+    #[cfg(feature = "encryption")]
+    key: Option<crate::SnapshotKey>,
+}
+
+#[cfg(feature = "serde")]
+impl SnapshotFile {
+    /// Constructs a new `SnapshotFile` reading from and writing to `path`.
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            #[cfg(feature = "encryption")]
+            key: None,
+        }
+    }
+
+    /// Encrypts the file with `key`, so it does not leak the limited keys at rest.
+    #[cfg(feature = "encryption")]
+    pub fn encrypted(mut self, key: crate::SnapshotKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Loads the snapshot, or an empty one if the file does not exist yet.
+    pub fn load<K>(&self) -> std::io::Result<Vec<BucketSnapshot<K>>>
+    where
+        K: serde::de::DeserializeOwned,
+    {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => {
+                #[cfg(feature = "encryption")]
+                let bytes = match &self.key {
+                    Some(key) => key.open(&bytes)?,
+                    None => bytes,
+                };
+                Ok(serde_json::from_slice(&bytes)?)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the file's snapshot with `buckets`.
+    pub fn save<K>(&self, buckets: &[BucketSnapshot<K>]) -> std::io::Result<()>
+    where
+        K: serde::Serialize,
+    {
+        let bytes = serde_json::to_vec(buckets)?;
+        #[cfg(feature = "encryption")]
+        let bytes = match &self.key {
+            Some(key) => key.seal(&bytes)?,
+            None => bytes,
+        };
+        // Write to a temporary file first so a crash never leaves a truncated snapshot behind.
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "serde")]
+    use super::*;
+    use crate::LimitState;
+    use http::Method;
+
+    #[test]
+    fn restored_buckets_keep_their_tokens() {
+        let state = LimitState::<Method>::default();
+        assert!(state.check(Method::GET, 2, 60_000));
+        assert!(state.check(Method::POST, 2, 60_000));
+        assert!(state.check(Method::POST, 2, 60_000));
+
+        let restored = LimitState::<Method>::default();
+        restored.restore(state.snapshot());
+        assert!(restored.check(Method::GET, 2, 60_000));
+        assert!(!restored.check(Method::GET, 2, 60_000));
+        assert!(!restored.check(Method::POST, 2, 60_000));
+    }
+
+    #[test]
+    fn restored_buckets_keep_their_refills() {
+        let state = LimitState::<Method>::default().with_carry_over(2);
+        assert!(state.check(Method::GET, 3, 60_000));
+        let snapshot = state.snapshot();
+        assert_eq!((snapshot[0].capacity, snapshot[0].rate), (5, 3));
+
+        let restored = LimitState::<Method>::default();
+        restored.restore(snapshot);
+        let snapshot = restored.snapshot();
+        assert_eq!((snapshot[0].capacity, snapshot[0].rate), (5, 3));
+        assert!(!snapshot[0].smooth);
+    }
+
+    #[test]
+    fn restored_buckets_keep_their_debt() {
+        let state = LimitState::<Method>::default().with_debt(2);
+        for _ in 0..4 {
+            assert!(state.check(Method::GET, 3, 60_000));
+        }
+        let snapshot = state.snapshot();
+        assert_eq!((snapshot[0].debt, snapshot[0].max_debt), (1, 2));
+
+        let restored = LimitState::<Method>::default();
+        restored.restore(snapshot);
+        assert!(restored.check(Method::GET, 3, 60_000));
+        assert!(!restored.check(Method::GET, 3, 60_000));
+
+        // A snapshot claiming more debt than allowed is capped instead of overflowing the bound.
+        let mut snapshot = restored.snapshot();
+        snapshot[0].debt = 5;
+        let restored = LimitState::<Method>::default();
+        restored.restore(snapshot);
+        assert!(!restored.check(Method::GET, 3, 60_000));
+        assert_eq!(restored.snapshot()[0].debt, 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_files_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("axum-limit-snapshot-{}.json", std::process::id()));
+        let file = SnapshotFile::new(&path);
+        assert_eq!(file.load::<String>().expect("Failed to load"), Vec::new());
+
+        let buckets = vec![BucketSnapshot {
+            key: "alice".to_string(),
+            capacity: 10,
+            rate: 10,
+            smooth: false,
+            debt: 0,
+            max_debt: 0,
+            per: 86_400_000,
+            tokens: 3,
+            elapsed: 3_600_000,
+        }];
+        file.save(&buckets).expect("Failed to save");
+        assert_eq!(file.load::<String>().expect("Failed to load"), buckets);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{Decision, Key, LimitRejection, LimitState};
+use axum_core::extract::{FromRef, FromRequestParts};
+use http::request::Parts;
+use std::ops::{Deref, DerefMut};
+
+/// A rate limit in observe-only mode: requests are charged like with [`Limit`](crate::Limit), but never rejected.
+///
+/// Handlers can inspect whether the request would have been rejected, e.g. to evaluate a new policy on
+/// individual routes while others enforce theirs. Bans, fault injection and other hooks of the
+/// [`LimitState`] do not apply.
+#[derive(Debug, Clone)]
+pub struct SoftLimit<const COUNT: usize, const PER: u64, K>
+where
+    K: Key,
+{
+    /// The extracted key data.
+    pub extractor: K::Extractor,
+    /// The state of the key's bucket, or `None` if the backend failed to decide.
+    pub decision: Option<Decision>,
+}
+
+impl<const COUNT: usize, const PER: u64, K> SoftLimit<COUNT, PER, K>
+where
+    K: Key,
+{
+    /// Returns `true` if the request would have been rejected by the equivalent `Limit`.
+    pub fn exceeded(&self) -> bool {
+        self.decision.is_some_and(|d| !d.allowed)
+    }
+
+    /// Returns the number of requests still allowed within the current period, if known.
+    pub fn remaining(&self) -> Option<usize> {
+        self.decision.map(|d| d.remaining)
+    }
+
+    /// Consumes the limit and returns the inner extractor.
+    pub fn into_inner(self) -> K::Extractor {
+        self.extractor
+    }
+}
+
+impl<const COUNT: usize, const PER: u64, K> Deref for SoftLimit<COUNT, PER, K>
+where
+    K: Key,
+{
+    type Target = K::Extractor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.extractor
+    }
+}
+
+impl<const COUNT: usize, const PER: u64, K> DerefMut for SoftLimit<COUNT, PER, K>
+where
+    K: Key,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.extractor
+    }
+}
+
+#[async_trait::async_trait]
+impl<const C: usize, const P: u64, K, S> FromRequestParts<S> for SoftLimit<C, P, K>
+where
+    LimitState<K>: FromRef<S>,
+    S: Send + Sync,
+    K: Key,
+    K::Extractor: FromRequestParts<S> + Send,
+{
+    type Rejection = LimitRejection<<<K as Key>::Extractor as FromRequestParts<S>>::Rejection>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let extractor = match K::Extractor::from_request_parts(parts, state).await {
+            Ok(ke) => ke,
+            Err(rejection) => return Err(LimitRejection::KeyExtractionFailure(rejection)),
+        };
+
+        let limit_state: LimitState<K> = FromRef::from_ref(state);
+        let key = K::from_extractor(&extractor);
+        let decision = limit_state.acquire(&key, C, P, 1).await.unwrap_or(None);
+        Ok(Self {
+            extractor,
+            decision,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{Method, StatusCode};
+
+    #[tokio::test]
+    async fn reports_without_rejecting() {
+        async fn handler(limit: SoftLimit<1, 60_000, Method>) -> String {
+            format!("{} {:?}", limit.exceeded(), limit.remaining())
+        }
+
+        let my_app = Router::new()
+            .route("/soft", get(handler))
+            .with_state(LimitState::default());
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/soft").await.text(), "false Some(0)");
+        let response = server.get("/soft").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "true Some(0)");
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{Key, LimitState};
+use axum_core::extract::FromRef;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// A router state holding one [`LimitState`] per key type, so routes can use limits with any keys without
+/// a hand-written state implementing `FromRef` for each of them.
+///
+/// States that were not inserted explicitly are created with default settings when first used.
+/// Clones share the same states.
+#[derive(Clone, Default)]
+pub struct LimitStates {
+    states: Arc<RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>,
+}
+
+impl LimitStates {
+    /// Constructs a new, empty `LimitStates`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts the state used for limits with keys of type `K`, replacing any previous one.
+    pub fn insert<K>(&self, state: LimitState<K>)
+    where
+        K: Key + 'static,
+    {
+        self.states
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(TypeId::of::<K>(), Box::new(state));
+    }
+
+    /// Returns the state used for limits with keys of type `K`.
+    pub fn get<K>(&self) -> LimitState<K>
+    where
+        K: Key + 'static,
+    {
+        let found = self
+            .states
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&TypeId::of::<K>())
+            .and_then(|state| state.downcast_ref::<LimitState<K>>())
+            .cloned();
+        if let Some(state) = found {
+            return state;
+        }
+
+        self.states
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(TypeId::of::<K>())
+            .or_insert_with(|| Box::new(LimitState::<K>::default()))
+            .downcast_ref::<LimitState<K>>()
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl<K> FromRef<LimitStates> for LimitState<K>
+where
+    K: Key + 'static,
+{
+    fn from_ref(states: &LimitStates) -> Self {
+        states.get()
+    }
+}
+
+/// A builder for routers whose limits get their states wired up automatically.
+///
+/// Routes are added with [`LimitStates`] as their state, so `.with_state` never needs to be placed correctly for
+/// each key type: states are attached by [`into_router`](Self::into_router), and created on first use unless
+/// configured with [`limit_state`](Self::limit_state).
+///
+/// ```
+/// use axum::routing::get;
+/// use axum_limit::{Limit, LimitState, LimitedRouter};
+/// use http::{Method, Uri};
+///
+/// async fn by_uri(_: Limit<10, 1000, Uri>) {}
+/// async fn by_method(_: Limit<100, 1000, Method>) {}
+///
+/// let _app: axum::Router = LimitedRouter::new()
+///     .limit_state(LimitState::<Uri>::default().with_grace(5))
+///     .route("/uri", get(by_uri))
+///     .route("/method", get(by_method))
+///     .into_router();
+/// ```
+#[cfg(feature = "axum")]
+#[derive(Default)]
+pub struct LimitedRouter {
+    router: axum::Router<LimitStates>,
+    states: LimitStates,
+}
+
+#[cfg(feature = "axum")]
+impl LimitedRouter {
+    /// Constructs a new `LimitedRouter` without any routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures the state used for limits with keys of type `K`.
+    pub fn limit_state<K>(self, state: LimitState<K>) -> Self
+    where
+        K: Key + 'static,
+    {
+        self.states.insert(state);
+        self
+    }
+
+    /// Adds a route, like [`Router::route`](axum::Router::route).
+    pub fn route(
+        mut self,
+        path: &str,
+        method_router: axum::routing::MethodRouter<LimitStates>,
+    ) -> Self {
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    /// Merges the routes of another router that uses [`LimitStates`] as its state.
+    pub fn merge(mut self, other: axum::Router<LimitStates>) -> Self {
+        self.router = self.router.merge(other);
+        self
+    }
+
+    /// Attaches the states and returns the finished router.
+    pub fn into_router<S>(self) -> axum::Router<S> {
+        self.router.with_state(self.states)
+    }
+}
+
+#[cfg(all(test, feature = "axum"))]
+mod tests {
+    use super::*;
+    use crate::Limit;
+    use axum::routing::get;
+    use axum_test::TestServer;
+    use http::{Method, StatusCode, Uri};
+
+    #[tokio::test]
+    async fn wires_states_by_key_type() {
+        async fn by_uri(_: Limit<1, 60_000, Uri>) {}
+        async fn by_method(_: Limit<2, 60_000, Method>) {}
+
+        let methods = LimitState::<Method>::default();
+        let my_app = LimitedRouter::new()
+            .limit_state(methods.clone())
+            .route("/uri", get(by_uri))
+            .route("/method", get(by_method))
+            .into_router::<()>();
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        assert_eq!(server.get("/uri").await.status_code(), StatusCode::OK);
+        assert_eq!(
+            server.get("/uri").await.status_code(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(server.get("/method").await.status_code(), StatusCode::OK);
+
+        // The configured state is the one in use.
+        assert!(methods.check(Method::GET, 2, 60_000));
+        assert!(!methods.check(Method::GET, 2, 60_000));
+    }
+}
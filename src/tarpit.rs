@@ -0,0 +1,113 @@
+use crate::map::Map;
+use crate::Decision;
+use std::hash::Hash;
+use std::time::Duration;
+
+/// Progressive response delays, slowing down keys as they approach and exceed their limit.
+///
+/// When installed on a [`LimitState`](crate::LimitState), requests are delayed once a key has used up the configured
+/// fraction of its quota, growing linearly up to the base delay at the limit. Requests beyond the limit are not
+/// rejected; instead, their delay doubles with every consecutive excess request, up to the maximum delay. Against
+/// naive scrapers and credential stuffers this is usually more effective than a `429 Too Many Requests` response,
+/// which tells them exactly when to back off.
+pub struct Tarpit<K> {
+    threshold: f64,
+    base: Duration,
+    max: Duration,
+    strikes: Map<K, u32>,
+}
+
+impl<K> Tarpit<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Constructs a new `Tarpit` delaying requests by up to `base` at the limit,
+    /// and by up to `max` beyond it. Delays start once half of the quota has been used.
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            threshold: 0.5,
+            base,
+            max: max.max(base),
+            strikes: Map::new(),
+        }
+    }
+
+    /// Sets the fraction of the quota, between `0.0` and `1.0`, a key may use before its requests are delayed.
+    pub fn start_at(mut self, fraction: f64) -> Self {
+        self.threshold = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns how long to delay a request of `key` that received `decision`.
+    pub(crate) fn delay(&self, key: &K, decision: &Decision) -> Duration {
+        if decision.allowed {
+            self.strikes.remove(key);
+            let used = 1.0 - decision.remaining as f64 / decision.limit.max(1) as f64;
+            if used < self.threshold || self.threshold >= 1.0 {
+                return Duration::ZERO;
+            }
+            return self
+                .base
+                .mul_f64((used - self.threshold) / (1.0 - self.threshold));
+        }
+
+        let mut strikes = self.strikes.entry(key.clone()).or_insert(0);
+        *strikes = strikes.saturating_add(1);
+        let factor = 2u32.checked_pow(*strikes).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(allowed: bool, remaining: usize) -> Decision {
+        Decision {
+            allowed,
+            limit: 4,
+            remaining,
+            reset: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn delays_grow_with_usage() {
+        let tarpit = Tarpit::new(Duration::from_secs(1), Duration::from_secs(5));
+
+        assert_eq!(tarpit.delay(&"a", &decision(true, 3)), Duration::ZERO);
+        assert_eq!(
+            tarpit.delay(&"a", &decision(true, 1)),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            tarpit.delay(&"a", &decision(true, 0)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            tarpit.delay(&"a", &decision(false, 0)),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            tarpit.delay(&"a", &decision(false, 0)),
+            Duration::from_secs(4)
+        );
+        assert_eq!(
+            tarpit.delay(&"a", &decision(false, 0)),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            tarpit.delay(&"b", &decision(false, 0)),
+            Duration::from_secs(2)
+        );
+
+        assert_eq!(
+            tarpit.delay(&"a", &decision(true, 1)),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            tarpit.delay(&"a", &decision(false, 0)),
+            Duration::from_secs(2)
+        );
+    }
+}
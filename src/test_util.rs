@@ -0,0 +1,101 @@
+//! Utilities for asserting configured limits in tests.
+//!
+//! ```
+//! use axum::{routing::get, Router};
+//! use axum_limit::test_util::MockClock;
+//! use axum_limit::{assert_limited_after, Limit, LimitState};
+//! use axum_test::TestServer;
+//! use http::Uri;
+//! use std::time::Duration;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! async fn handler(_: Limit<1, 60_000, Uri>) {}
+//!
+//! let clock = MockClock::new();
+//! let app = Router::new()
+//!     .route("/", get(handler))
+//!     .with_state(LimitState::<Uri>::default().with_clock(clock.clone()));
+//! let server = TestServer::new(app).unwrap();
+//!
+//! assert_limited_after!(server, "/", 1);
+//!
+//! // No need to wait a minute for the bucket to refill.
+//! clock.advance(Duration::from_secs(60));
+//! assert_limited_after!(server, "/", 1);
+//! # }
+//! ```
+
+use crate::Clock;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[doc(hidden)]
+pub use http::StatusCode;
+
+/// A manually advanced [`Clock`], for testing limits without waiting for buckets to refill.
+/// Clones share the same time, so a clone can be handed to a [`LimitState`](crate::LimitState)
+/// while the test keeps another to fast-forward it.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    offset: Arc<Mutex<Duration>>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    /// Constructs a new `MockClock` starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            offset: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.offset.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.offset.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Asserts that `route` accepts exactly `n` GET requests before rejecting the next one with
+/// `429 Too Many Requests`.
+///
+/// Works with any test server whose `get(route)` returns a future resolving to a response with a
+/// `status_code()` method, such as [axum-test](https://docs.rs/axum-test)'s `TestServer`.
+#[macro_export]
+macro_rules! assert_limited_after {
+    ($server:expr, $route:expr, $n:expr) => {{
+        let route = $route;
+        for i in 0..$n {
+            let status = $server.get(route).await.status_code();
+            assert!(
+                status.is_success(),
+                "request {} of {} to {} was not successful: {}",
+                i + 1,
+                $n,
+                route,
+                status
+            );
+        }
+        let status = $server.get(route).await.status_code();
+        assert_eq!(
+            status,
+            $crate::test_util::StatusCode::TOO_MANY_REQUESTS,
+            "request {} to {} was not rate limited",
+            $n + 1,
+            route
+        );
+    }};
+}
@@ -0,0 +1,62 @@
+use crate::algorithm::unix_millis;
+use crate::CronSchedule;
+use std::time::Instant;
+
+/// Limits varying by time of day or day of week, e.g. stricter limits during the business-hours peak.
+///
+/// When installed on a [`LimitState`](crate::LimitState), every limit is scaled by the fraction of the first period
+/// matching the current minute, in the syntax of a [`CronSchedule`], and enforced at its full count outside of them.
+/// Fractions above `1.0` raise limits, e.g. off-peak at night. Scaled limits allow at least one request unless their
+/// count is zero.
+#[derive(Debug, Clone, Default)]
+pub struct Timetable {
+    periods: Vec<(CronSchedule, f64)>,
+}
+
+impl Timetable {
+    /// Constructs a new `Timetable` enforcing every limit at its full count at all times.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scales limits by `fraction` during the minutes matching `schedule`, e.g. `"* 9-16 * * MON-FRI"` for business
+    /// hours. Periods added earlier take precedence.
+    pub fn during(mut self, schedule: CronSchedule, fraction: f64) -> Self {
+        self.periods.push((schedule, fraction.max(0.0)));
+        self
+    }
+
+    /// Returns the effective limit of `count` requests at `now`.
+    pub(crate) fn limit(&self, count: usize, now: Instant) -> usize {
+        let millis = unix_millis(now);
+        match self
+            .periods
+            .iter()
+            .find(|(schedule, _)| schedule.matches_millis(millis))
+        {
+            Some((_, fraction)) => ((count as f64 * fraction) as usize).max(count.min(1)),
+            None => count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varies_limits_by_time() {
+        let now = Instant::now();
+        let every_minute: CronSchedule = "* * * * *".parse().expect("valid expression");
+        let never: CronSchedule = "0 0 30 2 *".parse().expect("valid expression");
+
+        let timetable = Timetable::new()
+            .during(never, 0.1)
+            .during(every_minute.clone(), 0.5)
+            .during(every_minute, 2.0);
+        assert_eq!(timetable.limit(10, now), 5);
+        assert_eq!(timetable.limit(1, now), 1);
+        assert_eq!(timetable.limit(0, now), 0);
+        assert_eq!(Timetable::new().limit(10, now), 10);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{BoxFuture, Decision, Key, LimitState};
+use axum_core::body::Body;
+use axum_core::extract::FromRequestParts;
+use axum_core::response::{IntoResponse, Response};
+use bytes::Bytes;
+use http::Request;
+use http_body::{Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::error::Error;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tower_layer::Layer;
+use tower_service::Service;
+
+pub(crate) type BoxError = Box<dyn Error + Send + Sync>;
+pub(crate) type Charge = Arc<dyn Fn(usize) -> BoxFuture<Option<Decision>> + Send + Sync>;
+
+/// Returns a function charging tokens for `key` against a limit of `count` tokens per `per` milliseconds.
+pub(crate) fn charger<K>(state: LimitState<K>, key: K, count: usize, per: u64) -> Charge
+where
+    K: Key + 'static,
+{
+    Arc::new(move |cost| {
+        let state = state.clone();
+        let key = key.clone();
+        // The failure policy only applies when requests are admitted; later charges let a failing backend pass.
+        Box::pin(async move { state.charge(&key, count, per, cost).await.unwrap_or(None) })
+    })
+}
+
+/// The error a [`ChargedBody`] fails with once its key runs out of tokens mid-upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadLimitExceeded;
+
+impl Display for UploadLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Upload rate limit exceeded.")
+    }
+}
+
+impl Error for UploadLimitExceeded {}
+
+/// A layer that charges tokens for every chunk of a request body as it is received,
+/// so a single long-lived upload cannot monopolize a key's budget.
+///
+/// The key is extracted from the request parts like for [`Limit`](crate::Limit), so its extractor must not depend
+/// on the router state. Each data chunk costs one token, or one token per started `bytes_per_token` bytes if
+/// configured. Once the key runs out of tokens, reading the body fails with [`UploadLimitExceeded`], and whatever the
+/// handler responds is replaced by the `429 Too Many Requests` rejection of the key.
+pub struct UploadLimitLayer<K>
+where
+    K: Key,
+{
+    state: LimitState<K>,
+    count: usize,
+    per: u64,
+    bytes_per_token: Option<usize>,
+}
+
+impl<K> Clone for UploadLimitLayer<K>
+where
+    K: Key,
+{
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            count: self.count,
+            per: self.per,
+            bytes_per_token: self.bytes_per_token,
+        }
+    }
+}
+
+impl<K> UploadLimitLayer<K>
+where
+    K: Key,
+{
+    /// Constructs a new `UploadLimitLayer` charging body chunks against a limit of `count` tokens
+    /// per `per` milliseconds in `state`.
+    pub fn new(state: LimitState<K>, count: usize, per: u64) -> Self {
+        Self {
+            state,
+            count,
+            per,
+            bytes_per_token: None,
+        }
+    }
+
+    /// Charges one token per started `bytes` bytes of each chunk instead of one token per chunk.
+    pub fn bytes_per_token(mut self, bytes: usize) -> Self {
+        self.bytes_per_token = Some(bytes.max(1));
+        self
+    }
+}
+
+impl<S, K> Layer<S> for UploadLimitLayer<K>
+where
+    K: Key,
+{
+    type Service = UploadLimit<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UploadLimit {
+            inner,
+            config: self.clone(),
+            _key: PhantomData,
+        }
+    }
+}
+
+/// Middleware that charges tokens for request body chunks. See [`UploadLimitLayer`].
+pub struct UploadLimit<S, K>
+where
+    K: Key,
+{
+    inner: S,
+    config: UploadLimitLayer<K>,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<S, K> Clone for UploadLimit<S, K>
+where
+    S: Clone,
+    K: Key,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<S, K> Service<Request<Body>> for UploadLimit<S, K>
+where
+    S: Service<Request<Body>> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send,
+    K: Key + 'static,
+    K::Extractor: FromRequestParts<()> + Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Take the service that was driven to readiness, leaving a fresh clone behind.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let key_extractor = match K::Extractor::from_request_parts(&mut parts, &()).await {
+                Ok(ke) => ke,
+                Err(rejection) => return Ok(rejection.into_response()),
+            };
+            let key = K::from_extractor(&key_extractor);
+            let format = config.state.format(&parts);
+
+            let charge = charger(config.state.clone(), key.clone(), config.count, config.per);
+            let exceeded = Arc::new(OnceLock::new());
+            let body = ChargedBody::new(body, charge, config.bytes_per_token, exceeded.clone());
+
+            let response = inner
+                .call(Request::from_parts(parts, Body::new(body)))
+                .await?;
+            match exceeded.get() {
+                Some(&retry_after) => Ok(config
+                    .state
+                    .exhausted(&key, config.count, config.per, retry_after, format)
+                    .into_response()),
+                None => Ok(response.into_response()),
+            }
+        })
+    }
+}
+
+pin_project! {
+    /// A request body charging tokens for every data chunk. See [`UploadLimitLayer`].
+    pub struct ChargedBody<B> {
+        #[pin]
+        inner: B,
+        charge: Charge,
+        bytes_per_token: Option<usize>,
+        pending: Option<(Frame<Bytes>, BoxFuture<Option<Decision>>)>,
+        exceeded: Arc<OnceLock<Duration>>,
+    }
+}
+
+impl<B> ChargedBody<B> {
+    /// Constructs a new `ChargedBody`, storing the time until the key's tokens are replenished in `exceeded` once
+    /// they run out.
+    fn new(
+        inner: B,
+        charge: Charge,
+        bytes_per_token: Option<usize>,
+        exceeded: Arc<OnceLock<Duration>>,
+    ) -> Self {
+        Self {
+            inner,
+            charge,
+            bytes_per_token,
+            pending: None,
+            exceeded,
+        }
+    }
+}
+
+impl<B> http_body::Body for ChargedBody<B>
+where
+    B: http_body::Body<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            // A chunk is only handed on once its tokens have been charged.
+            if let Some((frame, mut charge)) = this.pending.take() {
+                let Poll::Ready(decision) = charge.as_mut().poll(cx) else {
+                    *this.pending = Some((frame, charge));
+                    return Poll::Pending;
+                };
+                return match decision {
+                    Some(decision) if !decision.allowed => {
+                        let _ = this.exceeded.set(decision.reset);
+                        Poll::Ready(Some(Err(Box::new(UploadLimitExceeded))))
+                    }
+                    _ => Poll::Ready(Some(Ok(frame))),
+                };
+            }
+
+            let frame = match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            };
+            let cost = match (frame.data_ref(), *this.bytes_per_token) {
+                (None, _) => 0,
+                (Some(data), Some(bytes)) => data.len().div_ceil(bytes),
+                (Some(_), None) => 1,
+            };
+            if cost == 0 {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+            *this.pending = Some((frame, (this.charge)(cost)));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use axum::Router;
+    use axum_test::TestServer;
+    use http::{StatusCode, Uri};
+
+    #[tokio::test]
+    async fn charges_uploaded_bytes() {
+        async fn handler(body: String) -> String {
+            body
+        }
+
+        let my_app = Router::new().route("/upload", post(handler)).layer(
+            UploadLimitLayer::new(LimitState::<Uri>::default(), 5, 60_000).bytes_per_token(10),
+        );
+
+        let server = TestServer::new(my_app).expect("Failed to create test server");
+
+        let response = server.post("/upload").text("a".repeat(30)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server
+            .post("/upload")
+            .text("a".repeat(30))
+            .expect_failure()
+            .await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = server.post("/upload").text("a".repeat(20)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+}
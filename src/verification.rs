@@ -0,0 +1,176 @@
+//! Property-based checks of the limiting algorithms against reference models.
+//!
+//! Enabled with the `model-check` feature: `cargo test --features model-check`.
+
+use crate::algorithm::{RequestLog, VirtualSchedule, WindowCounter};
+use crate::TokenBucket;
+use proptest::prelude::*;
+use std::time::{Duration, Instant};
+
+/// A limit of `count` requests per `per` milliseconds, and a sequence of request arrival gaps in milliseconds.
+fn scenario() -> impl Strategy<Value = (usize, u64, Vec<u64>)> {
+    (1usize..20, 1u64..5_000).prop_flat_map(|(count, per)| {
+        (
+            Just(count),
+            Just(per),
+            prop::collection::vec(0..per * 2, 1..200),
+        )
+    })
+}
+
+proptest! {
+    /// Windows are aligned to the creation of the bucket; none of them may admit more than `count` requests.
+    #[test]
+    fn token_bucket_never_admits_more_than_count_per_window((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(count, per, start);
+        let mut elapsed = 0;
+        let mut window = 0;
+        let mut admitted = 0;
+        for gap in gaps {
+            elapsed += gap;
+            if elapsed / per != window {
+                window = elapsed / per;
+                admitted = 0;
+            }
+            if bucket.try_acquire_at(start + Duration::from_millis(elapsed)) {
+                admitted += 1;
+            }
+            prop_assert!(admitted <= count, "window {window} admitted {admitted} > {count}");
+        }
+    }
+
+    /// Letting time pass without acquiring never removes tokens, and never yields more than `count`.
+    #[test]
+    fn token_bucket_refill_is_monotonic((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(count, per, start);
+        let mut elapsed = 0;
+        for gap in gaps {
+            let before = bucket.tokens;
+            elapsed += gap;
+            bucket.refill(start + Duration::from_millis(elapsed));
+            prop_assert!(bucket.tokens >= before);
+            prop_assert!(bucket.tokens <= count);
+            bucket.try_acquire_at(start + Duration::from_millis(elapsed));
+        }
+    }
+
+    /// Whatever happened before, a full idle period restores the full capacity of the bucket.
+    #[test]
+    fn token_bucket_loses_no_tokens((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(count, per, start);
+        let mut elapsed = 0;
+        for gap in gaps {
+            elapsed += gap;
+            bucket.try_acquire_at(start + Duration::from_millis(elapsed));
+        }
+
+        let idle = start + Duration::from_millis(elapsed + per);
+        for _ in 0..count {
+            prop_assert!(bucket.try_acquire_at(idle));
+        }
+        prop_assert!(!bucket.try_acquire_at(idle));
+    }
+
+    /// Counts are never estimated below the requests of the current window, so windows aligned to the creation of
+    /// the counter never admit more than `count` requests either.
+    #[test]
+    fn sliding_window_never_admits_more_than_count_per_window((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut counter = WindowCounter::new(per, start);
+        let mut elapsed = 0;
+        let mut window = 0;
+        let mut admitted = 0;
+        for gap in gaps {
+            elapsed += gap;
+            if elapsed / per != window {
+                window = elapsed / per;
+                admitted = 0;
+            }
+            if counter.decide_at(count, 1, start + Duration::from_millis(elapsed)).allowed {
+                admitted += 1;
+            }
+            prop_assert!(admitted <= count, "window {window} admitted {admitted} > {count}");
+        }
+    }
+
+    /// Whatever happened before, two idle periods restore the full limit of the counter.
+    #[test]
+    fn sliding_window_loses_no_capacity((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut counter = WindowCounter::new(per, start);
+        let mut elapsed = 0;
+        for gap in gaps {
+            elapsed += gap;
+            counter.decide_at(count, 1, start + Duration::from_millis(elapsed));
+        }
+
+        let idle = start + Duration::from_millis(elapsed + per * 2);
+        for _ in 0..count {
+            prop_assert!(counter.decide_at(count, 1, idle).allowed);
+        }
+        prop_assert!(!counter.decide_at(count, 1, idle).allowed);
+    }
+
+    /// The log admits a request exactly when fewer than `count` requests were admitted within the preceding `per`
+    /// milliseconds, whichever window is looked at.
+    #[test]
+    fn sliding_log_matches_reference_model((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut log = RequestLog::new(per);
+        let mut admitted: Vec<u64> = Vec::new();
+        let mut elapsed = 0;
+        for gap in gaps {
+            elapsed += gap;
+            let recent = admitted.iter().filter(|time| elapsed - **time < per).count();
+            let allowed = log.decide_at(count, 1, start + Duration::from_millis(elapsed)).allowed;
+            prop_assert_eq!(allowed, recent < count, "at {} with {} recent requests", elapsed, recent);
+            if allowed {
+                admitted.push(elapsed);
+            }
+        }
+    }
+
+    /// Between any two admitted requests, no more requests are admitted than the burst plus the sustained rate
+    /// allows for the time between them.
+    #[test]
+    fn gcra_never_exceeds_burst_and_rate((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut schedule = VirtualSchedule::new(per, start);
+        let interval = u128::from(per) * 1_000_000 / count as u128;
+        let mut admitted: Vec<u128> = Vec::new();
+        let mut elapsed = 0;
+        for gap in gaps {
+            elapsed += gap;
+            if schedule.decide_at(count, 1, start + Duration::from_millis(elapsed)).allowed {
+                admitted.push(u128::from(elapsed) * 1_000_000);
+            }
+        }
+        for (i, first) in admitted.iter().enumerate() {
+            for (j, last) in admitted.iter().enumerate().skip(i) {
+                let allowed = count as u128 + (last - first) / interval.max(1);
+                prop_assert!((j - i + 1) as u128 <= allowed, "{} requests within {}ns", j - i + 1, last - first);
+            }
+        }
+    }
+
+    /// Whatever happened before, a full idle period restores the full burst.
+    #[test]
+    fn gcra_restores_burst((count, per, gaps) in scenario()) {
+        let start = Instant::now();
+        let mut schedule = VirtualSchedule::new(per, start);
+        let mut elapsed = 0;
+        for gap in gaps {
+            elapsed += gap;
+            schedule.decide_at(count, 1, start + Duration::from_millis(elapsed));
+        }
+
+        let idle = start + Duration::from_millis(elapsed + per);
+        for _ in 0..count {
+            prop_assert!(schedule.decide_at(count, 1, idle).allowed);
+        }
+        prop_assert!(!schedule.decide_at(count, 1, idle).allowed);
+    }
+}